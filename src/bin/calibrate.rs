@@ -17,7 +17,24 @@
 //! ```
 //!
 //! ## Format CSV
-//! `ts_ms,gx_lsb,gy_lsb,gz_lsb,ax_lsb,ay_lsb,az_lsb,baro_alt_cm,baro_press_pa,baro_temp_mc,mag_x,mag_y,mag_z`
+//! `ts_ms,gx_lsb,gy_lsb,gz_lsb,ax_lsb,ay_lsb,az_lsb,baro_alt_cm,baro_press_pa,baro_temp_mc,mag_x,mag_y,mag_z,ekf_roll_mrad,ekf_pitch_mrad,ekf_yaw_mrad`
+//!
+//! Les colonnes `ekf_*` sont la sortie de `AttitudeEkf` alimenté par chaque
+//! échantillon IMU (Allan des résidus EKF vs bruit gyro brut).
+//!
+//! Les 1000 premiers échantillons donnent le plancher de bruit par axe
+//! (lignes `# NOISE`, comparées aux specs ICM-42688-P).
+//!
+//! Une Allan deviation gyro est aussi calculée en ligne (tau = 1…128
+//! échantillons, puis 0.5 s…~1050 s en clusters non chevauchants) : lignes
+//! `# ADEV` toutes les 60 s, puis `Q_QUAT` / `Q_GBIAS` prêts à copier en fin
+//! de session — utile sans MATLAB.
+//!
+//! En fin de session, un bloc `mod calibration { ... }` (biais gyro/accel,
+//! `R_ACCEL_NORMAL`, `Q_QUAT`, `Q_GBIAS`) est envoyé : le coller dans
+//! `src/calibration.rs`, inclus par `main.rs` : biais accel soustrait en vol,
+//! biais gyro en secours si la carte bouge pendant la calibration au
+//! démarrage, bruits appliqués à l'EKF.
 //!
 //! ## Script MATLAB (copier-coller)
 //!
@@ -25,7 +42,8 @@
 //! %% 1. Charger
 //! T = readtable('calib_data.csv', 'CommentStyle', '#');
 //! T.Properties.VariableNames = {'ts_ms','gx','gy','gz','ax','ay','az',...
-//!     'baro_alt_cm','baro_press_pa','baro_temp_mc','mag_x','mag_y','mag_z'};
+//!     'baro_alt_cm','baro_press_pa','baro_temp_mc','mag_x','mag_y','mag_z',...
+//!     'ekf_roll_mrad','ekf_pitch_mrad','ekf_yaw_mrad'};
 //! Fs_imu = 500; Fs_baro = 20;
 //!
 //! %% 2. Conversion
@@ -58,9 +76,10 @@
 //! [av_g,tau_g] = allanvar(gz,'octave',Fs_imu);
 //! adev_g = sqrt(av_g);
 //! ARW = adev_g(1)/sqrt(Fs_imu);
-//! [bi,~] = min(adev_g);
+//! [bi,k] = min(adev_g);
+//! Tc = max(tau_g(k), 60);   % temps de corrélation du biais (Gauss-Markov)
 //! fprintf('const Q_QUAT:  f32 = %.2e;\n', ARW^2/Fs_imu);
-//! fprintf('const Q_GBIAS: f32 = %.2e;\n', bi^2/Fs_imu);
+//! fprintf('const Q_GBIAS: f32 = %.2e;\n', 2*bi^2/Tc);
 //!
 //! %% 7. Baro variance
 //! baro_m = double(T.baro_alt_cm(1:Fs_imu/Fs_baro:end))/100;
@@ -77,6 +96,7 @@
 #[path = "../usb.rs"]     mod usb;
 #[path = "../drivers/mod.rs"]
 mod drivers {
+    #[path = "ahrs.rs"]     pub mod ahrs;
     #[path = "icm42688.rs"] pub mod icm42688;
     #[path = "spl06.rs"]    pub mod spl06;
     #[path = "hmc5883.rs"]  pub mod hmc5883;
@@ -85,27 +105,35 @@ mod drivers {
     #[path = "ekf.rs"]      pub mod ekf;
     #[path = "filter.rs"]   pub mod filter;
     #[path = "flash.rs"]    pub mod flash;
-    #[path = "gps.rs"]      pub mod gps;
     #[path = "kalman.rs"]   pub mod kalman;
     #[path = "roll.rs"]     pub mod roll;
+
+    pub use ahrs::Mahony;
 }
 
 use core::fmt::Write;
 use core::sync::atomic::{AtomicI32, AtomicU32, Ordering};
 use embassy_executor::Spawner;
+use embassy_futures::select::{select, Either};
 use embassy_stm32::dma::NoDma;
 use embassy_stm32::gpio::{Level, Output, Pin, Speed};
 use embassy_stm32::i2c::I2c;
 use embassy_stm32::spi::{Config as SpiConfig, Spi};
 use embassy_stm32::time::Hertz as TimeHertz;
+use embassy_stm32::usart::{Config as UsartConfig, Uart};
 use embassy_stm32::{bind_interrupts, peripherals};
 use embassy_time::{Duration, Instant, Ticker, Timer};
+use micromath::F32Ext;
 use {defmt_rtt as _, panic_probe as _};
 
 use crate::board::Board;
+use crate::drivers::crsf::{build_ping_packet, CrsfParser, CRSF_ADDRESS_FLIGHT_CONTROLLER};
+use crate::drivers::ekf::AttitudeEkf;
+use crate::drivers::filter::NoiseEstimator;
+use crate::drivers::flash::{W25qxx, FS_RESERVED_TAIL};
 use crate::drivers::hmc5883::Hmc5883;
 use crate::drivers::icm42688::Icm42688;
-use crate::drivers::spl06::Spl06;
+use crate::drivers::spl06::{Spl06, SPL06_ADDR_SDO_LOW};
 
 // ── Paramètres ────────────────────────────────────────────────────────────────
 
@@ -121,6 +149,21 @@ const BARO_RATE_HZ: u64 = 20;
 /// Fréquence magnétomètre (Hz)
 const MAG_RATE_HZ: u64 = 10;
 
+/// Attente max d'une réponse DEVICE_INFO au ping CRSF
+const CRSF_PING_TIMEOUT_MS: u64 = 1000;
+
+// ── Plancher de bruit (début de session) ──────────────────────────────────────
+
+/// Échantillons pour l'estimation du bruit au repos (2 s à 500 Hz)
+const NOISE_FLOOR_SAMPLES: u32 = 1000;
+
+/// Bruit gyro ICM-42688-P (datasheet, °/s RMS). À 16.4 LSB/dps la quantification
+/// seule donne déjà ~0.018 °/s (1 LSB / √12) : on compare avec une marge x2.
+const GYRO_NOISE_SPEC_DPS: f32 = 0.015;
+
+/// Densité de bruit accel ICM-42688-P (µg/√Hz), bande ≈ IMU_RATE_HZ / 2
+const ACCEL_NOISE_DENSITY_UG: f32 = 70.0;
+
 // ── Données partagées baro/mag (atomes, mis à jour par baro_task) ─────────────
 static BARO_ALT_CM:    AtomicI32 = AtomicI32::new(0);
 static BARO_PRESS_PA:  AtomicU32 = AtomicU32::new(0);
@@ -129,10 +172,209 @@ static MAG_X:          AtomicI32 = AtomicI32::new(0);
 static MAG_Y:          AtomicI32 = AtomicI32::new(0);
 static MAG_Z:          AtomicI32 = AtomicI32::new(0);
 
+// ── Allan Variance en ligne (gyro) ────────────────────────────────────────────
+
+/// Tailles de cluster m (en échantillons IMU) — tau = m / IMU_RATE_HZ
+const ADEV_TAUS: [usize; 8] = [1, 2, 4, 8, 16, 32, 64, 128];
+
+/// Historique circulaire : 2 × m max
+const ADEV_HIST: usize = 256;
+
+/// Conversion gyro LSB → rad/s (16.4 LSB/dps)
+const GYRO_LSB_TO_RAD_S: f32 = core::f32::consts::PI / 180.0 / 16.4;
+
+/// Taus longs (clusters non chevauchants) pour la bias instability :
+/// ADEV_LONG_BASE échantillons (0.512 s) puis octaves jusqu'à ~1050 s
+const ADEV_LONG_BASE: usize = 256;
+const ADEV_LONG_LEVELS: usize = 12;
+/// Clusters minimum avant d'exploiter un tau long (sinon variance trop bruitée)
+const ADEV_LONG_MIN_CLUSTERS: u32 = 8;
+
+/// Temps de corrélation minimal du biais gyro (Gauss-Markov) pour Q_GBIAS :
+/// le biais d'un MEMS dérive sur des minutes, pas sur des fractions de seconde
+const GBIAS_TAU_MIN_S: f32 = 60.0;
+
+/// Allan Variance à clusters NON chevauchants pour les taus longs : une
+/// somme en cours et la moyenne du cluster précédent par octave, pas d'historique.
+struct AllanLong {
+    sum: [[i64; 3]; ADEV_LONG_LEVELS],
+    len: [usize; ADEV_LONG_LEVELS],
+    prev: [[f64; 3]; ADEV_LONG_LEVELS],
+    has_prev: [bool; ADEV_LONG_LEVELS],
+    acc: [[f64; 3]; ADEV_LONG_LEVELS],
+    cnt: [u32; ADEV_LONG_LEVELS],
+}
+
+impl AllanLong {
+    const fn new() -> Self {
+        Self {
+            sum: [[0; 3]; ADEV_LONG_LEVELS],
+            len: [0; ADEV_LONG_LEVELS],
+            prev: [[0.0; 3]; ADEV_LONG_LEVELS],
+            has_prev: [false; ADEV_LONG_LEVELS],
+            acc: [[0.0; 3]; ADEV_LONG_LEVELS],
+            cnt: [0; ADEV_LONG_LEVELS],
+        }
+    }
+
+    fn push(&mut self, g: [i16; 3]) {
+        for k in 0..ADEV_LONG_LEVELS {
+            let m = ADEV_LONG_BASE << k;
+            for ax in 0..3 {
+                self.sum[k][ax] += g[ax] as i64;
+            }
+            self.len[k] += 1;
+            if self.len[k] < m { continue; }
+
+            for ax in 0..3 {
+                let mean = self.sum[k][ax] as f64 / m as f64;
+                if self.has_prev[k] {
+                    let d = mean - self.prev[k][ax];
+                    self.acc[k][ax] += d * d;
+                }
+                self.prev[k][ax] = mean;
+                self.sum[k][ax] = 0;
+            }
+            if self.has_prev[k] { self.cnt[k] += 1; }
+            self.has_prev[k] = true;
+            self.len[k] = 0;
+        }
+    }
+
+    fn tau_s(k: usize) -> f32 {
+        (ADEV_LONG_BASE << k) as f32 / IMU_RATE_HZ as f32
+    }
+
+    /// Allan deviation (rad/s), 0 tant que ADEV_LONG_MIN_CLUSTERS ne sont pas atteints
+    fn adev(&self, k: usize) -> [f32; 3] {
+        let mut out = [0.0f32; 3];
+        if self.cnt[k] < ADEV_LONG_MIN_CLUSTERS { return out; }
+        for ax in 0..3 {
+            let avar = (self.acc[k][ax] / (2.0 * self.cnt[k] as f64)) as f32;
+            out[ax] = avar.sqrt() * GYRO_LSB_TO_RAD_S;
+        }
+        out
+    }
+}
+
+/// Allan Variance à clusters chevauchants, calculée au fil de l'eau.
+///
+/// Pour chaque m : somme glissante des m derniers échantillons (`sum_a`) et des
+/// m précédents (`sum_b`), en entiers LSB (exact, pas de dérive sur 1 h).
+/// AVAR(m) = Σ (ȳa − ȳb)² / (2·N).
+struct AllanOnline {
+    hist: [[i16; 3]; ADEV_HIST],
+    n: usize,
+    sum_a: [[i32; 3]; ADEV_TAUS.len()],
+    sum_b: [[i32; 3]; ADEV_TAUS.len()],
+    acc: [[f64; 3]; ADEV_TAUS.len()],
+    cnt: [u32; ADEV_TAUS.len()],
+    long: AllanLong,
+}
+
+impl AllanOnline {
+    const fn new() -> Self {
+        Self {
+            hist: [[0; 3]; ADEV_HIST],
+            n: 0,
+            sum_a: [[0; 3]; ADEV_TAUS.len()],
+            sum_b: [[0; 3]; ADEV_TAUS.len()],
+            acc: [[0.0; 3]; ADEV_TAUS.len()],
+            cnt: [0; ADEV_TAUS.len()],
+            long: AllanLong::new(),
+        }
+    }
+
+    fn push(&mut self, g: [i16; 3]) {
+        let n = self.n;
+        for (i, &m) in ADEV_TAUS.iter().enumerate() {
+            for ax in 0..3 {
+                self.sum_a[i][ax] += g[ax] as i32;
+                if n >= m {
+                    let x_m = self.hist[(n - m) % ADEV_HIST][ax] as i32;
+                    self.sum_a[i][ax] -= x_m;
+                    self.sum_b[i][ax] += x_m;
+                }
+                if n >= 2 * m {
+                    // Lu avant l'écriture de hist[n] (même case pour m = 128)
+                    self.sum_b[i][ax] -= self.hist[(n - 2 * m) % ADEV_HIST][ax] as i32;
+                }
+            }
+            if n + 1 >= 2 * m {
+                for ax in 0..3 {
+                    let d = (self.sum_a[i][ax] - self.sum_b[i][ax]) as f64 / m as f64;
+                    self.acc[i][ax] += d * d;
+                }
+                self.cnt[i] += 1;
+            }
+        }
+        self.hist[n % ADEV_HIST] = g;
+        self.n += 1;
+        self.long.push(g);
+    }
+
+    fn tau_s(i: usize) -> f32 {
+        ADEV_TAUS[i] as f32 / IMU_RATE_HZ as f32
+    }
+
+    /// Allan deviation (rad/s) pour chaque axe, 0 tant que le cluster n'est pas plein
+    fn adev(&self, i: usize) -> [f32; 3] {
+        let mut out = [0.0f32; 3];
+        if self.cnt[i] == 0 { return out; }
+        for ax in 0..3 {
+            let avar = (self.acc[i][ax] / (2.0 * self.cnt[i] as f64)) as f32;
+            out[ax] = avar.sqrt() * GYRO_LSB_TO_RAD_S;
+        }
+        out
+    }
+
+    /// Pente log-log (moindres carrés) et ARW (rad/s/√Hz) d'un axe.
+    /// ARW = intercept à tau = 1 s de la droite de pente −0.5 : N = σ(τ)·√τ.
+    fn arw_fit(&self, ax: usize) -> (f32, f32) {
+        let (mut sx, mut sy, mut sxx, mut sxy, mut sn) = (0.0f32, 0.0f32, 0.0f32, 0.0f32, 0.0f32);
+        let mut ln_n = 0.0f32;
+        for i in 0..ADEV_TAUS.len() {
+            let s = self.adev(i)[ax];
+            if s <= 0.0 { continue; }
+            let x = Self::tau_s(i).ln();
+            let y = s.ln();
+            sx += x; sy += y; sxx += x * x; sxy += x * y; sn += 1.0;
+            ln_n += y + 0.5 * x;
+        }
+        if sn < 2.0 { return (0.0, 0.0); }
+        let slope = (sn * sxy - sx * sy) / (sn * sxx - sx * sx);
+        (slope, (ln_n / sn).exp())
+    }
+
+    /// Bias instability = minimum de la courbe ADEV (comme le script MATLAB) et
+    /// son tau (s). Cherché sur les taus longs : jusqu'à 0.256 s la courbe est
+    /// encore dominée par le bruit blanc. Session trop courte → taus courts
+    /// (valeur pessimiste).
+    fn bias_instability(&self, ax: usize) -> (f32, f32) {
+        let long = (0..ADEV_LONG_LEVELS).map(|k| (self.long.adev(k)[ax], AllanLong::tau_s(k)));
+        let short = (0..ADEV_TAUS.len()).map(|i| (self.adev(i)[ax], Self::tau_s(i)));
+        adev_min(long).or_else(|| adev_min(short)).unwrap_or((0.0, 0.0))
+    }
+
+    /// Densité spectrale du biais gyro ((rad/s)²/s, multipliée par dt dans
+    /// l'EKF) : Gauss-Markov d'écart-type BI et de temps de corrélation le tau
+    /// du minimum, au moins GBIAS_TAU_MIN_S → q = 2·BI² / T.
+    fn q_gbias(&self, ax: usize) -> f32 {
+        let (bi, tau) = self.bias_instability(ax);
+        2.0 * bi * bi / tau.max(GBIAS_TAU_MIN_S)
+    }
+}
+
+/// Point (adev, tau) le plus bas d'une courbe ADEV, en ignorant les taus vides
+fn adev_min(points: impl Iterator<Item = (f32, f32)>) -> Option<(f32, f32)> {
+    points.filter(|&(s, _)| s > 0.0).min_by(|a, b| a.0.total_cmp(&b.0))
+}
+
 // ── Interruptions ─────────────────────────────────────────────────────────────
 bind_interrupts!(struct Irqs {
     I2C1_EV => embassy_stm32::i2c::EventInterruptHandler<peripherals::I2C1>;
     I2C1_ER => embassy_stm32::i2c::ErrorInterruptHandler<peripherals::I2C1>;
+    UART4   => embassy_stm32::usart::InterruptHandler<peripherals::UART4>;
 });
 
 // ── Tâche USB ─────────────────────────────────────────────────────────────────
@@ -141,12 +383,19 @@ async fn usb_task(mut device: embassy_usb::UsbDevice<'static, crate::usb::UsbDri
     device.run().await
 }
 
+/// Envoi USB par chunks de 64 octets (limite USB CDC)
+async fn usb_write_chunked(usb_serial: &mut usb::UsbSerial<'static>, b: &[u8]) {
+    for chunk in b.chunks(64) {
+        let _ = usb_serial.write_packet(chunk).await;
+    }
+}
+
 // ── Tâche Baro + Mag (I2C) ───────────────────────────────────────────────────
 #[embassy_executor::task]
 async fn baro_mag_task(
     mut i2c: I2c<'static, peripherals::I2C1, peripherals::DMA1_CH7, peripherals::DMA1_CH0>,
 ) {
-    let mut baro = Spl06::new();
+    let mut baro = Spl06::new(SPL06_ADDR_SDO_LOW);
     let mut mag  = Hmc5883::new();
     let _ = baro.init(&mut i2c).await;
     let _ = mag.init(&mut i2c).await;
@@ -202,6 +451,22 @@ async fn main(spawner: Spawner) {
     let cs  = Output::new(p.PB12.degrade(), Level::High, Speed::VeryHigh);
     let mut imu = Icm42688::new(spi, cs);
 
+    // SPI3 @ 10 MHz → W25Qxx flash (SCK=PC10, MOSI=PC12, MISO=PC11, CS=PB3)
+    let mut flash_cfg = SpiConfig::default();
+    flash_cfg.frequency = TimeHertz(10_000_000);
+    let flash_spi = Spi::new(p.SPI3, p.PC10, p.PC12, p.PC11, NoDma, NoDma, flash_cfg);
+    let flash_cs  = Output::new(p.PB3.degrade(), Level::High, Speed::VeryHigh);
+    let mut flash = W25qxx::new(flash_spi, flash_cs);
+
+    // UART4 @ 420 kBd → récepteur ELRS (TX=PA0, RX=PA1)
+    let mut crsf_cfg = UsartConfig::default();
+    crsf_cfg.baudrate = 420_000;
+    let mut crsf_uart = Uart::new(
+        p.UART4, p.PA1, p.PA0, Irqs,
+        p.DMA1_CH4, p.DMA1_CH2,
+        crsf_cfg,
+    ).unwrap();
+
     Timer::after(Duration::from_millis(200)).await;
     let _ = imu.init().await;
 
@@ -216,12 +481,86 @@ async fn main(spawner: Spawner) {
     led.set_high();
     Timer::after(Duration::from_millis(200)).await;
 
+    // ── Auto-test flash : écriture d'une page connue puis relecture ───────────
+    {
+        let mut pattern = [0u8; 256];
+        for (i, b) in pattern.iter_mut().enumerate() {
+            *b = (i as u8) ^ 0xA5;
+        }
+        let mut readback = [0u8; 256];
+
+        // Dernier secteur de la puce (réservé par FlashFs), selon la taille détectée
+        let res = async {
+            let size = flash.detect_size().await?;
+            let addr = flash.total_bytes() - FS_RESERVED_TAIL;
+            flash.sector_erase_4k(addr).await?;
+            flash.page_program(addr, &pattern).await?;
+            flash.read(addr, &mut readback).await?;
+            Ok::<_, drivers::flash::Error>((size, addr))
+        }.await;
+
+        let mut msg = heapless::String::<96>::new();
+        match res {
+            Ok((size, addr)) => {
+                let mismatches = pattern.iter().zip(readback.iter())
+                    .filter(|(a, b)| a != b)
+                    .count();
+                if mismatches == 0 {
+                    let _ = write!(msg, "# FLASH selftest OK {:?} (256 octets @0x{:06X})\r\n",
+                        size, addr);
+                } else {
+                    let _ = write!(msg, "# FLASH selftest ECHEC: {} octets differents\r\n",
+                        mismatches);
+                }
+            }
+            Err(e) => {
+                let _ = write!(msg, "# FLASH selftest ERREUR: {:?}\r\n", e);
+            }
+        }
+        if usb_serial.dtr() {
+            let _ = usb_serial.write_packet(msg.as_bytes()).await;
+        }
+    }
+
+    // ── Ping CRSF : vérifier que le récepteur répond avant un vol d'essai ─────
+    {
+        let mut pkt = [0u8; 8];
+        let len = build_ping_packet(&mut pkt, CRSF_ADDRESS_FLIGHT_CONTROLLER);
+        let _ = crsf_uart.write(&pkt[..len]).await;
+
+        let mut parser = CrsfParser::new();
+        let mut rx = [0u8; 64];
+        let deadline = Instant::now() + Duration::from_millis(CRSF_PING_TIMEOUT_MS);
+        while parser.device_info.is_none() {
+            match select(crsf_uart.read_until_idle(&mut rx), Timer::at(deadline)).await {
+                Either::First(Ok(n)) => { parser.push_bytes(&rx[..n]); }
+                Either::First(Err(_)) => {}
+                Either::Second(_) => break,
+            }
+        }
+
+        let mut msg = heapless::String::<96>::new();
+        match &parser.device_info {
+            Some(info) => {
+                let _ = write!(msg, "# CRSF RX: {} (0x{:02X}) sw=0x{:08X}\r\n",
+                    info.name, info.origin, info.sw_version);
+            }
+            None => {
+                let _ = write!(msg, "# CRSF RX: pas de reponse au ping\r\n");
+            }
+        }
+        if usb_serial.dtr() {
+            let _ = usb_serial.write_packet(msg.as_bytes()).await;
+        }
+    }
+
     // ── En-tête CSV ───────────────────────────────────────────────────────────
     let hdr = b"# Goldhorn_Air - 1h Allan Variance Calibration\r\n\
                 # IMU: ICM-42688 @500Hz | Baro: SPL06 @20Hz | Mag: HMC5883 @10Hz\r\n\
                 # LSB scale: gyro=16.4 LSB/dps | accel=2048 LSB/g (see file header for MATLAB)\r\n\
                 # ts_ms,gx_lsb,gy_lsb,gz_lsb,ax_lsb,ay_lsb,az_lsb,\
-                baro_alt_cm,baro_press_pa,baro_temp_mc,mag_x,mag_y,mag_z\r\n";
+                baro_alt_cm,baro_press_pa,baro_temp_mc,mag_x,mag_y,mag_z,\
+                ekf_roll_mrad,ekf_pitch_mrad,ekf_yaw_mrad\r\n";
     let _ = usb_serial.write_packet(hdr).await;
 
     // ── Boucle d'acquisition ──────────────────────────────────────────────────
@@ -229,6 +568,17 @@ async fn main(spawner: Spawner) {
     let mut ticker = Ticker::every(Duration::from_hz(IMU_RATE_HZ));
     let mut n:    u64 = 0;
     let mut errs: u32 = 0;
+    let mut ekf    = AttitudeEkf::new();
+    let imu_dt     = 1.0 / IMU_RATE_HZ as f32;
+    let mut allan  = AllanOnline::new();
+    // Sommes pour biais et R_ACCEL_NORMAL (LSB bruts, exacts sur 1 h)
+    let mut gyro_sum:  [i64; 3] = [0; 3];
+    let mut accel_sum: [i64; 3] = [0; 3];
+    let mut amag_sum:  f64 = 0.0;
+    let mut amag_sq:   f64 = 0.0;
+    // Bruit au repos par axe (gyro en °/s, accel en g)
+    let mut gyro_noise  = [NoiseEstimator::new(); 3];
+    let mut accel_noise = [NoiseEstimator::new(); 3];
 
     loop {
         ticker.next().await;
@@ -250,14 +600,63 @@ async fn main(spawner: Spawner) {
         let my = MAG_Y.load(Ordering::Relaxed);
         let mz = MAG_Z.load(Ordering::Relaxed);
 
-        // Ligne CSV (max ~110 caractères)
-        let mut line = heapless::String::<128>::new();
+        allan.push(gyro);
+        for j in 0..3 {
+            gyro_sum[j]  += gyro[j] as i64;
+            accel_sum[j] += accel[j] as i64;
+        }
+
+        // EKF sur l'échantillon brut (gyro 16.4 LSB/dps, accel 2048 LSB/g)
+        let g = |v: i16| (v as f32 / 16.4).to_radians();
+        let a = |v: i16| v as f32 / 2048.0;
+
+        if !gyro_noise[0].is_converged(NOISE_FLOOR_SAMPLES) {
+            for j in 0..3 {
+                gyro_noise[j].update(gyro[j] as f32 / 16.4);
+                accel_noise[j].update(a(accel[j]));
+            }
+            if gyro_noise[0].is_converged(NOISE_FLOOR_SAMPLES) && usb_serial.dtr() {
+                let accel_spec_mg = ACCEL_NOISE_DENSITY_UG * 1e-3
+                    * (IMU_RATE_HZ as f32 / 2.0).sqrt();
+                for (j, name) in ["gx", "gy", "gz"].iter().enumerate() {
+                    let sigma = gyro_noise[j].noise_sigma();
+                    let mut l = heapless::String::<96>::new();
+                    let _ = write!(l,
+                        "# NOISE {}: {:.4} dps RMS (spec {:.3}) {}\r\n",
+                        name, sigma, GYRO_NOISE_SPEC_DPS,
+                        if sigma > 2.0 * GYRO_NOISE_SPEC_DPS { "HAUT" } else { "OK" }
+                    );
+                    usb_write_chunked(&mut usb_serial, l.as_bytes()).await;
+                }
+                for (j, name) in ["ax", "ay", "az"].iter().enumerate() {
+                    let sigma_mg = accel_noise[j].noise_sigma() * 1e3;
+                    let mut l = heapless::String::<96>::new();
+                    let _ = write!(l,
+                        "# NOISE {}: {:.2} mg RMS (spec {:.2}) {}\r\n",
+                        name, sigma_mg, accel_spec_mg,
+                        if sigma_mg > 2.0 * accel_spec_mg { "HAUT" } else { "OK" }
+                    );
+                    usb_write_chunked(&mut usb_serial, l.as_bytes()).await;
+                }
+            }
+        }
+        ekf.predict(imu_dt, g(gyro[0]), g(gyro[1]), g(gyro[2]));
+        ekf.update_accel(a(accel[0]), a(accel[1]), a(accel[2]));
+        let (roll, pitch, yaw) = ekf.get_euler();
+        let amag = (a(accel[0]) * a(accel[0]) + a(accel[1]) * a(accel[1])
+            + a(accel[2]) * a(accel[2])).sqrt() - 1.0;
+        amag_sum += amag as f64;
+        amag_sq  += (amag * amag) as f64;
+
+        // Ligne CSV (max ~130 caractères)
+        let mut line = heapless::String::<160>::new();
         let _ = write!(line,
-            "{},{},{},{},{},{},{},{},{},{},{},{},{}\r\n",
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}\r\n",
             elapsed_ms,
             gyro[0], gyro[1], gyro[2],
             accel[0], accel[1], accel[2],
             ba, bp, bt, mx, my, mz,
+            (roll * 1000.0) as i32, (pitch * 1000.0) as i32, (yaw * 1000.0) as i32,
         );
 
         // Envoi USB par chunks de 64 octets (limite USB CDC)
@@ -286,6 +685,26 @@ async fn main(spawner: Spawner) {
                 s, rem, n / 1000, errs
             );
             let _ = usb_serial.write_packet(msg.as_bytes()).await;
+
+            for i in 0..ADEV_TAUS.len() {
+                let [sx, sy, sz] = allan.adev(i);
+                let mut l = heapless::String::<64>::new();
+                let _ = write!(l,
+                    "# ADEV tau={:.3}s: gx={:.2}e-4 gy={:.2}e-4 gz={:.2}e-4\r\n",
+                    AllanOnline::tau_s(i), sx * 1e4, sy * 1e4, sz * 1e4
+                );
+                let _ = usb_serial.write_packet(l.as_bytes()).await;
+            }
+            for k in 0..ADEV_LONG_LEVELS {
+                let [sx, sy, sz] = allan.long.adev(k);
+                if sx == 0.0 { continue; }
+                let mut l = heapless::String::<64>::new();
+                let _ = write!(l,
+                    "# ADEV tau={:.1}s: gx={:.2}e-4 gy={:.2}e-4 gz={:.2}e-4\r\n",
+                    AllanLong::tau_s(k), sx * 1e4, sy * 1e4, sz * 1e4
+                );
+                let _ = usb_serial.write_packet(l.as_bytes()).await;
+            }
         }
     }
 
@@ -300,6 +719,56 @@ async fn main(spawner: Spawner) {
         if usb_serial.dtr() {
             let _ = usb_serial.write_packet(footer.as_bytes()).await;
         }
+
+        // ARW / bias instability par axe, puis constantes EKF (gz, comme MATLAB §6)
+        let fs = IMU_RATE_HZ as f32;
+        for (ax, name) in ["gx", "gy", "gz"].iter().enumerate() {
+            let (slope, arw) = allan.arw_fit(ax);
+            let (bi, bi_tau) = allan.bias_instability(ax);
+            let mut l = heapless::String::<96>::new();
+            let _ = write!(l,
+                "# {}: pente={:.2} ARW={:.3e} rad/s/rtHz BI={:.3e} rad/s @{:.1}s\r\n",
+                name, slope, arw, bi, bi_tau
+            );
+            if usb_serial.dtr() {
+                usb_write_chunked(&mut usb_serial, l.as_bytes()).await;
+            }
+        }
+
+        // Bloc Rust à coller dans src/calibration.rs (biais en LSB, gravité retirée sur Z)
+        if n > 0 {
+            let nf = n as f64;
+            let mut gb = [0.0f32; 3];
+            let mut ab = [0.0f32; 3];
+            for j in 0..3 {
+                gb[j] = (gyro_sum[j] as f64 / nf) as f32;
+                ab[j] = (accel_sum[j] as f64 / nf) as f32;
+            }
+            ab[2] -= 2048.0;
+            let mean = amag_sum / nf;
+            let r_accel = (amag_sq / nf - mean * mean) as f32;
+            let (_, arw) = allan.arw_fit(2);
+
+            let mut block = heapless::String::<512>::new();
+            let _ = write!(block,
+                "// Generated by bin/calibrate ({} samples)\r\n\
+                 mod calibration {{\r\n\
+                 \x20   pub const GYRO_BIAS: [f32; 3] = [{:.2}, {:.2}, {:.2}];\r\n\
+                 \x20   pub const ACCEL_BIAS: [f32; 3] = [{:.2}, {:.2}, {:.2}];\r\n\
+                 \x20   pub const R_ACCEL_NORMAL: f32 = {:.6};\r\n\
+                 \x20   pub const Q_QUAT: f32 = {:.2e};\r\n\
+                 \x20   pub const Q_GBIAS: f32 = {:.2e};\r\n\
+                 }}\r\n",
+                n,
+                gb[0], gb[1], gb[2],
+                ab[0], ab[1], ab[2],
+                r_accel,
+                arw * arw / fs, allan.q_gbias(2)
+            );
+            if usb_serial.dtr() {
+                usb_write_chunked(&mut usb_serial, block.as_bytes()).await;
+            }
+        }
     }
 
     // Clignote vite → session terminée
@@ -1,14 +1,77 @@
+use embassy_stm32::peripherals::RTC;
 use embassy_stm32::rcc::*;
+use embassy_stm32::rtc::{DateTime, DayOfWeek, Rtc, RtcConfig, RtcError};
 use embassy_stm32::time::Hertz as TimeHertz;
 use embassy_stm32::Config;
 
+#[cfg(all(feature = "stm32f405", feature = "stm32f411"))]
+compile_error!("select one MCU: build the F411 with --no-default-features --features stm32f411");
+
+/// Written to RTC BKP0R to request the system bootloader on the next boot
+const DFU_MAGIC: u32 = 0xDEAD_BEEF;
+/// System memory (ROM bootloader with USB DFU), same address on F405 and F411
+const SYSTEM_MEMORY: u32 = 0x1FFF_0000;
+
+/// RTC backup register holding the brown-out reset count
+const BKP_BROWNOUT_COUNT: usize = 1;
+
+/// Why the MCU last reset, from the RCC_CSR flags
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResetCause {
+    PowerOn,
+    /// Supply dipped below the BOR threshold (motor surge on a sagging pack)
+    BrownOut,
+    /// IWDG or WWDG: a task stalled
+    Watchdog,
+    /// SYSRESETREQ (DUMP_LOG, DFU, panic)
+    Software,
+    /// NRST pin only (reset button, debugger)
+    PinReset,
+    Unknown,
+}
+
+impl ResetCause {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::PowerOn => "power-on",
+            Self::BrownOut => "brown-out",
+            Self::Watchdog => "watchdog (a task stalled)",
+            Self::Software => "software",
+            Self::PinReset => "reset pin",
+            Self::Unknown => "other",
+        }
+    }
+}
+
+/// Seconds between 1970-01-01 and 2000-01-01 (the RTC calendar starts at year 2000)
+const UNIX_2000: u32 = 946_684_800;
+
 pub struct Board {
     pub p: embassy_stm32::Peripherals,
 }
 
 impl Board {
     pub fn init() -> Self {
+        // DFU request from the previous run: jump before touching the clocks
+        Self::enable_backup_access();
+        if embassy_stm32::pac::RTC.bkpr(0).read().bkp() == DFU_MAGIC {
+            embassy_stm32::pac::RTC.bkpr(0).write(|w| w.set_bkp(0));
+            unsafe { cortex_m::asm::bootload(SYSTEM_MEMORY as *const u32) };
+        }
+
         let mut config = Config::default();
+        Self::configure_clocks(&mut config);
+        // RTC on the 32.768 kHz LSE crystal (PC14 / PC15)
+        config.rcc.ls = LsConfig::default_lse();
+
+        let p = embassy_stm32::init(config);
+
+        Self { p }
+    }
+
+    /// JHEF405PRO: 8 MHz HSE → 168 MHz SYSCLK, 48 MHz USB
+    #[cfg(feature = "stm32f405")]
+    fn configure_clocks(config: &mut Config) {
         config.rcc.hse = Some(Hse {
             freq: TimeHertz(8_000_000), // Quartz 8MHz
             mode: HseMode::Oscillator,
@@ -25,9 +88,166 @@ impl Board {
         config.rcc.ahb_pre = AHBPrescaler::DIV1;
         config.rcc.apb1_pre = APBPrescaler::DIV4;
         config.rcc.apb2_pre = APBPrescaler::DIV2;
+    }
 
-        let p = embassy_stm32::init(config);
+    /// Black Pill STM32F411CE: 25 MHz HSE → 96 MHz SYSCLK, 48 MHz USB.
+    /// 100 MHz (the F411 max) would leave USB at 50 MHz, outside the ±0.25 %
+    /// the OTG FS PHY needs, so the VCO runs at 384 MHz instead of 400.
+    ///
+    /// Not a drop-in board swap — besides the clocks:
+    ///   - no UART4 / USART3 (CRSF, GPS): remap to USART1 / USART2 in main.rs
+    ///   - 48-pin package, port C stops at PC13–PC15: no baro DRDY (PC0), no
+    ///     current / VBAT ADC (PC2 / PC3), no SPI3 flash pins (PC10–PC12)
+    ///   - user key on PA0, which is CRSF TX on the JHEF405PRO
+    ///   - 512 KiB flash / 128 KiB RAM instead of 1 MiB / 192 KiB (memory.x)
+    #[cfg(feature = "stm32f411")]
+    fn configure_clocks(config: &mut Config) {
+        config.rcc.hse = Some(Hse {
+            freq: TimeHertz(25_000_000),
+            mode: HseMode::Oscillator,
+        });
+        config.rcc.pll_src = PllSource::HSE;
+        config.rcc.pll = Some(Pll {
+            prediv: PllPreDiv::DIV25,
+            mul: PllMul::MUL384,
+            divp: Some(PllPDiv::DIV4), // 96 MHz
+            divq: Some(PllQDiv::DIV8), // 48 MHz USB
+            divr: None,
+        });
+        config.rcc.sys = Sysclk::PLL1_P;
+        config.rcc.ahb_pre = AHBPrescaler::DIV1;
+        config.rcc.apb1_pre = APBPrescaler::DIV2; // 48 MHz (50 max)
+        config.rcc.apb2_pre = APBPrescaler::DIV1; // 96 MHz
+    }
 
-        Self { p }
+    /// Reboot into the ROM DFU bootloader (USB command `DFU`).
+    ///
+    /// Then flash from the host with:
+    ///   dfu-util -a 0 -s 0x08000000:leave -D firmware.bin
+    pub fn reboot_to_dfu() -> ! {
+        Self::enable_backup_access();
+        embassy_stm32::pac::RTC.bkpr(0).write(|w| w.set_bkp(DFU_MAGIC));
+        cortex_m::peripheral::SCB::sys_reset()
+    }
+
+    /// Cause of the last reset. Flags accumulate until `clear_reset_flags`;
+    /// the NRST pin flag is set by every reset, so it is checked last, and a
+    /// power-on also sets BORRSTF, so PORRSTF wins over it.
+    pub fn reset_cause() -> ResetCause {
+        let csr = embassy_stm32::pac::RCC.csr().read();
+        if csr.wdgrstf() || csr.wwdgrstf() {
+            ResetCause::Watchdog
+        } else if csr.sftrstf() {
+            ResetCause::Software
+        } else if csr.porrstf() {
+            ResetCause::PowerOn
+        } else if csr.borrstf() {
+            ResetCause::BrownOut
+        } else if csr.padrstf() {
+            ResetCause::PinReset
+        } else {
+            ResetCause::Unknown
+        }
+    }
+
+    pub fn clear_reset_flags() {
+        embassy_stm32::pac::RCC.csr().modify(|w| w.set_rmvf(true));
+    }
+
+    /// Increment and return the brown-out count kept in RTC BKP1R (survives
+    /// resets as long as the backup domain stays powered)
+    pub fn record_brownout() -> u32 {
+        Self::enable_backup_access();
+        let bkp = embassy_stm32::pac::RTC.bkpr(BKP_BROWNOUT_COUNT);
+        let n = bkp.read().bkp().saturating_add(1);
+        bkp.write(|w| w.set_bkp(n));
+        n
+    }
+
+    /// PWR clock on + backup-domain write protection off (RTC backup registers)
+    fn enable_backup_access() {
+        embassy_stm32::pac::RCC.apb1enr().modify(|w| w.set_pwren(true));
+        embassy_stm32::pac::PWR.cr1().modify(|w| w.set_dbp(true));
+    }
+
+    // ── RTC (wall-clock time for the flight log) ─────────────────────────────
+
+    /// Start the RTC (LSE, set up in `init`) and set the calendar to the given
+    /// UTC date — unless the calendar is already initialised (warm reset,
+    /// earlier SETTIME), which is kept. No VBAT cell: a power cut loses it.
+    pub fn init_rtc(rtc: RTC, year: u16, month: u8, day: u8, hour: u8, min: u8, sec: u8) -> Rtc {
+        let already_set = embassy_stm32::pac::RTC.isr().read().inits();
+        let mut rtc = Rtc::new(rtc, RtcConfig::default());
+        if !already_set {
+            let days = days_from_civil(year, month, day);
+            if let Ok(t) = DateTime::from(year, month, day, weekday(days), hour, min, sec) {
+                let _ = rtc.set_datetime(t);
+            }
+        }
+        rtc
+    }
+
+    /// Seconds since 1970-01-01 UTC (0 if the calendar cannot be read)
+    pub fn rtc_unix_timestamp(rtc: &Rtc) -> u32 {
+        let Ok(t) = rtc.now() else { return 0 };
+        let days = days_from_civil(t.year(), t.month(), t.day());
+        days * 86_400 + t.hour() as u32 * 3600 + t.minute() as u32 * 60 + t.second() as u32
+    }
+
+    /// Set the calendar from a Unix timestamp (USB `SETTIME=`)
+    pub fn set_rtc_unix(rtc: &mut Rtc, unix_s: u32) -> Result<(), RtcError> {
+        let unix_s = unix_s.max(UNIX_2000);
+        let days = unix_s / 86_400;
+        let secs = unix_s % 86_400;
+        let (year, month, day) = civil_from_days(days);
+        let t = DateTime::from(
+            year,
+            month,
+            day,
+            weekday(days),
+            (secs / 3600) as u8,
+            (secs / 60 % 60) as u8,
+            (secs % 60) as u8,
+        )
+        .map_err(RtcError::InvalidDateTime)?;
+        rtc.set_datetime(t)
+    }
+}
+
+/// Days since 1970-01-01 for a Gregorian date (H. Hinnant's algorithm, year ≥ 1970)
+fn days_from_civil(year: u16, month: u8, day: u8) -> u32 {
+    let y = year as u32 - (month <= 2) as u32;
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let m = month as u32;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as u32 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Inverse of `days_from_civil`: (year, month, day)
+fn civil_from_days(days: u32) -> (u16, u8, u8) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u8;
+    let year = (yoe + era * 400 + (month <= 2) as u32) as u16;
+    (year, month, day)
+}
+
+/// 1970-01-01 was a Thursday
+fn weekday(days: u32) -> DayOfWeek {
+    match (days + 3) % 7 {
+        0 => DayOfWeek::Monday,
+        1 => DayOfWeek::Tuesday,
+        2 => DayOfWeek::Wednesday,
+        3 => DayOfWeek::Thursday,
+        4 => DayOfWeek::Friday,
+        5 => DayOfWeek::Saturday,
+        _ => DayOfWeek::Sunday,
     }
 }
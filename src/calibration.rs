@@ -0,0 +1,9 @@
+// Generated by bin/calibrate — replace with the block printed at the end of a session.
+// Defaults: zero biases, noise = the current ekf.rs constants.
+mod calibration {
+    pub const GYRO_BIAS: [f32; 3] = [0.00, 0.00, 0.00];
+    pub const ACCEL_BIAS: [f32; 3] = [0.00, 0.00, 0.00];
+    pub const R_ACCEL_NORMAL: f32 = 0.050000;
+    pub const Q_QUAT: f32 = 1.00e-6;
+    pub const Q_GBIAS: f32 = 1.00e-7;
+}
@@ -19,6 +19,69 @@ impl Default for Quaternion {
     }
 }
 
+// ── Shared filter interface ──────────────────────────────────────────────────
+
+/// Common read-out for the attitude filters, so the caller can switch
+/// between Mahony and Madgwick with a single type alias.
+#[allow(dead_code)]
+pub trait AhrsFilter {
+    fn quaternion(&self) -> Quaternion;
+
+    /// Rotate the given vector (x, y, z) from BODY frame to EARTH frame
+    /// Returns (x_earth, y_earth, z_earth)
+    /// Used to get vertical acceleration (Z-earth)
+    fn rotate_vector(&self, x: f32, y: f32, z: f32) -> (f32, f32, f32) {
+        // q * v * q_conj
+        // Implementation of vector rotation by quaternion
+        let Quaternion { w: q0, x: q1, y: q2, z: q3 } = self.quaternion();
+
+        // https://gamedev.stackexchange.com/questions/28395/rotating-vector3-by-a-quaternion
+        let num12 = q0 * q0;
+        let num02 = q1 * q1;
+        let num13 = q2 * q2;
+        let num03 = q3 * q3;
+
+        let x_out = x * (num12 + num02 - num13 - num03)
+            + y * (2. * (q1 * q2 - q0 * q3))
+            + z * (2. * (q1 * q3 + q0 * q2));
+        let y_out = x * (2. * (q1 * q2 + q0 * q3))
+            + y * (num12 - num02 + num13 - num03)
+            + z * (2. * (q2 * q3 - q0 * q1));
+        let z_out = x * (2. * (q1 * q3 - q0 * q2))
+            + y * (2. * (q2 * q3 + q0 * q1))
+            + z * (num12 - num02 - num13 + num03);
+
+        (x_out, y_out, z_out)
+    }
+
+    fn get_euler_angles(&self) -> (f32, f32, f32) {
+        let Quaternion { w: q0, x: q1, y: q2, z: q3 } = self.quaternion();
+
+        // Roll (x-axis rotation)
+        let sinr_cosp = 2.0 * (q0 * q1 + q2 * q3);
+        let cosr_cosp = 1.0 - 2.0 * (q1 * q1 + q2 * q2);
+        let roll = sinr_cosp.atan2(cosr_cosp);
+
+        // Pitch (y-axis rotation)
+        let sinp = 2.0 * (q0 * q2 - q3 * q1);
+        let pitch = if sinp.abs() >= 1.0 {
+            // use 90 degrees if out of range
+            core::f32::consts::FRAC_PI_2.copysign(sinp)
+        } else {
+            sinp.asin()
+        };
+
+        // Yaw (z-axis rotation)
+        let siny_cosp = 2.0 * (q0 * q3 + q1 * q2);
+        let cosy_cosp = 1.0 - 2.0 * (q2 * q2 + q3 * q3);
+        let yaw = siny_cosp.atan2(cosy_cosp);
+
+        (roll, pitch, yaw)
+    }
+}
+
+// ── Mahony ───────────────────────────────────────────────────────────────────
+
 pub struct Mahony {
     // PID constants
     kp: f32,
@@ -29,21 +92,81 @@ pub struct Mahony {
     iy: f32,
     iz: f32,
 
+    /// |measured × estimated gravity| of the last update (sine of the tilt error)
+    grav_err: f32,
+
     pub q: Quaternion,
 }
 
+/// Default integral gain used by `Mahony::new`
+const DEFAULT_KI: f32 = 0.005;
+/// Gravity direction error (sine of the angle) below which the attitude is
+/// considered settled — about 1°
+pub const CONVERGENCE_THRESHOLD: f32 = 0.02;
+
 impl Mahony {
-    pub fn new() -> Self {
+    /// Mahony filter with proportional gain `kp` and the default `ki`.
+    pub fn new(kp: f32) -> Self {
+        Self::new_with_ki(kp, DEFAULT_KI)
+    }
+
+    pub fn new_with_ki(kp: f32, ki: f32) -> Self {
         Self {
-            kp: 2.0,   // Default Kp
-            ki: 0.005, // Default Ki (slightly higher for mag)
+            kp,
+            ki,
             ix: 0.0,
             iy: 0.0,
             iz: 0.0,
+            grav_err: 1.0,
             q: Quaternion::default(),
         }
     }
 
+    /// Runtime gain scheduling. Lower `kp` during the high-G burn: the accel
+    /// no longer points at gravity when total G >> 1, and a high `kp` would
+    /// drag the attitude towards the thrust vector.
+    #[allow(dead_code)]
+    pub fn set_kp(&mut self, kp: f32) {
+        self.kp = kp;
+    }
+
+    #[allow(dead_code)]
+    pub fn set_ki(&mut self, ki: f32) {
+        self.ki = ki;
+    }
+
+    /// Align on a static accelerometer reading (e.g. tilted on the launch rail)
+    /// instead of slowly converging from identity. Yaw is set to 0.
+    pub fn reset_to_accel(&mut self, ax: f32, ay: f32, az: f32) {
+        let roll = ay.atan2(az);
+        let pitch = (-ax).atan2((ay * ay + az * az).sqrt());
+
+        let (sr, cr) = ((roll * 0.5).sin(), (roll * 0.5).cos());
+        let (sp, cp) = ((pitch * 0.5).sin(), (pitch * 0.5).cos());
+
+        // ZYX Euler → quaternion with yaw = 0
+        self.q = Quaternion {
+            w: cr * cp,
+            x: sr * cp,
+            y: cr * sp,
+            z: -sr * sp,
+        };
+        self.ix = 0.0;
+        self.iy = 0.0;
+        self.iz = 0.0;
+        self.grav_err = 1.0;
+    }
+
+    /// Angle between the measured and the estimated gravity (sine), from the
+    /// last update — only meaningful while the board is still.
+    pub fn convergence_metric(&self) -> f32 {
+        self.grav_err
+    }
+
+    pub fn is_converged(&self) -> bool {
+        self.convergence_metric() < CONVERGENCE_THRESHOLD
+    }
+
     pub fn update(&mut self, dt: f32, gx: f32, gy: f32, gz: f32, ax: f32, ay: f32, az: f32) {
         let mut q0 = self.q.w;
         let mut q1 = self.q.x;
@@ -69,6 +192,7 @@ impl Mahony {
         let halfex = ay * halfvz - az * halfvy;
         let halfey = az * halfvx - ax * halfvz;
         let halfez = ax * halfvy - ay * halfvx;
+        self.grav_err = 2.0 * (halfex * halfex + halfey * halfey + halfez * halfez).sqrt();
 
         // Compute and apply integral feedback if enabled
         if self.ki > 0.0 {
@@ -108,6 +232,7 @@ impl Mahony {
         self.q.z = q3 * recip_norm;
     }
 
+    #[allow(dead_code)]
     pub fn update_9dof(
         &mut self,
         dt: f32,
@@ -158,14 +283,16 @@ impl Mahony {
                 let vx = 2.0 * (q1 * q3 - q0 * q2);
                 let vy = 2.0 * (q0 * q1 + q2 * q3);
                 let vz = q0 * q0 - q1 * q1 - q2 * q2 + q3 * q3;
+                let (gex, gey, gez) = (ay * vz - az * vy, az * vx - ax * vz, ax * vy - ay * vx);
+                self.grav_err = (gex * gex + gey * gey + gez * gez).sqrt();
                 let wx = 2.0 * bx * (0.5 - q2 * q2 - q3 * q3) + 2.0 * bz * (q1 * q3 - q0 * q2);
                 let wy = 2.0 * bx * (q1 * q2 - q0 * q3) + 2.0 * bz * (q0 * q1 + q2 * q3);
                 let wz = 2.0 * bx * (q0 * q2 + q1 * q3) + 2.0 * bz * (0.5 - q1 * q1 - q2 * q2);
 
                 // Error is sum of cross product between estimated direction and measured direction of field vectors
-                let ex = (ay * vz - az * vy) + (my * wz - mz * wy);
-                let ey = (az * vx - ax * vz) + (mz * wx - mx * wz);
-                let ez = (ax * vy - ay * vx) + (mx * wy - my * wx);
+                let ex = gex + (my * wz - mz * wy);
+                let ey = gey + (mz * wx - mx * wz);
+                let ez = gez + (mx * wy - my * wx);
 
                 if self.ki > 0.0 {
                     self.ix += self.ki * ex * dt;
@@ -197,61 +324,217 @@ impl Mahony {
             }
         }
     }
+}
 
-    /// Rotate the given vector (x, y, z) from BODY frame to EARTH frame
-    /// Returns (x_earth, y_earth, z_earth)
-    /// Used to get vertical acceleration (Z-earth)
-    pub fn rotate_vector(&self, x: f32, y: f32, z: f32) -> (f32, f32, f32) {
-        // q * v * q_conj
-        // Implementation of vector rotation by quaternion
-        let q0 = self.q.w;
-        let q1 = self.q.x;
-        let q2 = self.q.y;
-        let q3 = self.q.z;
+impl AhrsFilter for Mahony {
+    fn quaternion(&self) -> Quaternion {
+        self.q
+    }
+}
 
-        // https://gamedev.stackexchange.com/questions/28395/rotating-vector3-by-a-quaternion
-        let num12 = q0 * q0;
-        let num02 = q1 * q1;
-        let num13 = q2 * q2;
-        let num03 = q3 * q3;
+// ── Madgwick ─────────────────────────────────────────────────────────────────
 
-        let x_out = x * (num12 + num02 - num13 - num03)
-            + y * (2. * (q1 * q2 - q0 * q3))
-            + z * (2. * (q1 * q3 + q0 * q2));
-        let y_out = x * (2. * (q1 * q2 + q0 * q3))
-            + y * (num12 - num02 + num13 - num03)
-            + z * (2. * (q2 * q3 - q0 * q1));
-        let z_out = x * (2. * (q1 * q3 - q0 * q2))
-            + y * (2. * (q2 * q3 + q0 * q1))
-            + z * (num12 - num02 - num13 + num03);
+/// Madgwick gradient-descent filter.
+///
+/// `beta` tuning: 0.1 → slow convergence, low noise (steady flight);
+/// 2.0 → fast alignment (startup), noisier attitude.
+#[allow(dead_code)]
+pub struct Madgwick {
+    beta: f32,
+    pub q: Quaternion,
+}
 
-        (x_out, y_out, z_out)
+#[allow(dead_code)]
+impl Madgwick {
+    pub fn new(beta: f32) -> Self {
+        Self {
+            beta,
+            q: Quaternion::default(),
+        }
     }
-    pub fn get_euler_angles(&self) -> (f32, f32, f32) {
-        let q0 = self.q.w;
-        let q1 = self.q.x;
-        let q2 = self.q.y;
-        let q3 = self.q.z;
 
-        // Roll (x-axis rotation)
-        let sinr_cosp = 2.0 * (q0 * q1 + q2 * q3);
-        let cosr_cosp = 1.0 - 2.0 * (q1 * q1 + q2 * q2);
-        let roll = sinr_cosp.atan2(cosr_cosp);
+    pub fn set_beta(&mut self, beta: f32) {
+        self.beta = beta;
+    }
 
-        // Pitch (y-axis rotation)
-        let sinp = 2.0 * (q0 * q2 - q3 * q1);
-        let pitch = if sinp.abs() >= 1.0 {
-            // use 90 degrees if out of range
-            core::f32::consts::FRAC_PI_2.copysign(sinp)
-        } else {
-            sinp.asin()
-        };
+    pub fn update_6dof(&mut self, dt: f32, gx: f32, gy: f32, gz: f32, ax: f32, ay: f32, az: f32) {
+        let (mut q0, mut q1, mut q2, mut q3) = (self.q.w, self.q.x, self.q.y, self.q.z);
 
-        // Yaw (z-axis rotation)
-        let siny_cosp = 2.0 * (q0 * q3 + q1 * q2);
-        let cosy_cosp = 1.0 - 2.0 * (q2 * q2 + q3 * q3);
-        let yaw = siny_cosp.atan2(cosy_cosp);
+        // Rate of change of quaternion from gyroscope
+        let mut qdot1 = 0.5 * (-q1 * gx - q2 * gy - q3 * gz);
+        let mut qdot2 = 0.5 * (q0 * gx + q2 * gz - q3 * gy);
+        let mut qdot3 = 0.5 * (q0 * gy - q1 * gz + q3 * gx);
+        let mut qdot4 = 0.5 * (q0 * gz + q1 * gy - q2 * gx);
 
-        (roll, pitch, yaw)
+        let norm = ax * ax + ay * ay + az * az;
+        if norm > 0.0 {
+            let recip_norm = norm.sqrt().recip();
+            let ax = ax * recip_norm;
+            let ay = ay * recip_norm;
+            let az = az * recip_norm;
+
+            let _2q0 = 2.0 * q0;
+            let _2q1 = 2.0 * q1;
+            let _2q2 = 2.0 * q2;
+            let _2q3 = 2.0 * q3;
+            let _4q0 = 4.0 * q0;
+            let _4q1 = 4.0 * q1;
+            let _4q2 = 4.0 * q2;
+            let _8q1 = 8.0 * q1;
+            let _8q2 = 8.0 * q2;
+            let q0q0 = q0 * q0;
+            let q1q1 = q1 * q1;
+            let q2q2 = q2 * q2;
+            let q3q3 = q3 * q3;
+
+            // Gradient descent corrective step
+            let s0 = _4q0 * q2q2 + _2q2 * ax + _4q0 * q1q1 - _2q1 * ay;
+            let s1 = _4q1 * q3q3 - _2q3 * ax + 4.0 * q0q0 * q1 - _2q0 * ay - _4q1
+                + _8q1 * q1q1 + _8q1 * q2q2 + _4q1 * az;
+            let s2 = 4.0 * q0q0 * q2 + _2q0 * ax + _4q2 * q3q3 - _2q3 * ay - _4q2
+                + _8q2 * q1q1 + _8q2 * q2q2 + _4q2 * az;
+            let s3 = 4.0 * q1q1 * q3 - _2q1 * ax + 4.0 * q2q2 * q3 - _2q2 * ay;
+
+            let s_norm = s0 * s0 + s1 * s1 + s2 * s2 + s3 * s3;
+            if s_norm > 0.0 {
+                let recip_norm = s_norm.sqrt().recip();
+                qdot1 -= self.beta * s0 * recip_norm;
+                qdot2 -= self.beta * s1 * recip_norm;
+                qdot3 -= self.beta * s2 * recip_norm;
+                qdot4 -= self.beta * s3 * recip_norm;
+            }
+        }
+
+        q0 += qdot1 * dt;
+        q1 += qdot2 * dt;
+        q2 += qdot3 * dt;
+        q3 += qdot4 * dt;
+        self.set_normalised(q0, q1, q2, q3);
+    }
+
+    pub fn update_9dof(
+        &mut self,
+        dt: f32,
+        gx: f32,
+        gy: f32,
+        gz: f32,
+        ax: f32,
+        ay: f32,
+        az: f32,
+        mx: f32,
+        my: f32,
+        mz: f32,
+    ) {
+        let m_norm = mx * mx + my * my + mz * mz;
+        if m_norm == 0.0 {
+            self.update_6dof(dt, gx, gy, gz, ax, ay, az);
+            return;
+        }
+
+        let (mut q0, mut q1, mut q2, mut q3) = (self.q.w, self.q.x, self.q.y, self.q.z);
+
+        // Rate of change of quaternion from gyroscope
+        let mut qdot1 = 0.5 * (-q1 * gx - q2 * gy - q3 * gz);
+        let mut qdot2 = 0.5 * (q0 * gx + q2 * gz - q3 * gy);
+        let mut qdot3 = 0.5 * (q0 * gy - q1 * gz + q3 * gx);
+        let mut qdot4 = 0.5 * (q0 * gz + q1 * gy - q2 * gx);
+
+        let a_norm = ax * ax + ay * ay + az * az;
+        if a_norm > 0.0 {
+            let recip_norm = a_norm.sqrt().recip();
+            let ax = ax * recip_norm;
+            let ay = ay * recip_norm;
+            let az = az * recip_norm;
+
+            let recip_norm = m_norm.sqrt().recip();
+            let mx = mx * recip_norm;
+            let my = my * recip_norm;
+            let mz = mz * recip_norm;
+
+            let _2q0mx = 2.0 * q0 * mx;
+            let _2q0my = 2.0 * q0 * my;
+            let _2q0mz = 2.0 * q0 * mz;
+            let _2q1mx = 2.0 * q1 * mx;
+            let _2q0 = 2.0 * q0;
+            let _2q1 = 2.0 * q1;
+            let _2q2 = 2.0 * q2;
+            let _2q3 = 2.0 * q3;
+            let _2q0q2 = 2.0 * q0 * q2;
+            let _2q2q3 = 2.0 * q2 * q3;
+            let q0q0 = q0 * q0;
+            let q0q1 = q0 * q1;
+            let q0q2 = q0 * q2;
+            let q0q3 = q0 * q3;
+            let q1q1 = q1 * q1;
+            let q1q2 = q1 * q2;
+            let q1q3 = q1 * q3;
+            let q2q2 = q2 * q2;
+            let q2q3 = q2 * q3;
+            let q3q3 = q3 * q3;
+
+            // Reference direction of Earth's magnetic field
+            let hx = mx * q0q0 - _2q0my * q3 + _2q0mz * q2 + mx * q1q1 + _2q1 * my * q2
+                + _2q1 * mz * q3 - mx * q2q2 - mx * q3q3;
+            let hy = _2q0mx * q3 + my * q0q0 - _2q0mz * q1 + _2q1mx * q2 - my * q1q1
+                + my * q2q2 + _2q2 * mz * q3 - my * q3q3;
+            let _2bx = (hx * hx + hy * hy).sqrt();
+            let _2bz = -_2q0mx * q2 + _2q0my * q1 + mz * q0q0 + _2q1mx * q3 - mz * q1q1
+                + _2q2 * my * q3 - mz * q2q2 + mz * q3q3;
+            let _4bx = 2.0 * _2bx;
+            let _4bz = 2.0 * _2bz;
+
+            // Objective function residuals (gravity, then magnetic field)
+            let fax = 2.0 * q1q3 - _2q0q2 - ax;
+            let fay = 2.0 * q0q1 + _2q2q3 - ay;
+            let faz = 1.0 - 2.0 * q1q1 - 2.0 * q2q2 - az;
+            let fmx = _2bx * (0.5 - q2q2 - q3q3) + _2bz * (q1q3 - q0q2) - mx;
+            let fmy = _2bx * (q1q2 - q0q3) + _2bz * (q0q1 + q2q3) - my;
+            let fmz = _2bx * (q0q2 + q1q3) + _2bz * (0.5 - q1q1 - q2q2) - mz;
+
+            // Gradient descent corrective step
+            let s0 = -_2q2 * fax + _2q1 * fay - _2bz * q2 * fmx
+                + (-_2bx * q3 + _2bz * q1) * fmy
+                + _2bx * q2 * fmz;
+            let s1 = _2q3 * fax + _2q0 * fay - 4.0 * q1 * faz + _2bz * q3 * fmx
+                + (_2bx * q2 + _2bz * q0) * fmy
+                + (_2bx * q3 - _4bz * q1) * fmz;
+            let s2 = -_2q0 * fax + _2q3 * fay - 4.0 * q2 * faz
+                + (-_4bx * q2 - _2bz * q0) * fmx
+                + (_2bx * q1 + _2bz * q3) * fmy
+                + (_2bx * q0 - _4bz * q2) * fmz;
+            let s3 = _2q1 * fax + _2q2 * fay
+                + (-_4bx * q3 + _2bz * q1) * fmx
+                + (-_2bx * q0 + _2bz * q2) * fmy
+                + _2bx * q1 * fmz;
+
+            let s_norm = s0 * s0 + s1 * s1 + s2 * s2 + s3 * s3;
+            if s_norm > 0.0 {
+                let recip_norm = s_norm.sqrt().recip();
+                qdot1 -= self.beta * s0 * recip_norm;
+                qdot2 -= self.beta * s1 * recip_norm;
+                qdot3 -= self.beta * s2 * recip_norm;
+                qdot4 -= self.beta * s3 * recip_norm;
+            }
+        }
+
+        q0 += qdot1 * dt;
+        q1 += qdot2 * dt;
+        q2 += qdot3 * dt;
+        q3 += qdot4 * dt;
+        self.set_normalised(q0, q1, q2, q3);
+    }
+
+    fn set_normalised(&mut self, q0: f32, q1: f32, q2: f32, q3: f32) {
+        let recip_norm = (q0 * q0 + q1 * q1 + q2 * q2 + q3 * q3).sqrt().recip();
+        self.q.w = q0 * recip_norm;
+        self.q.x = q1 * recip_norm;
+        self.q.y = q2 * recip_norm;
+        self.q.z = q3 * recip_norm;
+    }
+}
+
+impl AhrsFilter for Madgwick {
+    fn quaternion(&self) -> Quaternion {
+        self.q
     }
 }
@@ -1,19 +1,177 @@
 pub const CRSF_SYNC: u8 = 0xC8;
 pub const CRSF_FRAMETYPE_RC_CHANNELS_PACKED: u8 = 0x16;
+pub const CRSF_FRAMETYPE_LINK_STATISTICS: u8 = 0x14;
+#[allow(dead_code)]
+pub const CRSF_FRAMETYPE_DEVICE_PING: u8 = 0x28;
+pub const CRSF_FRAMETYPE_DEVICE_INFO: u8 = 0x29;
+#[allow(dead_code)]
+pub const CRSF_FRAMETYPE_MSP_REQ: u8 = 0x7A;   // MSP request / command
+#[allow(dead_code)]
+pub const CRSF_FRAMETYPE_MSP_RESP: u8 = 0x7B;  // MSP response (FC → ground)
+#[allow(dead_code)]
+pub const CRSF_FRAMETYPE_MSP_WRITE: u8 = 0x7C; // MSP write, no response expected
+
+/// Lowest valid CRSF channel value (988 µs); throttle below it = no pilot input
+pub const CRSF_CHANNEL_MIN: u16 = 172;
+/// No RC frame for this long with throttle below CRSF_CHANNEL_MIN → failsafe
+pub const CRSF_FAILSAFE_TIMEOUT_MS: u32 = 500;
 
 #[derive(Debug, Default, Clone, Copy)]
 pub struct RcChannels {
     pub channels: [u16; 16], // 11-bit values (0-2047)
 }
 
+/// LINK_STATISTICS (0x14) — sent by the receiver, 10 bytes.
+/// RSSI bytes are positive numbers meaning -dBm (e.g. 49 → -49 dBm).
+#[derive(Debug, Default, Clone, Copy)]
+#[allow(dead_code)]
+pub struct LinkStats {
+    pub uplink_rssi_ant1: u8,  // -dBm
+    pub uplink_rssi_ant2: u8,  // -dBm
+    pub uplink_lq: u8,         // % (0-100)
+    pub uplink_snr: i8,        // dB
+    pub active_antenna: u8,    // 0 = ant1, 1 = ant2
+    pub rf_mode: u8,           // ELRS packet rate index
+    pub uplink_tx_power: u8,   // enum (0=0mW, 1=10mW, 2=25mW, 3=100mW, ...)
+    pub downlink_rssi: u8,     // -dBm
+    pub downlink_lq: u8,       // %
+    pub downlink_snr: i8,      // dB
+}
+
+impl LinkStats {
+    /// Uplink RSSI of the active antenna in dBm
+    pub fn rssi_dbm(&self) -> i16 {
+        let raw = if self.active_antenna == 0 { self.uplink_rssi_ant1 } else { self.uplink_rssi_ant2 };
+        -(raw as i16)
+    }
+}
+
+pub fn parse_link_stats(payload: &[u8]) -> Option<LinkStats> {
+    if payload.len() < 10 {
+        return None;
+    }
+    Some(LinkStats {
+        uplink_rssi_ant1: payload[0],
+        uplink_rssi_ant2: payload[1],
+        uplink_lq: payload[2],
+        uplink_snr: payload[3] as i8,
+        active_antenna: payload[4],
+        rf_mode: payload[5],
+        uplink_tx_power: payload[6],
+        downlink_rssi: payload[7],
+        downlink_lq: payload[8],
+        downlink_snr: payload[9] as i8,
+    })
+}
+
+/// DEVICE_INFO (0x29) — answer to a DEVICE_PING.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default)]
+pub struct DeviceInfo {
+    pub origin: u8,                 // address of the answering device
+    pub name: heapless::String<32>,
+    pub serial_no: u32,
+    pub hw_version: u32,
+    pub sw_version: u32,
+    pub field_count: u8,            // number of parameters
+    pub param_version: u8,
+}
+
+/// `payload` = everything after the type byte: [dest] [origin] [name\0] [serial u32]
+/// [hw u32] [sw u32] [field_count] [param_version], integers big-endian.
+pub fn parse_device_info(payload: &[u8]) -> Option<DeviceInfo> {
+    if payload.len() < 3 {
+        return None;
+    }
+    let origin = payload[1];
+    let rest = &payload[2..];
+    let nul = rest.iter().position(|&b| b == 0)?;
+    let tail = &rest[nul + 1..];
+    if tail.len() < 14 {
+        return None;
+    }
+    let be_u32 = |b: &[u8]| u32::from_be_bytes([b[0], b[1], b[2], b[3]]);
+
+    let mut name = heapless::String::new();
+    for &c in &rest[..nul] {
+        if name.push(c as char).is_err() {
+            break;
+        }
+    }
+    Some(DeviceInfo {
+        origin,
+        name,
+        serial_no: be_u32(&tail[0..4]),
+        hw_version: be_u32(&tail[4..8]),
+        sw_version: be_u32(&tail[8..12]),
+        field_count: tail[12],
+        param_version: tail[13],
+    })
+}
+
 pub struct CrsfParser {
     buffer: heapless::Vec<u8, 64>, // Max frame size
+    pub link_stats: LinkStats,     // latest 0x14 frame
+    pub link_stats_count: u16,     // 0x14 frames received
+    pub last_channels: RcChannels, // latest RC_CHANNELS_PACKED
+    pub device_info: Option<DeviceInfo>, // latest 0x29 (answer to build_ping_packet)
+    // ── Frame statistics (halved together when good_frames saturates) ──
+    pub crc_errors: u16,           // complete frames with a bad CRC
+    pub frame_errors: u16,         // invalid length byte after sync
+    pub good_frames: u16,          // CRC-valid frames of any type
+    // ── Timing (caller fills these, like GpsData::last_byte_ms) ──
+    pub last_frame_ms: u32,        // millis() at last parsed RC frame
+    pub now_ms: u32,               // millis() at the last push/poll
 }
 
 impl CrsfParser {
     pub fn new() -> Self {
         Self {
             buffer: heapless::Vec::new(),
+            link_stats: LinkStats::default(),
+            link_stats_count: 0,
+            last_channels: RcChannels::default(),
+            device_info: None,
+            crc_errors: 0,
+            frame_errors: 0,
+            good_frames: 0,
+            last_frame_ms: 0,
+            now_ms: 0,
+        }
+    }
+
+    /// Link lost: receiver outputs zeroed channels, or throttle dropped below
+    /// the valid range and no RC frame arrived for CRSF_FAILSAFE_TIMEOUT_MS.
+    pub fn is_failsafe(&self) -> bool {
+        let ch = &self.last_channels.channels;
+        let zeros = ch.iter().filter(|&&c| c == 0).count();
+        if zeros >= 8 {
+            return true;
+        }
+        ch[2] < CRSF_CHANNEL_MIN
+            && self.now_ms.wrapping_sub(self.last_frame_ms) > CRSF_FAILSAFE_TIMEOUT_MS
+    }
+
+    /// Share of complete frames dropped for a bad CRC (%)
+    pub fn frame_loss_pct(&self) -> f32 {
+        let total = self.good_frames as u32 + self.crc_errors as u32;
+        if total == 0 {
+            return 0.0;
+        }
+        self.crc_errors as f32 / total as f32 * 100.0
+    }
+
+    fn count_frame(&mut self, crc_ok: bool) {
+        if self.good_frames == u16::MAX || self.crc_errors == u16::MAX {
+            // Keep the ratio, bias it towards recent frames
+            self.good_frames /= 2;
+            self.crc_errors /= 2;
+            self.frame_errors /= 2;
+        }
+        if crc_ok {
+            self.good_frames += 1;
+        } else {
+            self.crc_errors += 1;
         }
     }
 
@@ -32,6 +190,7 @@ impl CrsfParser {
         if self.buffer.len() == 1 {
             // Length byte. Valid range approx 2 to 62.
             if b < 2 || b > 62 {
+                self.frame_errors = self.frame_errors.saturating_add(1);
                 self.buffer.clear(); // Invalid length
                                      // If this byte was sync, maybe we should restart?
                 if b == CRSF_SYNC {
@@ -58,16 +217,34 @@ impl CrsfParser {
             let payload_crc_range = &frame[2..total_size - 1];
             let received_crc = frame[total_size - 1];
 
-            if calc_crc8(payload_crc_range) == received_crc {
+            let crc_ok = calc_crc8(payload_crc_range) == received_crc;
+            self.count_frame(crc_ok);
+            let frame = self.buffer.as_slice();
+
+            if crc_ok {
                 // Valid Frame
                 let type_byte = frame[2];
                 let payload = &frame[3..total_size - 1];
 
                 if type_byte == CRSF_FRAMETYPE_RC_CHANNELS_PACKED && payload.len() == 22 {
                     let channels = parse_channels(payload);
+                    self.last_channels = channels;
                     self.buffer.clear();
                     return Some(channels);
                 }
+
+                if type_byte == CRSF_FRAMETYPE_LINK_STATISTICS {
+                    if let Some(ls) = parse_link_stats(payload) {
+                        self.link_stats = ls;
+                        self.link_stats_count = self.link_stats_count.wrapping_add(1);
+                    }
+                }
+
+                if type_byte == CRSF_FRAMETYPE_DEVICE_INFO {
+                    if let Some(info) = parse_device_info(payload) {
+                        self.device_info = Some(info);
+                    }
+                }
             }
 
             // Done with frame
@@ -77,6 +254,27 @@ impl CrsfParser {
         None
     }
 
+    /// MSP payload of a complete CRSF MSP frame
+    /// ([Sync] [Len] [0x7A|0x7C] [Dest] [Origin] [payload...] [CRC]), CRC checked.
+    #[allow(dead_code)]
+    pub fn extract_msp_payload<'a>(frame: &'a [u8]) -> Option<&'a [u8]> {
+        if frame.len() < 6 {
+            return None;
+        }
+        let total = 2 + frame[1] as usize;
+        if frame.len() < total {
+            return None;
+        }
+        let type_byte = frame[2];
+        if type_byte != CRSF_FRAMETYPE_MSP_REQ && type_byte != CRSF_FRAMETYPE_MSP_WRITE {
+            return None;
+        }
+        if calc_crc8(&frame[2..total - 1]) != frame[total - 1] {
+            return None;
+        }
+        Some(&frame[5..total - 1])
+    }
+
     pub fn push_bytes(&mut self, data: &[u8]) -> Option<RcChannels> {
         let mut last_res = None;
         for &b in data {
@@ -88,7 +286,52 @@ impl CrsfParser {
     }
 }
 
-fn calc_crc8(data: &[u8]) -> u8 {
+/// Stick channels (roll, pitch, throttle, yaw) — the only ones smoothed
+const SMOOTHED_CHANNELS: usize = 4;
+
+/// Per-channel first-order IIR against ELRS frame-to-frame jitter (±5 LSB):
+/// y = alpha·y + (1 − alpha)·x. 0.9 = heavy (laggy), 0.3 = light, 0.0 = bypass.
+/// Only the sticks are filtered: switch channels pass through untouched so a
+/// flip is never seen at an intermediate position (e.g. a 3-position aux).
+pub struct ChannelSmoother {
+    alpha: f32,
+    state: [f32; SMOOTHED_CHANNELS],
+    initialized: bool,
+}
+
+impl ChannelSmoother {
+    pub fn new(alpha: f32) -> Self {
+        Self {
+            alpha: alpha.clamp(0.0, 0.99),
+            state: [0.0; SMOOTHED_CHANNELS],
+            initialized: false,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn set_alpha(&mut self, alpha: f32) {
+        self.alpha = alpha.clamp(0.0, 0.99);
+    }
+
+    pub fn smooth(&mut self, ch: [u16; 16]) -> [u16; 16] {
+        if !self.initialized || self.alpha == 0.0 {
+            // First frame seeds the state (no ramp from 0); bypass tracks the input
+            for (s, &c) in self.state.iter_mut().zip(ch.iter()) {
+                *s = c as f32;
+            }
+            self.initialized = true;
+            return ch;
+        }
+        let mut out = ch;
+        for i in 0..SMOOTHED_CHANNELS {
+            self.state[i] = self.alpha * self.state[i] + (1.0 - self.alpha) * ch[i] as f32;
+            out[i] = (self.state[i] + 0.5) as u16;
+        }
+        out
+    }
+}
+
+pub fn calc_crc8(data: &[u8]) -> u8 {
     let mut crc: u8 = 0;
     for &b in data {
         crc ^= b;
@@ -159,6 +402,7 @@ pub const CRSF_FRAMETYPE_GPS: u8 = 0x02;
 pub const CRSF_FRAMETYPE_BATTERY_SENSOR: u8 = 0x08;
 pub const CRSF_FRAMETYPE_ATTITUDE: u8 = 0x1E;
 pub const CRSF_FRAMETYPE_FLIGHT_MODE: u8 = 0x21;
+pub const CRSF_FRAMETYPE_HEARTBEAT: u8 = 0x0B;
 
 // --- Telemetry Structures ---
 // These are not "parsed" but "constructed"
@@ -197,6 +441,65 @@ pub fn build_telemetry_packet(buf: &mut [u8], frame_type: u8, payload: &[u8]) ->
     2 + len // Total size: Sync(1) + Len(1) + Type(1) + Payload(N) + CRC(1) = 2 + (1 + N + 1) = 4 + N
 }
 
+/// Heartbeat: [Sync] [Len=2] [Type=0x0B] [CRC], no payload.
+/// Keeps ELRS receivers forwarding telemetry to the TX.
+pub fn build_heartbeat_packet(buf: &mut [u8]) -> usize {
+    build_telemetry_packet(buf, CRSF_FRAMETYPE_HEARTBEAT, &[])
+}
+
+/// Device discovery: extended frame [Sync] [Len=4] [Type=0x28] [Dest=0x00] [Origin] [CRC].
+/// Every CRSF device on the bus answers with DEVICE_INFO (0x29).
+#[allow(dead_code)]
+pub fn build_ping_packet(buf: &mut [u8], src_addr: u8) -> usize {
+    build_telemetry_packet(buf, CRSF_FRAMETYPE_DEVICE_PING, &[CRSF_ADDRESS_BROADCAST, src_addr])
+}
+
+// --- MSP over CRSF ---
+// CRSF MSP payload: [status] [MSP size] [MSP cmd] [data...] [xor checksum]
+// status: bits 0-3 sequence, bit 4 start of packet, bits 5-6 MSP version (1)
+
+#[allow(dead_code)]
+pub const MSP_STATUS: u8 = 101;
+#[allow(dead_code)]
+const MSP_CRSF_STATUS_START_V1: u8 = 0x30;
+
+/// Wrap an MSP payload in an extended CRSF frame, type 0x7A, FC → radio.
+#[allow(dead_code)]
+pub fn build_msp_frame(buf: &mut [u8], msp_payload: &[u8]) -> usize {
+    build_msp_frame_typed(buf, CRSF_FRAMETYPE_MSP_REQ, msp_payload)
+}
+
+fn build_msp_frame_typed(buf: &mut [u8], frame_type: u8, msp_payload: &[u8]) -> usize {
+    let mut payload: heapless::Vec<u8, 60> = heapless::Vec::new();
+    if payload.push(CRSF_ADDRESS_RADIO_TRANSMITTER).is_err()
+        || payload.push(CRSF_ADDRESS_FLIGHT_CONTROLLER).is_err()
+        || payload.extend_from_slice(msp_payload).is_err()
+    {
+        return 0;
+    }
+    build_telemetry_packet(buf, frame_type, &payload)
+}
+
+/// MSP_STATUS reply (single chunk, MSP v1) so a Betaflight-compatible ground
+/// station sees the FC as armed / disarmed. `seq` = sequence of the request.
+#[allow(dead_code)]
+pub fn build_msp_status_response(buf: &mut [u8], seq: u8, armed: bool, cycle_time_us: u16) -> usize {
+    let mut data = [0u8; 11];
+    data[0..2].copy_from_slice(&cycle_time_us.to_le_bytes());
+    // [2..4] i2c errors, [4..6] sensors: left at 0
+    let mode_flags: u32 = if armed { 1 } else { 0 }; // bit 0 = ARM
+    data[6..10].copy_from_slice(&mode_flags.to_le_bytes());
+    // [10] profile 0
+
+    let mut msp = [0u8; 15];
+    msp[0] = MSP_CRSF_STATUS_START_V1 | (seq & 0x0F);
+    msp[1] = data.len() as u8;
+    msp[2] = MSP_STATUS;
+    msp[3..14].copy_from_slice(&data);
+    msp[14] = msp[1..14].iter().fold(0u8, |c, &b| c ^ b);
+    build_msp_frame_typed(buf, CRSF_FRAMETYPE_MSP_RESP, &msp)
+}
+
 pub fn payload_flight_mode(mode: &str) -> heapless::Vec<u8, 64> {
     let mut buf = heapless::Vec::new();
     // Flight mode is just a null-terminated string
@@ -273,6 +576,18 @@ pub fn payload_battery(
     buf
 }
 
+/// Motor RPM (non-standard id, decoded by a transmitter Lua script — see llm.txt §10.2)
+pub const CRSF_FRAMETYPE_RPM: u8 = 0x30;
+
+/// Up to 4 motor RPM values, u16 big-endian each
+pub fn payload_rpm(rpm: [u16; 4]) -> [u8; 8] {
+    let mut buf = [0u8; 8];
+    for (i, r) in rpm.iter().enumerate() {
+        buf[2 * i..2 * i + 2].copy_from_slice(&r.to_be_bytes());
+    }
+    buf
+}
+
 pub const CRSF_FRAMETYPE_VARIO: u8 = 0x09; // Baro Altitude + Vario
 pub const CRSF_FRAMETYPE_BAROMETRIC_SENSORS: u8 = 0x11; // Pressure + Temp
 
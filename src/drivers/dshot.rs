@@ -1,17 +1,96 @@
 use cortex_m::asm;
-use embassy_stm32::gpio::{AnyPin, Level, Output, Speed};
+use embassy_time::{Duration, Instant, Timer};
+use cortex_m::peripheral::DWT;
+use embassy_stm32::gpio::{AnyPin, Flex, Level, Output, Pull, Speed};
+use embassy_stm32::timer::simple_pwm::SimplePwm;
+use embassy_stm32::timer::{CaptureCompare16bitInstance, Channel, UpDma};
+use embassy_stm32::{into_ref, Peripheral, PeripheralRef};
 
-pub struct Dshot300 {
+/// Master ESC safety switch: while true no task may send throttle or special
+/// commands to the ESCs (only 0 / disarm frames go out).
+pub const ESC_OUTPUT_LOCKED: bool = true;
+
+/// Special commands (values 0-47 of the 11-bit field, sent with the telemetry bit set).
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum DshotCommand {
+    Disarm = 0,
+    Beep1 = 1,
+    Beep2 = 2,
+    Beep3 = 3,
+    Beep4 = 4,
+    Beep5 = 5,
+    Enable3D = 10,
+    Disable3D = 11,
+    SaveSettings = 12,
+    MotorDirectionNormal = 20,
+    MotorDirectionReversed = 21,
+}
+
+/// ESCs only accept a special command after 6+ identical frames
+const SPECIAL_COMMAND_REPEAT: u8 = 10;
+
+/// Anything that can put a DShot frame on a motor wire (bit-bang or timer + DMA).
+#[allow(async_fn_in_trait)]
+pub trait DshotOutput: Send + 'static {
+    async fn send_command(&mut self, command_11bit: u16, telemetry: bool);
+}
+
+/// DShot bit rate. Cycle counts below assume SYSCLK = 168 MHz.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DshotSpeed {
+    Dshot300,
+    Dshot600,
+    Dshot1200,
+}
+
+#[allow(dead_code)]
+impl DshotSpeed {
+    pub const fn kbaud(self) -> u32 {
+        match self {
+            DshotSpeed::Dshot300 => 300,
+            DshotSpeed::Dshot600 => 600,
+            DshotSpeed::Dshot1200 => 1200,
+        }
+    }
+
+    /// Frame duration (16 bits) in µs
+    pub const fn frame_us(self) -> u32 {
+        16_000 / self.kbaud()
+    }
+}
+
+/// Bit-banged DShot, timing fixed at compile time (CPU cycles per bit / high times).
+pub struct DshotN<const TOTAL: u32, const HI1: u32, const HI0: u32, const GAP: u32> {
     pin: Output<'static, AnyPin>,
 }
 
-impl Dshot300 {
-    const BIT_TOTAL_CYCLES: u32 = 560;
-    const BIT1_HIGH_CYCLES: u32 = 420;
+/// 3.33 µs per bit, ~53 µs per frame
+pub type Dshot300 = DshotN<560, 420, 210, 5200>;
+/// 1.67 µs per bit, ~27 µs per frame
+#[allow(dead_code)]
+pub type Dshot600 = DshotN<280, 210, 105, 2600>;
+/// 0.83 µs per bit, ~13 µs per frame
+#[allow(dead_code)]
+pub type Dshot1200 = DshotN<140, 105, 52, 1300>;
+
+impl<const TOTAL: u32, const HI1: u32, const HI0: u32, const GAP: u32> DshotN<TOTAL, HI1, HI0, GAP> {
+    const BIT_TOTAL_CYCLES: u32 = TOTAL;
+    const BIT1_HIGH_CYCLES: u32 = HI1;
     const BIT1_LOW_CYCLES: u32 = Self::BIT_TOTAL_CYCLES - Self::BIT1_HIGH_CYCLES;
-    const BIT0_HIGH_CYCLES: u32 = 210;
+    const BIT0_HIGH_CYCLES: u32 = HI0;
     const BIT0_LOW_CYCLES: u32 = Self::BIT_TOTAL_CYCLES - Self::BIT0_HIGH_CYCLES;
-    const FRAME_GAP_CYCLES: u32 = 5200;
+    const FRAME_GAP_CYCLES: u32 = GAP;
+
+    #[allow(dead_code)]
+    pub const SPEED: DshotSpeed = if TOTAL >= 560 {
+        DshotSpeed::Dshot300
+    } else if TOTAL >= 280 {
+        DshotSpeed::Dshot600
+    } else {
+        DshotSpeed::Dshot1200
+    };
 
     pub fn new(pin: AnyPin) -> Self {
         Self {
@@ -24,13 +103,27 @@ impl Dshot300 {
         self.send_frame(frame);
     }
 
-    pub fn send_frame(&mut self, frame: u16) {
-        // Disable interrupts during bit-bang to prevent timing corruption
-        // from UART/I2C/USB ISRs (~60µs critical window)
-        critical_section::with(|_cs| {
-            for bit in (0..16).rev() {
-                let one = ((frame >> bit) & 0x1) != 0;
+    /// Send `cmd` SPECIAL_COMMAND_REPEAT times. Refused (returns false) while
+    /// ESC_OUTPUT_LOCKED is set.
+    #[allow(dead_code)]
+    pub fn send_special_command(&mut self, cmd: DshotCommand) -> bool {
+        if ESC_OUTPUT_LOCKED {
+            return false;
+        }
+        for _ in 0..SPECIAL_COMMAND_REPEAT {
+            self.send_command(cmd as u16, true);
+        }
+        true
+    }
 
+    pub fn send_frame(&mut self, frame: u16) {
+        // Interrupts are masked one bit at a time (≤ 3.3 µs at DShot300), not
+        // for the whole frame: the high time, which carries the bit value, is
+        // never stretched; a pending UART/I2C/USB ISR runs between two bits and
+        // only lengthens the low phase, which ESCs tolerate.
+        for bit in (0..16).rev() {
+            let one = ((frame >> bit) & 0x1) != 0;
+            critical_section::with(|_cs| {
                 self.pin.set_high();
                 if one {
                     asm::delay(Self::BIT1_HIGH_CYCLES);
@@ -41,11 +134,125 @@ impl Dshot300 {
                     self.pin.set_low();
                     asm::delay(Self::BIT0_LOW_CYCLES);
                 }
-            }
+            });
+        }
 
-            self.pin.set_low();
-            asm::delay(Self::FRAME_GAP_CYCLES);
-        });
+        // Inter-frame gap: line idle low, interrupts enabled
+        self.pin.set_low();
+        asm::delay(Self::FRAME_GAP_CYCLES);
+    }
+}
+
+impl<const TOTAL: u32, const HI1: u32, const HI0: u32, const GAP: u32> DshotOutput
+    for DshotN<TOTAL, HI1, HI0, GAP>
+{
+    async fn send_command(&mut self, command_11bit: u16, telemetry: bool) {
+        DshotN::send_command(self, command_11bit, telemetry);
+    }
+}
+
+// ── Timer + DMA DShot ─────────────────────────────────────────────────────────
+
+/// PWM carrier for DShot300 — one timer period per bit
+#[allow(dead_code)]
+pub const DSHOT300_PWM_HZ: u32 = 300_000;
+
+/// DShot generated by a timer channel in PWM mode: the 16 compare values of a frame
+/// are pushed by the timer update DMA, one per period. No critical section, the CPU
+/// is free while the frame goes out.
+///
+/// The SimplePwm must be created at DSHOT300_PWM_HZ (or 600/1200 kHz) with `channel`
+/// enabled. Not used for the tab motor yet: on PB0 (TIM3_CH3) the TIM3_UP stream
+/// collides with UART4 RX on DMA1 stream 2.
+#[allow(dead_code)]
+pub struct DshotTimerOutput<'d, T: CaptureCompare16bitInstance, D: UpDma<T>> {
+    pwm: SimplePwm<'d, T>,
+    channel: Channel,
+    dma: PeripheralRef<'d, D>,
+    /// 16 bits + 2 idle slots so the line ends low
+    duty: [u16; 18],
+    bit1_duty: u16,
+    bit0_duty: u16,
+}
+
+#[allow(dead_code)]
+impl<'d, T: CaptureCompare16bitInstance, D: UpDma<T>> DshotTimerOutput<'d, T, D> {
+    pub fn new(pwm: SimplePwm<'d, T>, channel: Channel, dma: impl Peripheral<P = D> + 'd) -> Self {
+        into_ref!(dma);
+        let max = pwm.get_max_duty();
+        Self {
+            pwm,
+            channel,
+            dma,
+            duty: [0; 18],
+            bit1_duty: (max as u32 * 3 / 4) as u16,  // 75 % high
+            bit0_duty: (max as u32 * 3 / 8) as u16,  // 37.5 % high
+        }
+    }
+
+    pub async fn send_command(&mut self, command_11bit: u16, telemetry: bool) {
+        let frame = dshot_frame(command_11bit, telemetry);
+        for (i, bit) in (0..16).rev().enumerate() {
+            let one = ((frame >> bit) & 0x1) != 0;
+            self.duty[i] = if one { self.bit1_duty } else { self.bit0_duty };
+        }
+        self.duty[16] = 0;
+        self.duty[17] = 0;
+        self.pwm.gen_waveform(self.dma.reborrow(), self.channel, &self.duty).await;
+    }
+}
+
+impl<T: CaptureCompare16bitInstance, D: UpDma<T>> DshotOutput for DshotTimerOutput<'static, T, D>
+where
+    Self: Send,
+{
+    async fn send_command(&mut self, command_11bit: u16, telemetry: bool) {
+        DshotTimerOutput::send_command(self, command_11bit, telemetry).await;
+    }
+}
+
+// ── Four-motor manager ────────────────────────────────────────────────────────
+
+/// Motor slots (MOTOR1..MOTOR4 resources: PB0, PB1, PA3, PA2)
+pub const MOTOR_COUNT: usize = 4;
+pub const MOTOR_TAB: usize = 0;
+pub const MOTOR_MAIN: usize = 1;
+
+/// Standard ESC arming: zero throttle for this long
+const ARM_DURATION_MS: u64 = 500;
+/// Frame period while arming (1 kHz, same as the run loop)
+const ARM_FRAME_PERIOD_US: u64 = 1000;
+
+pub struct DshotQuad<D: DshotOutput> {
+    motors: [D; MOTOR_COUNT],
+}
+
+impl<D: DshotOutput> DshotQuad<D> {
+    pub fn new(motors: [D; MOTOR_COUNT]) -> Self {
+        Self { motors }
+    }
+
+    /// One frame to each motor, in slot order
+    pub async fn send_all(&mut self, cmds: [u16; MOTOR_COUNT]) {
+        for (motor, cmd) in self.motors.iter_mut().zip(cmds) {
+            motor.send_command(cmd, false).await;
+        }
+    }
+
+    #[allow(dead_code)]
+    pub async fn send_motor(&mut self, idx: usize, cmd: u16) {
+        if let Some(motor) = self.motors.get_mut(idx) {
+            motor.send_command(cmd, false).await;
+        }
+    }
+
+    /// Zero throttle on every output for ARM_DURATION_MS so the ESCs arm.
+    pub async fn arm_all(&mut self) {
+        let end = Instant::now() + Duration::from_millis(ARM_DURATION_MS);
+        while Instant::now() < end {
+            self.send_all([0; MOTOR_COUNT]).await;
+            Timer::after(Duration::from_micros(ARM_FRAME_PERIOD_US)).await;
+        }
     }
 }
 
@@ -65,3 +272,142 @@ pub fn dshot_frame(command: u16, telemetry: bool) -> u16 {
 
     (packet << 4) | csum
 }
+
+// ── Bidirectional DShot300 ────────────────────────────────────────────────────
+
+/// Bidirectional DShot300: inverted frames (idle high, open-drain + pull-up), the ESC
+/// answers ~30 µs later on the same wire with a 21-bit GCR eRPM frame at 375 kbaud.
+/// Send and capture share one critical section (~140 µs).
+#[allow(dead_code)]
+pub struct Dshot300Bidir {
+    pin: Flex<'static, AnyPin>,
+    last_raw: Option<u32>,
+}
+
+#[allow(dead_code)]
+impl Dshot300Bidir {
+    const BIT_TOTAL_CYCLES: u32 = 560;
+    const BIT1_ACTIVE_CYCLES: u32 = 420;
+    const BIT0_ACTIVE_CYCLES: u32 = 210;
+    /// 5/4 × 300 kbaud = 375 kbaud → 448 cycles per telemetry bit
+    const TLM_BIT_CYCLES: u32 = 448;
+    const TLM_BITS: u32 = 21;
+    /// Give up if the ESC has not pulled the line low within 60 µs
+    const TLM_WAIT_CYCLES: u32 = 10_080;
+
+    pub fn new(pin: AnyPin) -> Self {
+        let mut pin = Flex::new(pin);
+        pin.set_high();
+        pin.set_as_input_output(Speed::VeryHigh, Pull::Up);
+
+        // DWT cycle counter times the telemetry sampling
+        let mut cp = unsafe { cortex_m::Peripherals::steal() };
+        cp.DCB.enable_trace();
+        cp.DWT.enable_cycle_counter();
+
+        Self { pin, last_raw: None }
+    }
+
+    /// Send one frame and capture the ESC reply (decoded by `read_telemetry`).
+    pub fn send_command(&mut self, command_11bit: u16) {
+        let frame = dshot_frame_bidir(command_11bit, false);
+        self.last_raw = critical_section::with(|_cs| {
+            for bit in (0..16).rev() {
+                let one = ((frame >> bit) & 0x1) != 0;
+                let active = if one { Self::BIT1_ACTIVE_CYCLES } else { Self::BIT0_ACTIVE_CYCLES };
+
+                self.pin.set_low();
+                asm::delay(active);
+                self.pin.set_high();
+                asm::delay(Self::BIT_TOTAL_CYCLES - active);
+            }
+            // Line released (open-drain): the ESC now drives it
+            self.capture_reply()
+        });
+    }
+
+    fn capture_reply(&mut self) -> Option<u32> {
+        let start = DWT::cycle_count();
+        while self.pin.is_high() {
+            if DWT::cycle_count().wrapping_sub(start) > Self::TLM_WAIT_CYCLES {
+                return None;
+            }
+        }
+
+        // Sample each bit in its middle
+        let t0 = DWT::cycle_count().wrapping_add(Self::TLM_BIT_CYCLES / 2);
+        let mut raw = 0u32;
+        for i in 0..Self::TLM_BITS {
+            let t = t0.wrapping_add(i * Self::TLM_BIT_CYCLES);
+            while (DWT::cycle_count().wrapping_sub(t) as i32) < 0 {}
+            raw = (raw << 1) | self.pin.is_high() as u32;
+        }
+        Some(raw)
+    }
+
+    /// eRPM from the reply to the last `send_command`, None if missing / corrupt
+    /// or if the ESC sent an extended telemetry (EDT) frame instead.
+    pub fn read_telemetry(&mut self) -> Option<u32> {
+        decode_bidir_telemetry(self.last_raw.take()?)
+    }
+}
+
+/// Same as `dshot_frame` but with the inverted checksum bidirectional ESCs expect.
+#[allow(dead_code)]
+pub fn dshot_frame_bidir(command: u16, telemetry: bool) -> u16 {
+    let frame = dshot_frame(command, telemetry);
+    frame ^ 0x000f
+}
+
+fn gcr_to_nibble(gcr: u32) -> Option<u16> {
+    let n = match gcr {
+        0x19 => 0x0,
+        0x1B => 0x1,
+        0x12 => 0x2,
+        0x13 => 0x3,
+        0x1D => 0x4,
+        0x15 => 0x5,
+        0x16 => 0x6,
+        0x17 => 0x7,
+        0x1A => 0x8,
+        0x09 => 0x9,
+        0x0A => 0xA,
+        0x0B => 0xB,
+        0x1E => 0xC,
+        0x0D => 0xD,
+        0x0E => 0xE,
+        0x0F => 0xF,
+        _ => return None,
+    };
+    Some(n)
+}
+
+/// 21 sampled levels → GCR (transition = 1) → 16 bits [eee mmmmmmmmm][crc4].
+/// Returns eRPM (0 when the motor is stopped).
+#[allow(dead_code)]
+pub fn decode_bidir_telemetry(raw: u32) -> Option<u32> {
+    let gcr = (raw ^ (raw >> 1)) & 0xF_FFFF;
+
+    let mut value: u16 = 0;
+    for i in (0..4).rev() {
+        value = (value << 4) | gcr_to_nibble((gcr >> (i * 5)) & 0x1F)?;
+    }
+
+    let csum = value ^ (value >> 4) ^ (value >> 8) ^ (value >> 12);
+    if csum & 0x0f != 0x0f {
+        return None;
+    }
+
+    let edt = value >> 4;
+    if edt & 0x100 == 0 && edt & 0xE00 != 0 {
+        return None; // extended telemetry (temperature, voltage…), not eRPM
+    }
+    if edt == 0xFFF {
+        return Some(0);
+    }
+    let period_us = ((edt & 0x1FF) as u32) << (edt >> 9);
+    if period_us == 0 {
+        return None;
+    }
+    Some(60_000_000 / period_us)
+}
@@ -12,18 +12,38 @@ use micromath::F32Ext;
 
 // ── Constants ────────────────────────────────────────────────────────────────
 
-/// Process noise for quaternion integration (very small - gyro is trusted)
+/// Process noise for quaternion integration (very small - gyro is trusted);
+/// default until `set_noise` applies the bin/calibrate value
 const Q_QUAT: f32 = 1e-6;
-/// Process noise for gyro bias drift
+/// Process noise for gyro bias drift (default, see `set_noise`)
 const Q_GBIAS: f32 = 1e-7;
 /// Process noise for accel bias drift
 const Q_ABIAS: f32 = 1e-7;
 
 /// Measurement noise for accelerometer under normal flight (< 1.5G total)
+/// (default, see `set_noise`)
 const R_ACCEL_NORMAL: f32 = 0.05;
 /// Measurement noise when high-G detected (rocket burn / high thrust): EKF trusts only gyro
 const R_ACCEL_HIGH_G: f32 = 500.0;
 
+/// Measurement noise for the normalised magnetometer (less trusted than accel)
+const R_MAG: f32 = 0.2;
+/// Magnetometer noise under high-G: motor current / vibration distort the field
+const R_MAG_HIGH_G: f32 = 5.0;
+/// Magnetometer noise while ESC switching interference is detected (throttle-correlated)
+const R_MAG_INTERFERENCE: f32 = 5.0;
+/// Zero-velocity update: |gyro| below this (rad/s) counts as stationary
+const ZUPT_THRESHOLD: f32 = 0.01;
+/// Gyro-bias observation noise for ZUPT ((rad/s)²) — 5 s at 1 kHz brings
+/// the bias σ to ~1e-5 rad/s
+const R_ZUPT: f32 = 1e-6;
+
+/// Mag noise when |m| is off the expected field (update effectively disabled)
+const R_MAG_DISTURBED: f32 = 1e6;
+/// Default |m| tolerance for update_mag_gated (µT): earth field ~50 µT,
+/// motor/ESC disturbance often 100+ µT
+pub const MAG_FIELD_TOLERANCE_UT: f32 = 50.0;
+
 /// Threshold in G above which we boost accelerometer noise
 const HIGH_G_THRESHOLD: f32 = 1.5; // G (includes gravity = ~1G at rest, so ~0.5G net accel)
 
@@ -32,14 +52,58 @@ const P0_QUAT: f32 = 0.01;
 /// Initial covariance diagonal for bias states
 const P0_BIAS: f32 = 0.1;
 
+/// Health: trace(P) above this means the filter has diverged
+const P_TRACE_MAX: f32 = 10.0;
+/// Health: quaternion norm below this means the state is corrupt
+const QUAT_NORM_MIN: f32 = 0.99;
+
 // ── Data types ───────────────────────────────────────────────────────────────
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default)]
 pub struct EkfDebug {
     pub is_high_g: bool,
     pub accel_mag_g: f32,
 }
 
+impl core::fmt::Display for EkfDebug {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "hg={} |a|={:.2}G", self.is_high_g as u8, self.accel_mag_g)
+    }
+}
+
+impl EkfDebug {
+    #[allow(dead_code)]
+    pub fn to_heapless_string(&self) -> heapless::String<64> {
+        use core::fmt::Write;
+        let mut s = heapless::String::new();
+        let _ = write!(s, "{}", self);
+        s
+    }
+}
+
+/// Per-block covariance traces: attitude, gyro bias, accel bias
+#[derive(Clone, Copy, Default)]
+pub struct EkfCovarDiag {
+    pub q: f32,
+    pub gb: f32,
+    pub ab: f32,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub struct EkfHealth {
+    pub is_healthy: bool,
+    pub p_trace: f32,
+    pub quat_norm: f32,
+}
+
+impl EkfHealth {
+    /// trace(P) / N — mean state variance, comparable across filter sizes
+    pub fn p_trace_per_state(&self) -> f32 {
+        self.p_trace / N as f32
+    }
+}
+
 // ── Helper matrix functions (10×10 flat arrays) ──────────────────────────────
 
 const N: usize = 10;
@@ -124,13 +188,45 @@ pub struct AttitudeEkf {
     p: Mat,
     /// Debug info from last update
     pub debug: EkfDebug,
+    /// Set from Hmc5883::detect_interference — inflates R_MAG
+    mag_interference: bool,
+    /// Sensor noise (Q_QUAT, Q_GBIAS, R_ACCEL_NORMAL unless `set_noise`)
+    q_quat: f32,
+    q_gbias: f32,
+    r_accel_normal: f32,
 }
 
 impl AttitudeEkf {
     pub fn new() -> Self {
+        Self {
+            x: Self::x0(),
+            p: Self::p0(),
+            debug: EkfDebug { is_high_g: false, accel_mag_g: 1.0 },
+            mag_interference: false,
+            q_quat: Q_QUAT,
+            q_gbias: Q_GBIAS,
+            r_accel_normal: R_ACCEL_NORMAL,
+        }
+    }
+
+    /// Noise figures measured on this board by bin/calibrate (src/calibration.rs)
+    pub fn set_noise(&mut self, q_quat: f32, q_gbias: f32, r_accel_normal: f32) {
+        self.q_quat = q_quat;
+        self.q_gbias = q_gbias;
+        self.r_accel_normal = r_accel_normal;
+    }
+
+    pub fn set_mag_interference(&mut self, on: bool) {
+        self.mag_interference = on;
+    }
+
+    fn x0() -> Vec10 {
         let mut x = [0.0f32; N];
         x[0] = 1.0; // q0 = 1 (identity quaternion)
+        x
+    }
 
+    fn p0() -> Mat {
         let mut p = mat_identity();
         for i in 0..4 {
             mset(&mut p, i, i, P0_QUAT);
@@ -138,11 +234,81 @@ impl AttitudeEkf {
         for i in 4..N {
             mset(&mut p, i, i, P0_BIAS);
         }
+        p
+    }
 
-        Self {
-            x,
-            p,
-            debug: EkfDebug { is_high_g: false, accel_mag_g: 1.0 },
+    /// Back to identity quaternion, zero biases and initial covariance P0
+    pub fn reset(&mut self) {
+        self.x = Self::x0();
+        self.p = Self::p0();
+    }
+
+    /// Static alignment: roll/pitch from a (normalised) gravity reading, yaw = 0,
+    /// same math as `Mahony::reset_to_accel`. Biases are kept, P is reset to P0.
+    /// Any accel unit works.
+    pub fn initialize_from_accel(&mut self, ax: f32, ay: f32, az: f32) {
+        let norm = (ax*ax + ay*ay + az*az).sqrt();
+        if norm < 1e-6 { return; }
+        let (ax, ay, az) = (ax / norm, ay / norm, az / norm);
+        let roll = ay.atan2(az);
+        let pitch = (-ax).atan2((ay*ay + az*az).sqrt());
+
+        let (sr, cr) = ((roll * 0.5).sin(), (roll * 0.5).cos());
+        let (sp, cp) = ((pitch * 0.5).sin(), (pitch * 0.5).cos());
+        self.x[0] = cr * cp;
+        self.x[1] = sr * cp;
+        self.x[2] = cr * sp;
+        self.x[3] = -sr * sp;
+
+        self.p = Self::p0(); // P0_QUAT on the quaternion diagonal
+    }
+
+    /// Divergence check: trace(P), quaternion norm and NaN in the quaternion
+    pub fn health_check(&self) -> EkfHealth {
+        let p_trace = self.p_trace();
+        let q = self.get_quaternion();
+        let quat_norm = (q[0]*q[0] + q[1]*q[1] + q[2]*q[2] + q[3]*q[3]).sqrt();
+        let has_nan = q.iter().any(|v| v.is_nan()) || p_trace.is_nan();
+
+        EkfHealth {
+            is_healthy: !has_nan && p_trace <= P_TRACE_MAX && quat_norm >= QUAT_NORM_MIN,
+            p_trace,
+            quat_norm,
+        }
+    }
+
+    // ── Covariance diagnostics ───────────────────────────────────────────────
+
+    fn diag_sum(&self, from: usize, to: usize) -> f32 {
+        (from..to).map(|i| m(&self.p, i, i)).sum()
+    }
+
+    /// trace(P)
+    pub fn p_trace(&self) -> f32 {
+        self.diag_sum(0, N)
+    }
+
+    /// Attitude (quaternion) uncertainty, P[0..4] diagonal
+    pub fn p_quat_trace(&self) -> f32 {
+        self.diag_sum(0, 4)
+    }
+
+    /// Gyro bias uncertainty, P[4..7] diagonal
+    pub fn p_gbias_trace(&self) -> f32 {
+        self.diag_sum(4, 7)
+    }
+
+    /// Accel bias uncertainty, P[7..10] diagonal
+    pub fn p_abias_trace(&self) -> f32 {
+        self.diag_sum(7, N)
+    }
+
+    /// All three block traces — each should fall from its P0 value as the filter converges
+    pub fn covar_diag(&self) -> EkfCovarDiag {
+        EkfCovarDiag {
+            q: self.p_quat_trace(),
+            gb: self.p_gbias_trace(),
+            ab: self.p_abias_trace(),
         }
     }
 
@@ -247,8 +413,8 @@ impl AttitudeEkf {
 
         // Build process noise Q
         let mut q_noise = mat_zero();
-        for i in 0..4 { mset(&mut q_noise, i, i, Q_QUAT * dt); }
-        for i in 4..7 { mset(&mut q_noise, i, i, Q_GBIAS * dt); }
+        for i in 0..4 { mset(&mut q_noise, i, i, self.q_quat * dt); }
+        for i in 4..7 { mset(&mut q_noise, i, i, self.q_gbias * dt); }
         for i in 7..10 { mset(&mut q_noise, i, i, Q_ABIAS * dt); }
 
         // P = F*P*F' + Q
@@ -273,7 +439,7 @@ impl AttitudeEkf {
             R_ACCEL_HIGH_G
         } else {
             self.debug.is_high_g = false;
-            R_ACCEL_NORMAL
+            self.r_accel_normal
         };
 
         // Normalise accelerometer (pointing towards real gravity direction)
@@ -309,6 +475,107 @@ impl AttitudeEkf {
         // Row 2 → hz
         h_jac[2*N+0] =  2.*q0; h_jac[2*N+1] = -2.*q1; h_jac[2*N+2] = -2.*q2; h_jac[2*N+3] =  2.*q3;
 
+        self.correct3(&h_jac, [y0, y1, y2], r_accel);
+    }
+
+    // ── Update step (magnetometer) ───────────────────────────────────────────
+
+    /// Correct state with a magnetometer measurement (any unit, normalised here).
+    /// `mag_ref` is the expected unit field vector in the earth frame, in the same
+    /// z-up convention as the accel model (include the local inclination, otherwise
+    /// the mag fights the accel on roll/pitch).
+    pub fn update_mag(&mut self, mx: f32, my: f32, mz: f32, mag_ref: [f32; 3]) {
+        let r_mag = if self.debug.is_high_g {
+            R_MAG_HIGH_G
+        } else if self.mag_interference {
+            R_MAG_INTERFERENCE
+        } else {
+            R_MAG
+        };
+        self.update_mag_r(mx, my, mz, mag_ref, r_mag);
+    }
+
+    /// `update_mag` with a field-magnitude gate: `mx/my/mz` in µT, and when
+    /// ||m| − expected_field_uT| > tolerance (motor burn, ESC currents) the
+    /// update runs with R_MAG_DISTURBED, i.e. yaw is left to the gyro.
+    /// Returns false when the sample was gated out.
+    pub fn update_mag_gated(
+        &mut self,
+        mx: f32,
+        my: f32,
+        mz: f32,
+        expected_field_ut: f32,
+        tolerance: f32,
+        mag_ref: [f32; 3],
+    ) -> bool {
+        let norm = (mx*mx + my*my + mz*mz).sqrt();
+        if (norm - expected_field_ut).abs() > tolerance {
+            self.update_mag_r(mx, my, mz, mag_ref, R_MAG_DISTURBED);
+            return false;
+        }
+        self.update_mag(mx, my, mz, mag_ref);
+        true
+    }
+
+    fn update_mag_r(&mut self, mx: f32, my: f32, mz: f32, mag_ref: [f32; 3], r_mag: f32) {
+        let norm = (mx*mx + my*my + mz*mz).sqrt();
+        if norm < 1e-6 { return; }
+        let recip = norm.recip();
+        let (mx_n, my_n, mz_n) = (mx * recip, my * recip, mz * recip);
+
+        let q0 = self.x[0]; let q1 = self.x[1];
+        let q2 = self.x[2]; let q3 = self.x[3];
+        let [rx, ry, rz] = mag_ref;
+
+        // Expected field in body frame: h = R^T * mag_ref
+        let hx = (q0*q0 + q1*q1 - q2*q2 - q3*q3)*rx + 2.*(q1*q2 + q0*q3)*ry + 2.*(q1*q3 - q0*q2)*rz;
+        let hy = 2.*(q1*q2 - q0*q3)*rx + (q0*q0 - q1*q1 + q2*q2 - q3*q3)*ry + 2.*(q2*q3 + q0*q1)*rz;
+        let hz = 2.*(q1*q3 + q0*q2)*rx + 2.*(q2*q3 - q0*q1)*ry + (q0*q0 - q1*q1 - q2*q2 + q3*q3)*rz;
+
+        // Jacobian H (3×10), quaternion columns only
+        let mut h_jac = [0.0f32; 3 * N];
+        h_jac[0*N+0] =  2.*( q0*rx + q3*ry - q2*rz);
+        h_jac[0*N+1] =  2.*( q1*rx + q2*ry + q3*rz);
+        h_jac[0*N+2] =  2.*(-q2*rx + q1*ry - q0*rz);
+        h_jac[0*N+3] =  2.*(-q3*rx + q0*ry + q1*rz);
+        h_jac[1*N+0] =  2.*(-q3*rx + q0*ry + q1*rz);
+        h_jac[1*N+1] =  2.*( q2*rx - q1*ry + q0*rz);
+        h_jac[1*N+2] =  2.*( q1*rx + q2*ry + q3*rz);
+        h_jac[1*N+3] =  2.*(-q0*rx - q3*ry + q2*rz);
+        h_jac[2*N+0] =  2.*( q2*rx - q1*ry + q0*rz);
+        h_jac[2*N+1] =  2.*( q3*rx - q0*ry - q1*rz);
+        h_jac[2*N+2] =  2.*( q0*rx + q3*ry - q2*rz);
+        h_jac[2*N+3] =  2.*( q1*rx + q2*ry + q3*rz);
+
+        self.correct3(&h_jac, [mx_n - hx, my_n - hy, mz_n - hz], r_mag);
+    }
+
+    // ── Zero-velocity update (pad) ───────────────────────────────────────────
+
+    /// When the vehicle is stationary (|gyro| < ZUPT_THRESHOLD), the gyro
+    /// reading is the bias: observe the bias states directly
+    /// (H = [0₃ₓ₄ I₃ 0₃ₓ₃]) to pull down their covariance. Same gyro input as
+    /// `predict`. Returns true if the update was applied.
+    pub fn zupt_update(&mut self, gx: f32, gy: f32, gz: f32) -> bool {
+        if (gx*gx + gy*gy + gz*gz).sqrt() >= ZUPT_THRESHOLD {
+            return false;
+        }
+        let mut h_jac = [0.0f32; 3 * N];
+        for i in 0..3 {
+            h_jac[i*N + 4 + i] = 1.0;
+        }
+        let y = [gx - self.x[4], gy - self.x[5], gz - self.x[6]];
+        self.correct3(&h_jac, y, R_ZUPT);
+        true
+    }
+
+    // ── Shared 3-D measurement correction ────────────────────────────────────
+
+    /// H→S→K→x→P for a 3-component measurement with innovation `y` and
+    /// isotropic noise `r_meas` (R = r_meas·I).
+    fn correct3(&mut self, h_jac: &[f32; 3 * N], y: [f32; 3], r_meas: f32) {
+        let [y0, y1, y2] = y;
+
         // S = H * P * H' + R*I  (3×3)
         // K = P * H' * S^{-1}   (10×3)
         // x = x + K * y
@@ -326,23 +593,11 @@ impl AttitudeEkf {
             }
         }
 
-        // S = H*P*H' + R*I  (3×3)
+        // S = H*P*H' + R*I  (3×3):  S[r,c] = sum_k HP[r,k] * H[c,k]
         let mut s_mat = [0.0f32; 9];
         for r in 0..3 {
             for c in 0..3 {
-                let mut v = if r==c { r_accel } else { 0.0 };
-                for k in 0..N {
-                    v += h_jac[r*N+k] * hp[c*N+k]; // hp[c] is H[c,:]*P = (HP)[c,:]
-                }
-                // Wait, HP is (HP)[r,c] already. H' is H[k,c]. Need HP * H'
-                s_mat[r*3+c] = s_mat[r*3+c]; // will redo below
-                let _ = v;
-            }
-        }
-        // Redo: S[r,c] = sum_k HP[r,k] * H[c,k]
-        for r in 0..3 {
-            for c in 0..3 {
-                let mut v = if r==c { r_accel } else { 0.0 };
+                let mut v = if r==c { r_meas } else { 0.0 };
                 for k in 0..N {
                     v += hp[r*N+k] * h_jac[c*N+k];
                 }
@@ -389,18 +644,49 @@ impl AttitudeEkf {
 
         // Covariance update: P = (I - K*H)*P = P - K*H*P = P - K*(HP)
         // K*HP (10×10)
-        let mut khp = mat_zero();
-        for r in 0..N {
-            for c in 0..N {
-                let mut v = 0.0f32;
-                for k in 0..3 {
-                    v += kk[r*3+k] * hp[k*N+c];
+        #[cfg(not(feature = "joseph_form"))]
+        {
+            let mut khp = mat_zero();
+            for r in 0..N {
+                for c in 0..N {
+                    let mut v = 0.0f32;
+                    for k in 0..3 {
+                        v += kk[r*3+k] * hp[k*N+c];
+                    }
+                    mset(&mut khp, r, c, v);
                 }
-                mset(&mut khp, r, c, v);
+            }
+            for i in 0..N*N {
+                self.p[i] -= khp[i];
             }
         }
-        for i in 0..N*N {
-            self.p[i] -= khp[i];
+
+        // Joseph form: P = (I-KH)*P*(I-KH)' + K*R*K'
+        // Symmetric and PSD for any K, one extra 10×10 multiply
+        #[cfg(feature = "joseph_form")]
+        {
+            let mut ikh = mat_identity();
+            for r in 0..N {
+                for c in 0..N {
+                    let mut v = 0.0f32;
+                    for k in 0..3 {
+                        v += kk[r*3+k] * h_jac[k*N+c];
+                    }
+                    ikh[r*N+c] -= v;
+                }
+            }
+            let ikh_p = mat_mul(&ikh, &self.p);
+            let mut p_new = mat_mul_t(&ikh_p, &ikh);
+            for r in 0..N {
+                for c in 0..N {
+                    let mut v = 0.0f32;
+                    for k in 0..3 {
+                        v += kk[r*3+k] * kk[c*3+k];
+                    }
+                    p_new[r*N+c] += r_meas * v;
+                }
+            }
+            self.p = p_new;
         }
 
         // Normalise quaternion after update
@@ -87,6 +87,18 @@ impl BiquadFilter {
         output
     }
 
+    /// Filter a whole buffer (e.g. an IMU FIFO burst). Processes
+    /// `min(input.len(), output.len())` samples; the rest of `output` is untouched.
+    /// Inlined so the loop can be unrolled at the call site — needs LTO when
+    /// the caller lives in another crate/codegen unit.
+    #[allow(dead_code)]
+    #[inline(always)]
+    pub fn filter_slice(&mut self, input: &[f32], output: &mut [f32]) {
+        for (y, &x) in output.iter_mut().zip(input.iter()) {
+            *y = self.filter(x);
+        }
+    }
+
     /// Reset filter state (call on re-init or after a gap in data)
     pub fn reset(&mut self) {
         self.z1 = 0.0;
@@ -94,3 +106,271 @@ impl BiquadFilter {
         self.initialized = false;
     }
 }
+
+/// First-order low-pass (PT1): y += k · (x − y)
+pub struct Pt1Filter {
+    k: f32,
+    state: f32,
+    initialized: bool,
+}
+
+impl Pt1Filter {
+    /// - `cutoff_freq` : cutoff frequency in Hz
+    /// - `sample_rate` : sample rate in Hz
+    pub fn new(cutoff_freq: f32, sample_rate: f32) -> Self {
+        let rc = 1.0 / (2.0 * core::f32::consts::PI * cutoff_freq);
+        let dt = 1.0 / sample_rate;
+        Self {
+            k: dt / (rc + dt),
+            state: 0.0,
+            initialized: false,
+        }
+    }
+
+    pub fn filter(&mut self, input: f32) -> f32 {
+        if !self.initialized {
+            self.state = input;
+            self.initialized = true;
+        }
+        self.state += self.k * (input - self.state);
+        self.state
+    }
+
+    pub fn reset(&mut self) {
+        self.state = 0.0;
+        self.initialized = false;
+    }
+}
+
+/// FIR filter, direct form over a ring buffer (linear phase for symmetric taps,
+/// group delay (N − 1) / 2 samples).
+#[allow(dead_code)]
+pub struct FirFilter<const N: usize> {
+    coefficients: [f32; N],
+    buffer: [f32; N],
+    idx: usize,
+}
+
+#[allow(dead_code)]
+impl<const N: usize> FirFilter<N> {
+    pub fn new(coeffs: &[f32; N]) -> Self {
+        Self {
+            coefficients: *coeffs,
+            buffer: [0.0; N],
+            idx: 0,
+        }
+    }
+
+    /// N-tap moving average (all taps 1/N): unity at DC, zero at Nyquist for even N
+    pub fn new_moving_average_n() -> Self {
+        Self::new(&[1.0 / N as f32; N])
+    }
+
+    pub fn filter(&mut self, x: f32) -> f32 {
+        self.buffer[self.idx] = x;
+        // coefficients[0] multiplies the newest sample
+        let mut acc = 0.0f32;
+        let mut j = self.idx;
+        for c in self.coefficients.iter() {
+            acc += c * self.buffer[j];
+            j = if j == 0 { N - 1 } else { j - 1 };
+        }
+        self.idx = (self.idx + 1) % N;
+        acc
+    }
+
+    pub fn reset(&mut self) {
+        self.buffer = [0.0; N];
+        self.idx = 0;
+    }
+}
+
+/// Default blend for `ComplementaryAltFilter` (trusts the integrated accel)
+#[allow(dead_code)]
+pub const COMP_ALT_ALPHA: f32 = 0.98;
+
+/// Baro + vertical accel complementary filter: a cheap cross-check of
+/// VerticalKalman, and a fallback while the EKF covariance is still settling.
+/// `accel_ms2` is world-frame vertical acceleration, gravity removed, up positive.
+#[allow(dead_code)]
+pub struct ComplementaryAltFilter {
+    alpha: f32,
+    baro_alt: f32,
+    accel_alt: f32,
+    vel: f32,
+    initialized: bool,
+}
+
+#[allow(dead_code)]
+impl ComplementaryAltFilter {
+    pub fn new(alpha: f32) -> Self {
+        Self {
+            alpha: alpha.clamp(0.0, 1.0),
+            baro_alt: 0.0,
+            accel_alt: 0.0,
+            vel: 0.0,
+            initialized: false,
+        }
+    }
+
+    /// Returns the fused altitude (m):
+    /// alpha · (accel_alt + vel·dt) + (1 − alpha) · baro_m
+    pub fn update(&mut self, dt: f32, baro_m: f32, accel_ms2: f32) -> f32 {
+        self.baro_alt = baro_m;
+        if !self.initialized {
+            self.accel_alt = baro_m;
+            self.vel = 0.0;
+            self.initialized = true;
+            return baro_m;
+        }
+        if dt <= 0.0 {
+            return self.accel_alt;
+        }
+
+        self.vel += accel_ms2 * dt;
+        let predicted = self.accel_alt + self.vel * dt;
+        self.accel_alt = self.alpha * predicted + (1.0 - self.alpha) * baro_m;
+
+        // Feed the baro residual back into the velocity (Benedict-Bordner gain
+        // for g = 1 − alpha), otherwise accel bias makes vel drift without bound
+        let g = 1.0 - self.alpha;
+        let h = g * g / (2.0 - g);
+        self.vel += h * (baro_m - predicted) / dt;
+        self.accel_alt
+    }
+
+    pub fn velocity(&self) -> f32 {
+        self.vel
+    }
+
+    pub fn reset(&mut self) {
+        self.initialized = false;
+    }
+}
+
+/// Gains for `AlphaBetaGamma`
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub struct AbgConfig {
+    pub alpha: f32,
+    pub beta: f32,
+    pub gamma: f32,
+}
+
+#[allow(dead_code)]
+impl AbgConfig {
+    /// Critically damped gains (fading-memory g-h-k, triple pole at
+    /// θ = e^(−ω_n·dt)): α = 1 − θ³, β = 1.5·(1 − θ²)(1 − θ), γ = 0.5·(1 − θ)³
+    pub fn critical_damping(dt: f32, omega_n: f32) -> AbgConfig {
+        let theta = (-omega_n * dt).exp();
+        let one_m = 1.0 - theta;
+        AbgConfig {
+            alpha: 1.0 - theta * theta * theta,
+            beta: 1.5 * (1.0 - theta * theta) * one_m,
+            gamma: 0.5 * one_m * one_m * one_m,
+        }
+    }
+}
+
+/// Alpha-beta-gamma (g-h-k) tracker: position / velocity / acceleration from
+/// a position measurement, fixed gains, no matrices. Correction on residual r:
+/// pos += α·r, vel += β·r/dt, acc += 2γ·r/dt².
+#[allow(dead_code)]
+pub struct AlphaBetaGamma {
+    alpha: f32,
+    beta: f32,
+    gamma: f32,
+    pos: f32,
+    vel: f32,
+    acc: f32,
+    initialized: bool,
+}
+
+#[allow(dead_code)]
+impl AlphaBetaGamma {
+    pub fn new(alpha: f32, beta: f32, gamma: f32) -> Self {
+        Self {
+            alpha,
+            beta,
+            gamma,
+            pos: 0.0,
+            vel: 0.0,
+            acc: 0.0,
+            initialized: false,
+        }
+    }
+
+    pub fn from_config(cfg: AbgConfig) -> Self {
+        Self::new(cfg.alpha, cfg.beta, cfg.gamma)
+    }
+
+    /// Returns (position, velocity, acceleration)
+    pub fn update(&mut self, dt: f32, measurement: f32) -> (f32, f32, f32) {
+        if !self.initialized {
+            self.pos = measurement;
+            self.initialized = true;
+            return (self.pos, self.vel, self.acc);
+        }
+        if dt <= 0.0 {
+            return (self.pos, self.vel, self.acc);
+        }
+
+        // Predict
+        self.pos += self.vel * dt + 0.5 * self.acc * dt * dt;
+        self.vel += self.acc * dt;
+
+        // Correct
+        let r = measurement - self.pos;
+        self.pos += self.alpha * r;
+        self.vel += self.beta * r / dt;
+        self.acc += 2.0 * self.gamma * r / (dt * dt);
+        (self.pos, self.vel, self.acc)
+    }
+
+    pub fn reset(&mut self) {
+        self.pos = 0.0;
+        self.vel = 0.0;
+        self.acc = 0.0;
+        self.initialized = false;
+    }
+}
+
+/// Running sensor noise estimate (Welford): mean and standard deviation of a
+/// stationary signal, to size filters from the measured noise floor.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Default)]
+pub struct NoiseEstimator {
+    n: u32,
+    mean: f32,
+    m2: f32,
+}
+
+#[allow(dead_code)]
+impl NoiseEstimator {
+    pub const fn new() -> Self {
+        Self { n: 0, mean: 0.0, m2: 0.0 }
+    }
+
+    pub fn update(&mut self, x: f32) {
+        self.n += 1;
+        let d = x - self.mean;
+        self.mean += d / self.n as f32;
+        self.m2 += d * (x - self.mean);
+    }
+
+    /// Sample standard deviation (0 until two samples are in)
+    pub fn noise_sigma(&self) -> f32 {
+        if self.n < 2 {
+            return 0.0;
+        }
+        (self.m2 / (self.n - 1) as f32).sqrt()
+    }
+
+    pub fn mean(&self) -> f32 {
+        self.mean
+    }
+
+    pub fn is_converged(&self, min_samples: u32) -> bool {
+        self.n >= min_samples
+    }
+}
@@ -1,19 +1,130 @@
 use embassy_stm32::gpio::{AnyPin, Output};
-use embassy_stm32::spi::{Error, Instance, Spi};
+use embassy_stm32::spi::{Error as SpiError, Instance, Spi};
+use embassy_time::{Duration, Instant, Timer};
+
+use super::roll::{PidGains, PID_GAINS_SIZE};
 
 #[allow(dead_code)]
 const CMD_JEDEC_ID: u8 = 0x9F;
+#[allow(dead_code)]
+const CMD_WRITE_ENABLE: u8 = 0x06;
+#[allow(dead_code)]
+const CMD_READ_STATUS1: u8 = 0x05;
+#[allow(dead_code)]
+const CMD_READ_STATUS2: u8 = 0x35;
+#[allow(dead_code)]
+const CMD_WRITE_STATUS: u8 = 0x01;
+#[allow(dead_code)]
+const CMD_READ_UID: u8 = 0x4B;
+#[allow(dead_code)]
+const CMD_READ_DATA: u8 = 0x03;
+#[allow(dead_code)]
+const CMD_PAGE_PROGRAM: u8 = 0x02;
+#[allow(dead_code)]
+const CMD_SECTOR_ERASE_4K: u8 = 0x20;
+#[allow(dead_code)]
+const CMD_BLOCK_ERASE_64K: u8 = 0xD8;
+#[allow(dead_code)]
+const CMD_CHIP_ERASE: u8 = 0xC7;
+
+/// SR1 bit 0: Write In Progress (BUSY)
+#[allow(dead_code)]
+const SR1_WIP: u8 = 0x01;
+/// SR1 bits 4:2: block protect BP2..BP0 (non-zero → part of the array read-only)
+pub const SR1_BP_MASK: u8 = 0x1C;
+/// SR1 bit 7 / SR2 bit 0: status register protect SRP0 / SRP1.
+///   SRP1:SRP0 = 00 → SR writable after WREN, 01 → locked while /WP is low,
+///   10 → locked until the next power cycle, 11 → locked for good (OTP).
+#[allow(dead_code)]
+pub const SR1_SRP0: u8 = 0x80;
+#[allow(dead_code)]
+pub const SR2_SRP1: u8 = 0x01;
+/// SR2 bit 6: complement protect — inverts the BP range (CMP=1, BP=0 protects everything)
+pub const SR2_CMP: u8 = 0x40;
+
+// Busy-wait timeouts (datasheet max values, W25Q128 worst case + margin)
+const PAGE_PROGRAM_TIMEOUT: Duration = Duration::from_millis(5);
+const SECTOR_ERASE_TIMEOUT: Duration = Duration::from_millis(500);
+const BLOCK_ERASE_TIMEOUT: Duration = Duration::from_millis(2_500);
+const CHIP_ERASE_TIMEOUT: Duration = Duration::from_secs(200);
+const WRITE_STATUS_TIMEOUT: Duration = Duration::from_millis(15);
+
+/// Default SR1 polling interval (yields to the executor between polls)
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_micros(100);
+/// Chip erase takes tens of seconds: no point polling faster than this
+const CHIP_ERASE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Program granularity: one page-program writes at most one 256-byte page
+pub const PAGE_SIZE: usize = 256;
+
+#[allow(dead_code)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Underlying SPI bus error
+    Spi(SpiError),
+    /// Write would exceed the 256-byte page (the chip would wrap inside the page)
+    PageOverflow,
+    /// WIP bit still set after the operation timeout
+    Timeout,
+    /// JEDEC capacity byte not in `FlashSize`
+    UnknownSize(u8),
+    /// Read-back differs from what was programmed (first mismatching byte)
+    VerifyFailed { addr: u32, expected: u8, actual: u8 },
+    /// Stored record fails its CRC32 (blank sector or torn write)
+    BadCrc,
+}
+
+impl From<SpiError> for Error {
+    fn from(e: SpiError) -> Self {
+        Error::Spi(e)
+    }
+}
+
+/// Supported W25Q densities (JEDEC ID byte 3 = log2 of the size in bytes)
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FlashSize {
+    /// 16 Mbit, fitted on the JHEF405PRO
+    #[default]
+    W25Q16,
+    W25Q32,
+    W25Q64,
+    W25Q128,
+}
+
+#[allow(dead_code)]
+impl FlashSize {
+    pub fn from_capacity_byte(b: u8) -> Option<Self> {
+        match b {
+            0x15 => Some(Self::W25Q16),
+            0x16 => Some(Self::W25Q32),
+            0x17 => Some(Self::W25Q64),
+            0x18 => Some(Self::W25Q128),
+            _ => None,
+        }
+    }
+
+    pub fn total_bytes(self) -> u32 {
+        match self {
+            Self::W25Q16 => 2 * 1024 * 1024,
+            Self::W25Q32 => 4 * 1024 * 1024,
+            Self::W25Q64 => 8 * 1024 * 1024,
+            Self::W25Q128 => 16 * 1024 * 1024,
+        }
+    }
+}
 
 #[allow(dead_code)]
 pub struct W25qxx<'d, T: Instance, Tx, Rx> {
     spi: Spi<'d, T, Tx, Rx>,
     cs: Output<'d, AnyPin>,
+    size: FlashSize, // W25Q16 until `detect_size`
 }
 
 #[allow(dead_code)]
 impl<'d, T: Instance, Tx, Rx> W25qxx<'d, T, Tx, Rx> {
     pub fn new(spi: Spi<'d, T, Tx, Rx>, cs: Output<'d, AnyPin>) -> Self {
-        Self { spi, cs }
+        Self { spi, cs, size: FlashSize::default() }
     }
 
     pub async fn read_id(&mut self) -> Result<[u8; 3], Error> {
@@ -24,4 +135,939 @@ impl<'d, T: Instance, Tx, Rx> W25qxx<'d, T, Tx, Rx> {
         self.cs.set_high();
         Ok(id)
     }
+
+    /// Read the JEDEC capacity byte and remember the chip size
+    pub async fn detect_size(&mut self) -> Result<FlashSize, Error> {
+        let id = self.read_id().await?;
+        self.size = FlashSize::from_capacity_byte(id[2]).ok_or(Error::UnknownSize(id[2]))?;
+        Ok(self.size)
+    }
+
+    /// Capacity of the detected chip (W25Q16 before `detect_size`)
+    pub fn total_bytes(&self) -> u32 {
+        self.size.total_bytes()
+    }
+
+    /// Number of 4 KiB erase sectors
+    pub fn sector_count(&self) -> u32 {
+        self.total_bytes() / SECTOR_SIZE
+    }
+
+    /// 64-bit factory unique ID (0x4B + 4 dummy bytes), distinct per chip
+    pub async fn read_uid(&mut self) -> Result<[u8; 8], Error> {
+        let mut uid = [0u8; 8];
+        self.cs.set_low();
+        let res = self
+            .spi
+            .blocking_write(&[CMD_READ_UID, 0, 0, 0, 0])
+            .and_then(|_| self.spi.blocking_read(&mut uid));
+        self.cs.set_high();
+        res?;
+        Ok(uid)
+    }
+
+    /// Set the Write Enable Latch (required before every program/erase)
+    pub async fn write_enable(&mut self) -> Result<(), Error> {
+        self.cs.set_low();
+        let res = self.spi.blocking_write(&[CMD_WRITE_ENABLE]);
+        self.cs.set_high();
+        res?;
+        Ok(())
+    }
+
+    /// SR1: WIP, WEL, BP2..0, TB, SEC, SRP0
+    pub async fn read_status_register1(&mut self) -> Result<u8, Error> {
+        self.read_status(CMD_READ_STATUS1).await
+    }
+
+    /// SR2: SRP1, QE, LB1..3, CMP, SUS
+    pub async fn read_status_register2(&mut self) -> Result<u8, Error> {
+        self.read_status(CMD_READ_STATUS2).await
+    }
+
+    async fn read_status(&mut self, cmd: u8) -> Result<u8, Error> {
+        let mut buf = [cmd, 0x00];
+        self.cs.set_low();
+        let res = self.spi.blocking_transfer_in_place(&mut buf);
+        self.cs.set_high();
+        res?;
+        Ok(buf[1])
+    }
+
+    /// True when any part of the array is write-protected (BP bits or CMP set)
+    pub async fn is_write_protected(&mut self) -> Result<bool, Error> {
+        let sr1 = self.read_status_register1().await?;
+        let sr2 = self.read_status_register2().await?;
+        Ok(sr1 & SR1_BP_MASK != 0 || sr2 & SR2_CMP != 0)
+    }
+
+    /// Write 0x00 to SR1 and SR2 (0x01 + two data bytes): clears BP/TB/SEC/CMP,
+    /// so the whole array is writable, and SRP1:SRP0 back to 00.
+    ///
+    /// WREN must go first — without it the chip ignores the write and the
+    /// protection silently stays. Never write SRP1:SRP0 = 11 here: that makes
+    /// the status registers one-time programmable and a protected chip stays
+    /// read-only for good. With SRP1 = 1 already set the write is refused
+    /// until power cycle, which the caller sees as BP bits still set.
+    pub async fn clear_write_protection(&mut self) -> Result<(), Error> {
+        self.write_enable().await?;
+        self.cs.set_low();
+        let res = self.spi.blocking_write(&[CMD_WRITE_STATUS, 0x00, 0x00]);
+        self.cs.set_high();
+        res?;
+        self.wait_busy_timeout(DEFAULT_POLL_INTERVAL, WRITE_STATUS_TIMEOUT).await
+    }
+
+    /// Poll SR1 until the WIP bit clears (page-program timeout)
+    pub async fn wait_busy(&mut self) -> Result<(), Error> {
+        self.wait_busy_timeout(DEFAULT_POLL_INTERVAL, PAGE_PROGRAM_TIMEOUT).await
+    }
+
+    /// Poll SR1 every `poll_interval` until the WIP bit clears.
+    /// Returns `Error::Timeout` if the chip is still busy after `timeout`.
+    pub async fn wait_busy_timeout(
+        &mut self,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        let start = Instant::now();
+        loop {
+            if self.read_status_register1().await? & SR1_WIP == 0 {
+                return Ok(());
+            }
+            if start.elapsed() >= timeout {
+                return Err(Error::Timeout);
+            }
+            Timer::after(poll_interval).await;
+        }
+    }
+
+    /// Read `buf.len()` bytes starting at `addr` in a single burst.
+    /// The address counter auto-increments across page/sector boundaries.
+    pub async fn read(&mut self, addr: u32, buf: &mut [u8]) -> Result<(), Error> {
+        let cmd = [
+            CMD_READ_DATA,
+            (addr >> 16) as u8,
+            (addr >> 8) as u8,
+            addr as u8,
+        ];
+        self.cs.set_low();
+        let res = self
+            .spi
+            .blocking_write(&cmd)
+            .and_then(|_| self.spi.blocking_read(buf));
+        self.cs.set_high();
+        res?;
+        Ok(())
+    }
+
+    /// Read a little-endian u32 at `addr`
+    pub async fn read_u32_le(&mut self, addr: u32) -> Result<u32, Error> {
+        let mut b = [0u8; 4];
+        self.read(addr, &mut b).await?;
+        Ok(u32::from_le_bytes(b))
+    }
+
+    /// Program up to 256 bytes starting at `addr`.
+    /// The target range must already be erased (0xFF) and must not cross a
+    /// page boundary — the W25Qxx would silently wrap to the page start.
+    pub async fn page_program(&mut self, addr: u32, data: &[u8]) -> Result<(), Error> {
+        let page_offset = addr as usize % PAGE_SIZE;
+        if page_offset + data.len() > PAGE_SIZE {
+            return Err(Error::PageOverflow);
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        self.write_enable().await?;
+
+        let cmd = [
+            CMD_PAGE_PROGRAM,
+            (addr >> 16) as u8,
+            (addr >> 8) as u8,
+            addr as u8,
+        ];
+        self.cs.set_low();
+        let res = self
+            .spi
+            .blocking_write(&cmd)
+            .and_then(|_| self.spi.blocking_write(data));
+        self.cs.set_high();
+        res?;
+
+        self.wait_busy().await
+    }
+
+    /// `page_program` followed by a read-back compare. A mismatch is retried
+    /// once (NOR can only clear bits, so a second pass fixes a dropped write
+    /// but not a byte that was not erased).
+    pub async fn page_program_verified(&mut self, addr: u32, data: &[u8]) -> Result<(), Error> {
+        let mut readback = [0u8; PAGE_SIZE];
+        let mut attempt = 0;
+        loop {
+            self.page_program(addr, data).await?;
+            let rb = &mut readback[..data.len()];
+            self.read(addr, rb).await?;
+            let Some(i) = data.iter().zip(rb.iter()).position(|(a, b)| a != b) else {
+                return Ok(());
+            };
+            attempt += 1;
+            if attempt == 2 {
+                return Err(Error::VerifyFailed {
+                    addr: addr + i as u32,
+                    expected: data[i],
+                    actual: rb[i],
+                });
+            }
+        }
+    }
+
+    /// Erase the 4 KiB sector containing `addr` (all bytes → 0xFF)
+    pub async fn sector_erase_4k(&mut self, addr: u32) -> Result<(), Error> {
+        self.erase_cmd(CMD_SECTOR_ERASE_4K, addr).await?;
+        self.wait_busy_timeout(DEFAULT_POLL_INTERVAL, SECTOR_ERASE_TIMEOUT).await
+    }
+
+    /// Erase the 64 KiB block containing `addr`
+    pub async fn block_erase_64k(&mut self, addr: u32) -> Result<(), Error> {
+        self.erase_cmd(CMD_BLOCK_ERASE_64K, addr).await?;
+        self.wait_busy_timeout(DEFAULT_POLL_INTERVAL, BLOCK_ERASE_TIMEOUT).await
+    }
+
+    /// Erase the whole chip (up to ~100 s on a 128 Mbit device)
+    pub async fn chip_erase(&mut self) -> Result<(), Error> {
+        self.write_enable().await?;
+        self.cs.set_low();
+        let res = self.spi.blocking_write(&[CMD_CHIP_ERASE]);
+        self.cs.set_high();
+        res?;
+        self.wait_busy_timeout(CHIP_ERASE_POLL_INTERVAL, CHIP_ERASE_TIMEOUT).await
+    }
+
+    /// WREN + erase opcode + 24-bit address (caller waits for completion)
+    async fn erase_cmd(&mut self, cmd: u8, addr: u32) -> Result<(), Error> {
+        self.write_enable().await?;
+        let frame = [cmd, (addr >> 16) as u8, (addr >> 8) as u8, addr as u8];
+        self.cs.set_low();
+        let res = self.spi.blocking_write(&frame);
+        self.cs.set_high();
+        res?;
+        Ok(())
+    }
+}
+
+// ── Persistent config ─────────────────────────────────────────────────────────
+//  Sector 1, between the two superblock slots. Record: PidGains
+//  (24 bytes LE) followed by the CRC32 of those bytes.
+
+const PID_GAINS_ADDR: u32 = SECTOR_SIZE;
+const PID_RECORD_SIZE: usize = PID_GAINS_SIZE + 4;
+
+/// CRC-32 (IEEE 802.3, reflected 0xEDB88320), bitwise — only run on config writes
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[allow(dead_code)]
+impl<'d, T: Instance, Tx, Rx> W25qxx<'d, T, Tx, Rx> {
+    /// Saved roll gains; `Error::BadCrc` when none were ever written
+    pub async fn read_pid_gains(&mut self) -> Result<PidGains, Error> {
+        let mut rec = [0u8; PID_RECORD_SIZE];
+        self.read(PID_GAINS_ADDR, &mut rec).await?;
+        let (body, crc) = rec.split_at(PID_GAINS_SIZE);
+        if crc32(body) != u32::from_le_bytes([crc[0], crc[1], crc[2], crc[3]]) {
+            return Err(Error::BadCrc);
+        }
+        let mut b = [0u8; PID_GAINS_SIZE];
+        b.copy_from_slice(body);
+        Ok(PidGains::from_bytes(&b))
+    }
+
+    /// Erase the config sector and program the gains (~50 ms: not in flight)
+    pub async fn write_pid_gains(&mut self, gains: &PidGains) -> Result<(), Error> {
+        let body = gains.to_bytes();
+        let mut rec = [0u8; PID_RECORD_SIZE];
+        rec[..PID_GAINS_SIZE].copy_from_slice(&body);
+        rec[PID_GAINS_SIZE..].copy_from_slice(&crc32(&body).to_le_bytes());
+        self.sector_erase_4k(PID_GAINS_ADDR).await?;
+        self.page_program_verified(PID_GAINS_ADDR, &rec).await
+    }
+}
+
+// ── Flat file system ──────────────────────────────────────────────────────────
+//
+// Layout:
+//   [0 .. 4 KiB]                    superblock slot A (one page used, rest blank)
+//   [PID_GAINS_ADDR .. +4 KiB]      persistent config, not part of the file system
+//   [0x2000 .. +4 KiB]              superblock slot B
+//   [FS_DATA_START .. data_end]     file data, each file starting on a 4 KiB sector
+//   [data_end .. chip end]          FS_RESERVED_TAIL, the calibrate flash self-test
+//
+// data_end follows the detected chip size (W25qxx::detect_size at mount).
+//
+// Superblock (little-endian, one page):
+//   0   magic "GLD2"
+//   4   sequence number (the valid slot with the highest one is current)
+//   8   data area start
+//   12  data area end
+//   16  file count
+//   20  CRC32 of the page with this field and every `len` blank
+//   24  FS_MAX_FILES × { name [u8; 8], start u32, len u32 }
+//
+// A new superblock always goes to the other slot, so a power cut during the
+// erase / program leaves the previous one intact. The CRC does not cover the
+// `len` fields: they stay blank (0xFFFFFFFF) while a file is open and `close`
+// programs them in place in the current slot. A file left open by a power
+// cut is recovered at mount by scanning for the first blank word on an
+// FS_RECOVER_STRIDE boundary — writers must never start a stride with
+// 0xFFFFFFFF (LogRecord.ts_ms never is).
+//
+// Files are append-only and packed back to back; only the last one can be open.
+// The data area is never erased behind the user's back: with no valid
+// superblock, mount only formats a blank chip and otherwise reports
+// `Unformatted` until `format` is called (USB ERASE_LOG).
+
+pub const SECTOR_SIZE: u32 = 4096;
+const BLOCK_SIZE: u32 = 64 * 1024;
+const FS_SUPERBLOCK_ADDRS: [u32; 2] = [0x0000_0000, PID_GAINS_ADDR + SECTOR_SIZE];
+const FS_SUPERBLOCK_SIZE: usize = PAGE_SIZE;
+const FS_HEADER_SIZE: usize = 24;
+const FS_ENTRY_SIZE: usize = 16;
+const FS_CRC_OFFSET: usize = 20;
+const FS_MAGIC: [u8; 4] = *b"GLD2";
+/// File name length in the superblock (shorter names are zero-padded)
+pub const FS_NAME_LEN: usize = 8;
+pub const FS_MAX_FILES: usize = (FS_SUPERBLOCK_SIZE - FS_HEADER_SIZE) / FS_ENTRY_SIZE;
+const FS_DATA_START: u32 = FS_SUPERBLOCK_ADDRS[1] + SECTOR_SIZE;
+/// Kept blank at the end of the chip for the calibrate self-test
+pub const FS_RESERVED_TAIL: u32 = SECTOR_SIZE;
+const FS_RECOVER_STRIDE: u32 = LOG_RECORD_SIZE as u32;
+const BLANK_U32: u32 = 0xFFFF_FFFF;
+
+#[allow(dead_code)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum FsError {
+    Flash(Error),
+    /// Name longer than FS_NAME_LEN bytes
+    NameTooLong,
+    /// No free superblock entry
+    TooManyFiles,
+    /// Data area exhausted
+    NoSpace,
+    /// Write to a file that is not the open one
+    ReadOnly,
+    /// No valid superblock and the data area is not blank: nothing is
+    /// written until `format`
+    Unformatted,
+}
+
+impl From<Error> for FsError {
+    fn from(e: Error) -> Self {
+        FsError::Flash(e)
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+struct FsEntry {
+    name: [u8; FS_NAME_LEN],
+    start: u32,
+    /// Bytes written (kept in RAM while the file is open, blank on flash)
+    len: u32,
+}
+
+#[allow(dead_code)]
+const EMPTY_ENTRY: FsEntry = FsEntry { name: [0; FS_NAME_LEN], start: 0, len: 0 };
+
+/// CRC of a superblock page, with the CRC field and every `len` blanked
+fn superblock_crc(sb: &[u8; FS_SUPERBLOCK_SIZE]) -> u32 {
+    let mut page = *sb;
+    page[FS_CRC_OFFSET..FS_CRC_OFFSET + 4].fill(0xFF);
+    for i in 0..FS_MAX_FILES {
+        let o = FS_HEADER_SIZE + i * FS_ENTRY_SIZE;
+        page[o + 12..o + 16].fill(0xFF);
+    }
+    crc32(&page)
+}
+
+#[allow(dead_code)]
+pub struct FlashFs<'d, T: Instance, Tx, Rx> {
+    flash: W25qxx<'d, T, Tx, Rx>,
+    entries: [FsEntry; FS_MAX_FILES],
+    count: usize,
+    /// Index of the file being written (its length is not on flash yet)
+    open: Option<usize>,
+    /// End of the data area, from the chip size
+    data_end: u32,
+    /// Superblock slot holding the current table, and its sequence number
+    active: usize,
+    seq: u32,
+    /// False while mount found foreign data (FsError::Unformatted)
+    mounted: bool,
+}
+
+#[allow(dead_code)]
+impl<'d, T: Instance, Tx, Rx> FlashFs<'d, T, Tx, Rx> {
+    pub fn new(flash: W25qxx<'d, T, Tx, Rx>) -> Self {
+        Self {
+            flash,
+            entries: [EMPTY_ENTRY; FS_MAX_FILES],
+            count: 0,
+            open: None,
+            data_end: FlashSize::default().total_bytes() - FS_RESERVED_TAIL,
+            active: 0,
+            seq: 0,
+            mounted: false,
+        }
+    }
+
+    /// Load the newest valid superblock (call once at start-up); a file left
+    /// open is recovered and closed. With no valid superblock a blank chip is
+    /// formatted, anything else is left untouched (`Unformatted`).
+    pub async fn mount(&mut self) -> Result<(), FsError> {
+        self.flash.detect_size().await?;
+        self.data_end = self.flash.total_bytes() - FS_RESERVED_TAIL;
+        self.count = 0;
+        self.open = None;
+        self.mounted = false;
+
+        let mut sb = [0u8; FS_SUPERBLOCK_SIZE];
+        let mut best: Option<(usize, u32)> = None;
+        for (slot, &addr) in FS_SUPERBLOCK_ADDRS.iter().enumerate() {
+            self.flash.read(addr, &mut sb).await?;
+            if let Some(seq) = self.check_superblock(&sb) {
+                if best.is_none_or(|(_, s)| seq.wrapping_sub(s) as i32 > 0) {
+                    best = Some((slot, seq));
+                }
+            }
+        }
+
+        let Some((slot, seq)) = best else {
+            if !self.data_area_blank().await? {
+                return Err(FsError::Unformatted);
+            }
+            self.seq = 0;
+            self.active = 1; // first superblock goes to slot A
+            self.mounted = true;
+            return self.write_superblock().await;
+        };
+
+        self.flash.read(FS_SUPERBLOCK_ADDRS[slot], &mut sb).await?;
+        let u32_at = |i: usize| u32::from_le_bytes([sb[i], sb[i + 1], sb[i + 2], sb[i + 3]]);
+        let count = u32_at(16) as usize;
+        self.active = slot;
+        self.seq = seq;
+        self.count = count;
+        for (i, e) in self.entries[..count].iter_mut().enumerate() {
+            let o = FS_HEADER_SIZE + i * FS_ENTRY_SIZE;
+            e.name.copy_from_slice(&sb[o..o + FS_NAME_LEN]);
+            e.start = u32_at(o + 8);
+            e.len = u32_at(o + 12);
+        }
+        self.mounted = true;
+
+        // Power cut while the last file was open (or while its length was
+        // being programmed): find its end, then close it
+        if let Some(last) = count.checked_sub(1) {
+            let e = self.entries[last];
+            if e.len == BLANK_U32 || e.start.checked_add(e.len).is_none_or(|end| end > self.data_end) {
+                let mut end = e.start;
+                while end < self.data_end && self.flash.read_u32_le(end).await? != BLANK_U32 {
+                    end += FS_RECOVER_STRIDE;
+                }
+                self.entries[last].len = end - e.start;
+                if e.len == BLANK_U32 {
+                    self.open = Some(last);
+                    self.close().await?;
+                } else {
+                    // len half-programmed: it can only be fixed in a new superblock
+                    self.write_superblock().await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sequence number of a superblock page, `None` if it is not ours
+    fn check_superblock(&self, sb: &[u8; FS_SUPERBLOCK_SIZE]) -> Option<u32> {
+        let u32_at = |i: usize| u32::from_le_bytes([sb[i], sb[i + 1], sb[i + 2], sb[i + 3]]);
+        let valid = sb[0..4] == FS_MAGIC
+            && u32_at(8) == FS_DATA_START
+            && u32_at(12) == self.data_end
+            && u32_at(16) as usize <= FS_MAX_FILES
+            && u32_at(FS_CRC_OFFSET) == superblock_crc(sb);
+        valid.then(|| u32_at(4))
+    }
+
+    /// Every data sector starts blank. Files start on a sector and never
+    /// begin a stride with a blank word, so the first word is enough.
+    async fn data_area_blank(&mut self) -> Result<bool, FsError> {
+        let mut addr = FS_DATA_START;
+        while addr < self.data_end {
+            if self.flash.read_u32_le(addr).await? != BLANK_U32 {
+                return Ok(false);
+            }
+            addr += SECTOR_SIZE;
+        }
+        Ok(true)
+    }
+
+    pub fn is_mounted(&self) -> bool {
+        self.mounted
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.count
+    }
+
+    /// Length in bytes of file `idx`
+    pub fn file_len(&self, idx: usize) -> Option<u32> {
+        (idx < self.count).then(|| self.entries[idx].len)
+    }
+
+    /// Index of the file currently open for writing
+    pub fn open_file(&self) -> Option<usize> {
+        self.open
+    }
+
+    /// Allocate a new file after the last one and open it for writing
+    /// (closes the previously open file; writes a new superblock).
+    pub async fn create_file(&mut self, name: &str) -> Result<FlashFile<'_, 'd, T, Tx, Rx>, FsError> {
+        if !self.mounted {
+            return Err(FsError::Unformatted);
+        }
+        let name = name.as_bytes();
+        if name.len() > FS_NAME_LEN {
+            return Err(FsError::NameTooLong);
+        }
+        self.close().await?;
+        if self.count >= FS_MAX_FILES {
+            return Err(FsError::TooManyFiles);
+        }
+
+        let start = match self.count.checked_sub(1) {
+            Some(last) => {
+                let end = self.entries[last].start + self.entries[last].len;
+                end.div_ceil(SECTOR_SIZE) * SECTOR_SIZE
+            }
+            None => FS_DATA_START,
+        };
+        if start >= self.data_end {
+            return Err(FsError::NoSpace);
+        }
+
+        let idx = self.count;
+        let mut entry = FsEntry { name: [0; FS_NAME_LEN], start, len: 0 };
+        entry.name[..name.len()].copy_from_slice(name);
+        self.entries[idx] = entry;
+        self.count += 1;
+        self.open = Some(idx);
+        self.write_superblock().await?;
+        Ok(FlashFile { fs: self, idx, rd_pos: 0 })
+    }
+
+    /// Handle on an existing file, positioned at its start
+    pub fn file(&mut self, idx: usize) -> Option<FlashFile<'_, 'd, T, Tx, Rx>> {
+        if idx < self.count {
+            Some(FlashFile { fs: self, idx, rd_pos: 0 })
+        } else {
+            None
+        }
+    }
+
+    /// Program the open file's length into its entry in the current superblock
+    pub async fn close(&mut self) -> Result<(), FsError> {
+        if let Some(i) = self.open.take() {
+            let addr = FS_SUPERBLOCK_ADDRS[self.active]
+                + (FS_HEADER_SIZE + i * FS_ENTRY_SIZE + 12) as u32;
+            self.flash
+                .page_program_verified(addr, &self.entries[i].len.to_le_bytes())
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Erase every sector in use and write an empty superblock. Unmounted
+    /// (foreign data), the whole data area is erased — several seconds.
+    pub async fn format(&mut self) -> Result<(), FsError> {
+        if !self.mounted {
+            return self.wipe().await;
+        }
+        let end = match self.count.checked_sub(1) {
+            Some(last) => self.entries[last].start + self.entries[last].len,
+            None => FS_DATA_START,
+        };
+        let mut addr = FS_DATA_START;
+        while addr < end {
+            self.flash.sector_erase_4k(addr).await?;
+            addr += SECTOR_SIZE;
+        }
+        self.count = 0;
+        self.open = None;
+        self.write_superblock().await
+    }
+
+    /// Erase the whole data area (stale data would break open-file recovery),
+    /// then write an empty superblock
+    async fn wipe(&mut self) -> Result<(), FsError> {
+        let mut addr = FS_DATA_START;
+        while addr < self.data_end {
+            if addr % BLOCK_SIZE == 0 && addr + BLOCK_SIZE <= self.data_end {
+                self.flash.block_erase_64k(addr).await?;
+                addr += BLOCK_SIZE;
+            } else {
+                self.flash.sector_erase_4k(addr).await?;
+                addr += SECTOR_SIZE;
+            }
+        }
+        self.count = 0;
+        self.open = None;
+        self.mounted = true;
+        self.write_superblock().await
+    }
+
+    /// Write the table to the other slot with the next sequence number; the
+    /// current slot stays valid until the new one is programmed and verified
+    async fn write_superblock(&mut self) -> Result<(), FsError> {
+        let target = 1 - self.active;
+        let seq = self.seq.wrapping_add(1);
+        let mut sb = [0xFFu8; FS_SUPERBLOCK_SIZE];
+        sb[0..4].copy_from_slice(&FS_MAGIC);
+        sb[4..8].copy_from_slice(&seq.to_le_bytes());
+        sb[8..12].copy_from_slice(&FS_DATA_START.to_le_bytes());
+        sb[12..16].copy_from_slice(&self.data_end.to_le_bytes());
+        sb[16..20].copy_from_slice(&(self.count as u32).to_le_bytes());
+        for (i, e) in self.entries[..self.count].iter().enumerate() {
+            let o = FS_HEADER_SIZE + i * FS_ENTRY_SIZE;
+            sb[o..o + FS_NAME_LEN].copy_from_slice(&e.name);
+            sb[o + 8..o + 12].copy_from_slice(&e.start.to_le_bytes());
+            if self.open != Some(i) {
+                sb[o + 12..o + 16].copy_from_slice(&e.len.to_le_bytes());
+            }
+        }
+        let crc = superblock_crc(&sb);
+        sb[FS_CRC_OFFSET..FS_CRC_OFFSET + 4].copy_from_slice(&crc.to_le_bytes());
+
+        let addr = FS_SUPERBLOCK_ADDRS[target];
+        self.flash.sector_erase_4k(addr).await?;
+        self.flash.page_program_verified(addr, &sb).await?;
+        self.active = target;
+        self.seq = seq;
+        Ok(())
+    }
+}
+
+/// One file of a `FlashFs` (borrows the file system while in use)
+#[allow(dead_code)]
+pub struct FlashFile<'a, 'd, T: Instance, Tx, Rx> {
+    fs: &'a mut FlashFs<'d, T, Tx, Rx>,
+    idx: usize,
+    rd_pos: u32,
+}
+
+#[allow(dead_code)]
+impl<'a, 'd, T: Instance, Tx, Rx> FlashFile<'a, 'd, T, Tx, Rx> {
+    pub fn len(&self) -> u32 {
+        self.fs.entries[self.idx].len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Append `data` (open file only). Each sector is erased as the file
+    /// enters it and writes are split at page boundaries.
+    pub async fn write(&mut self, data: &[u8]) -> Result<(), FsError> {
+        if self.fs.open != Some(self.idx) {
+            return Err(FsError::ReadOnly);
+        }
+        let e = self.fs.entries[self.idx];
+        let mut addr = e.start + e.len;
+        if addr + data.len() as u32 > self.fs.data_end {
+            return Err(FsError::NoSpace);
+        }
+
+        let mut rest = data;
+        while !rest.is_empty() {
+            if addr % SECTOR_SIZE == 0 {
+                self.fs.flash.sector_erase_4k(addr).await?;
+            }
+            let n = rest.len().min(PAGE_SIZE - addr as usize % PAGE_SIZE);
+            self.fs.flash.page_program_verified(addr, &rest[..n]).await?;
+            addr += n as u32;
+            self.fs.entries[self.idx].len += n as u32;
+            rest = &rest[n..];
+        }
+        Ok(())
+    }
+
+    /// Move the read position (clamped to the file length)
+    pub fn seek(&mut self, pos: u32) {
+        self.rd_pos = pos.min(self.len());
+    }
+
+    /// Read from the current position; returns the byte count (0 at end of file)
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
+        let e = self.fs.entries[self.idx];
+        let n = buf.len().min((e.len - self.rd_pos) as usize);
+        if n > 0 {
+            self.fs.flash.read(e.start + self.rd_pos, &mut buf[..n]).await?;
+            self.rd_pos += n as u32;
+        }
+        Ok(n)
+    }
+}
+
+// ── Flight data logger ────────────────────────────────────────────────────────
+//
+// One FlashFs file per flight ("flight00", "flight01", …), created at launch
+// (so bench arms cost no file entry) and closed on landing or disarm. The
+// caller keeps the armed records preceding launch and hands them over with
+// `record_all`. Records are fixed 32-byte slots, 8 per page.
+
+/// Slot size on flash (26 bytes used, padded so records never straddle a page)
+pub const LOG_RECORD_SIZE: usize = 32;
+const LOG_RECORD_USED: usize = 26;
+
+/// LogRecord.flags bits
+pub const LOG_FLAG_ARMED: u8 = 1 << 0;
+pub const LOG_FLAG_HIGH_G: u8 = 1 << 1;
+pub const LOG_FLAG_APOGEE: u8 = 1 << 2;
+/// Set once the flight reached FlightPhase::Landed — closes the flight file
+pub const LOG_FLAG_LANDED: u8 = 1 << 3;
+/// Set from FlightPhase::Boost on — opens the flight file
+pub const LOG_FLAG_LAUNCHED: u8 = 1 << 4;
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct LogRecord {
+    pub ts_ms: u32,
+    pub accel: [i16; 3],   // raw LSB (±16G → 2048 LSB/g)
+    pub gyro: [i16; 3],    // raw LSB (±2000 dps → 16.4 LSB/dps)
+    pub baro_alt_cm: i16,  // AGL, saturates at ±327 m
+    pub vbat_mv: u16,
+    pub flags: u8,         // LOG_FLAG_*
+    pub unix_s: u32,       // RTC wall clock, 0 until set over USB (SETTIME=)
+}
+
+impl LogRecord {
+    /// Serialise to a flash slot: little-endian fields followed by crc8
+    pub fn to_bytes(&self) -> [u8; LOG_RECORD_USED] {
+        let mut b = [0u8; LOG_RECORD_USED];
+        b[0..4].copy_from_slice(&self.ts_ms.to_le_bytes());
+        for i in 0..3 {
+            b[4 + 2 * i..6 + 2 * i].copy_from_slice(&self.accel[i].to_le_bytes());
+            b[10 + 2 * i..12 + 2 * i].copy_from_slice(&self.gyro[i].to_le_bytes());
+        }
+        b[16..18].copy_from_slice(&self.baro_alt_cm.to_le_bytes());
+        b[18..20].copy_from_slice(&self.vbat_mv.to_le_bytes());
+        b[20] = self.flags;
+        b[21..25].copy_from_slice(&self.unix_s.to_le_bytes());
+        b[25] = crc8(&b[..25]);
+        b
+    }
+
+    /// Parse a slot; `None` if the slot is blank or the crc8 does not match
+    pub fn from_bytes(b: &[u8]) -> Option<Self> {
+        if b.len() < LOG_RECORD_USED || crc8(&b[..25]) != b[25] {
+            return None;
+        }
+        let i16_at = |i: usize| i16::from_le_bytes([b[i], b[i + 1]]);
+        let ts_ms = u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+        if ts_ms == BLANK_U32 {
+            return None;
+        }
+        Some(Self {
+            ts_ms,
+            accel: [i16_at(4), i16_at(6), i16_at(8)],
+            gyro: [i16_at(10), i16_at(12), i16_at(14)],
+            baro_alt_cm: i16_at(16),
+            vbat_mv: u16::from_le_bytes([b[18], b[19]]),
+            flags: b[20],
+            unix_s: u32::from_le_bytes([b[21], b[22], b[23], b[24]]),
+        })
+    }
+}
+
+/// CRC-8 (poly 0x07, init 0x00)
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc: u8 = 0;
+    for &b in data {
+        crc ^= b;
+        for _ in 0..8 {
+            if (crc & 0x80) != 0 {
+                crc = (crc << 1) ^ 0x07;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+    crc
+}
+
+#[allow(dead_code)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum LogError {
+    Flash(Error),
+    Fs(FsError),
+    /// Log area or file table exhausted — erase before recording again
+    Full,
+    /// `record` called with no flight file open
+    NotRecording,
+}
+
+impl From<Error> for LogError {
+    fn from(e: Error) -> Self {
+        LogError::Flash(e)
+    }
+}
+
+impl From<FsError> for LogError {
+    fn from(e: FsError) -> Self {
+        match e {
+            FsError::Flash(e) => LogError::Flash(e),
+            FsError::NoSpace | FsError::TooManyFiles => LogError::Full,
+            e => LogError::Fs(e),
+        }
+    }
+}
+
+/// Read position of `FlightLogger::next_record` (start with `default()`)
+#[derive(Clone, Copy, Default, Debug)]
+pub struct LogCursor {
+    file: usize,
+    pos: u32,
+}
+
+#[allow(dead_code)]
+pub struct FlightLogger<'d, T: Instance, Tx, Rx> {
+    fs: FlashFs<'d, T, Tx, Rx>,
+}
+
+#[allow(dead_code)]
+impl<'d, T: Instance, Tx, Rx> FlightLogger<'d, T, Tx, Rx> {
+    pub fn new(flash: W25qxx<'d, T, Tx, Rx>) -> Self {
+        Self { fs: FlashFs::new(flash) }
+    }
+
+    /// Mount the file system (call once at start-up). Factory-fresh parts can
+    /// ship with every block protected, which would make each write a no-op.
+    pub async fn init(&mut self) -> Result<(), LogError> {
+        if self.fs.flash.is_write_protected().await? {
+            self.fs.flash.clear_write_protection().await?;
+        }
+        self.fs.mount().await?;
+        Ok(())
+    }
+
+    /// Number of records stored across all flights
+    pub fn record_count(&self) -> u32 {
+        (0..self.fs.file_count())
+            .filter_map(|i| self.fs.file_len(i))
+            .map(|len| len / LOG_RECORD_SIZE as u32)
+            .sum()
+    }
+
+    /// True between `start_flight` and `end_flight`
+    pub fn is_recording(&self) -> bool {
+        self.fs.open_file().is_some()
+    }
+
+    /// Open the next "flightNN" file (call at launch)
+    pub async fn start_flight(&mut self) -> Result<(), LogError> {
+        let n = self.fs.file_count() as u8;
+        let mut name = *b"flight00";
+        name[6] = b'0' + n / 10;
+        name[7] = b'0' + n % 10;
+        self.fs
+            .create_file(core::str::from_utf8(&name).unwrap_or("flight"))
+            .await?;
+        Ok(())
+    }
+
+    /// Close the current flight file (call on landing or disarm)
+    pub async fn end_flight(&mut self) -> Result<(), LogError> {
+        self.fs.close().await?;
+        Ok(())
+    }
+
+    /// Append one record to the open flight file
+    pub async fn record(&mut self, r: LogRecord) -> Result<(), LogError> {
+        let idx = self.fs.open_file().ok_or(LogError::NotRecording)?;
+        let mut slot = [0xFFu8; LOG_RECORD_SIZE];
+        slot[..LOG_RECORD_USED].copy_from_slice(&r.to_bytes());
+        if let Some(mut file) = self.fs.file(idx) {
+            file.write(&slot).await?;
+        }
+        Ok(())
+    }
+
+    /// Append a batch of records, a page at a time
+    pub async fn record_all(&mut self, records: impl IntoIterator<Item = LogRecord>) -> Result<(), LogError> {
+        let idx = self.fs.open_file().ok_or(LogError::NotRecording)?;
+        let mut page = [0xFFu8; PAGE_SIZE];
+        let mut n = 0;
+        for r in records {
+            let o = n * LOG_RECORD_SIZE;
+            page[o..o + LOG_RECORD_USED].copy_from_slice(&r.to_bytes());
+            n += 1;
+            if n == PAGE_SIZE / LOG_RECORD_SIZE {
+                if let Some(mut file) = self.fs.file(idx) {
+                    file.write(&page).await?;
+                }
+                page = [0xFFu8; PAGE_SIZE];
+                n = 0;
+            }
+        }
+        if n > 0 {
+            if let Some(mut file) = self.fs.file(idx) {
+                file.write(&page[..n * LOG_RECORD_SIZE]).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Next valid record after `cursor`, oldest flight first (corrupt slots
+    /// are skipped); `None` once every flight has been read. The caller
+    /// paces the dump, so a slow consumer loses nothing.
+    pub async fn next_record(&mut self, cursor: &mut LogCursor) -> Result<Option<LogRecord>, LogError> {
+        let mut slot = [0u8; LOG_RECORD_SIZE];
+        while cursor.file < self.fs.file_count() {
+            let Some(mut file) = self.fs.file(cursor.file) else { break };
+            file.seek(cursor.pos);
+            if file.read(&mut slot).await? < LOG_RECORD_SIZE {
+                cursor.file += 1;
+                cursor.pos = 0;
+                continue;
+            }
+            cursor.pos += LOG_RECORD_SIZE as u32;
+            if let Some(r) = LogRecord::from_bytes(&slot) {
+                return Ok(Some(r));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Erase every flight file and start an empty file table (also recovers
+    /// a chip holding foreign data)
+    pub async fn erase_log(&mut self) -> Result<(), LogError> {
+        self.fs.format().await?;
+        Ok(())
+    }
+
+    /// The logger owns the flash chip: config goes through it
+    pub async fn read_pid_gains(&mut self) -> Result<PidGains, Error> {
+        self.fs.flash.read_pid_gains().await
+    }
+
+    pub async fn write_pid_gains(&mut self, gains: &PidGains) -> Result<(), Error> {
+        self.fs.flash.write_pid_gains(gains).await
+    }
 }
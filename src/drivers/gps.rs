@@ -1,4 +1,6 @@
 use core::str::FromStr;
+// Host tests link std, whose inherent f32 methods take precedence
+#[cfg_attr(test, allow(unused_imports))]
 use micromath::F32Ext;
 
 // ─── UBX Protocol Constants for u-blox M10 (CFG-VALSET keys) ───
@@ -40,6 +42,12 @@ const CFG_MSGOUT_GSA_UART1: u32   = 0x209100BF;
 const CFG_MSGOUT_GSV_UART1: u32   = 0x209100C4;
 const CFG_MSGOUT_RMC_UART1: u32   = 0x209100AC;
 
+// CFG-MSGOUT-UBX (UART1)
+const CFG_MSGOUT_UBX_NAV_PVT_UART1: u32 = 0x20910007;
+
+// CFG-UART1
+const CFG_UART1_BAUDRATE: u32 = 0x40520001;
+
 // Dynamic model values
 const DYNMODEL_AIRBORNE_4G: u8 = 8;
 
@@ -47,9 +55,10 @@ const DYNMODEL_AIRBORNE_4G: u8 = 8;
 const EGNOS_SCANMASK: u64 = (1 << 3) | (1 << 6) | (1 << 16); // 0x0001_0048
 
 // ─── GNSS System ID (for constellation tracking in GSV) ───
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 #[repr(u8)]
 pub enum GnssSystem {
+    #[default]
     Unknown = 0,
     Gps     = 1,
     Glonass = 2,
@@ -59,10 +68,6 @@ pub enum GnssSystem {
     Qzss    = 6,
 }
 
-impl Default for GnssSystem {
-    fn default() -> Self { GnssSystem::Unknown }
-}
-
 impl GnssSystem {
     /// Single-char prefix for compact SV display
     pub fn prefix(self) -> char {
@@ -79,14 +84,17 @@ impl GnssSystem {
 }
 
 // ─── UBX Message Builder ───
+/// VALSET buffer size: the merged GNSS + NAV/SBAS/RATE message is 151 bytes
+pub const UBX_CFG_BUF_LEN: usize = 192;
+
 pub struct UbxBuilder {
-    pub buf: [u8; 128],
+    pub buf: [u8; UBX_CFG_BUF_LEN],
     pub idx: usize,
 }
 
 impl UbxBuilder {
     fn new() -> Self {
-        let mut s = Self { buf: [0u8; 128], idx: 10 };
+        let mut s = Self { buf: [0u8; UBX_CFG_BUF_LEN], idx: 10 };
         s.buf[0] = 0xB5; // sync1
         s.buf[1] = 0x62; // sync2
         s.buf[2] = 0x06; // class: CFG
@@ -96,6 +104,25 @@ impl UbxBuilder {
         s
     }
 
+    /// Target configuration layers (bit 0 = RAM, bit 1 = BBR, bit 2 = Flash)
+    #[allow(dead_code)]
+    pub fn set_layers(&mut self, ram: bool, bbr: bool, flash: bool) -> &mut Self {
+        self.buf[7] = (ram as u8) | ((bbr as u8) << 1) | ((flash as u8) << 2);
+        self
+    }
+
+    /// Append `other`'s key/value pairs to this message (both unfinalized).
+    /// Keeps this builder's layers. `None` if the result + checksum exceeds UBX_CFG_BUF_LEN.
+    pub fn merge(mut self, other: UbxBuilder) -> Option<UbxBuilder> {
+        let extra = other.idx - 10;
+        if self.idx + extra + 2 > self.buf.len() {
+            return None;
+        }
+        self.buf[self.idx..self.idx + extra].copy_from_slice(&other.buf[10..other.idx]);
+        self.idx += extra;
+        Some(self)
+    }
+
     fn add_key(&mut self, key: u32) {
         let i = self.idx;
         self.buf[i]   = (key & 0xFF) as u8;
@@ -124,6 +151,14 @@ impl UbxBuilder {
         self.idx += 2;
     }
 
+    fn add_u32(&mut self, key: u32, val: u32) {
+        self.add_key(key);
+        for i in 0..4 {
+            self.buf[self.idx + i] = ((val >> (i * 8)) & 0xFF) as u8;
+        }
+        self.idx += 4;
+    }
+
     fn add_u64(&mut self, key: u32, val: u64) {
         self.add_key(key);
         for i in 0..8 {
@@ -142,6 +177,11 @@ impl UbxBuilder {
         self.idx += 2;
         self.idx
     }
+
+    fn build(mut self) -> ([u8; UBX_CFG_BUF_LEN], usize) {
+        let len = self.finalize();
+        (self.buf, len)
+    }
 }
 
 fn ubx_checksum(data: &[u8]) -> (u8, u8) {
@@ -160,7 +200,11 @@ fn ubx_checksum(data: &[u8]) -> (u8, u8) {
 /// GPS + Galileo + BeiDou + GLONASS + SBAS(EGNOS)
 /// QZSS disabled: Japan-only system, wastes M10 tracking channels
 /// with weak 11-15 dB-Hz signals that will never contribute to a fix.
-pub fn ubx_cfg_gnss_all() -> ([u8; 128], usize) {
+pub fn ubx_cfg_gnss_all() -> ([u8; UBX_CFG_BUF_LEN], usize) {
+    gnss_all_keys().build()
+}
+
+fn gnss_all_keys() -> UbxBuilder {
     let mut b = UbxBuilder::new();
     // GPS — primary constellation, worldwide
     b.add_bool(CFG_SIGNAL_GPS_ENA, true);
@@ -180,12 +224,15 @@ pub fn ubx_cfg_gnss_all() -> ([u8; 128], usize) {
     // QZSS — DISABLED (Japan/Oceania only, useless in Europe)
     b.add_bool(CFG_SIGNAL_QZSS_ENA, false);
     b.add_bool(CFG_SIGNAL_QZSS_L1CA_ENA, false);
-    let len = b.finalize();
-    (b.buf, len)
+    b
 }
 
 /// Message 2: Airborne 4G + SBAS EGNOS + 10Hz rate + disable GLL/VTG
-pub fn ubx_cfg_nav_sbas_rate() -> ([u8; 128], usize) {
+pub fn ubx_cfg_nav_sbas_rate() -> ([u8; UBX_CFG_BUF_LEN], usize) {
+    nav_sbas_rate_keys().build()
+}
+
+fn nav_sbas_rate_keys() -> UbxBuilder {
     let mut b = UbxBuilder::new();
     // Dynamic model: Airborne 4G (for drones)
     b.add_u8(CFG_NAVSPG_DYNMODEL, DYNMODEL_AIRBORNE_4G);
@@ -209,38 +256,297 @@ pub fn ubx_cfg_nav_sbas_rate() -> ([u8; 128], usize) {
     b.add_u8(CFG_MSGOUT_GSA_UART1, 5);
     // GSV at 1Hz (every 10th nav cycle) — saves ~10KB/s bandwidth!
     b.add_u8(CFG_MSGOUT_GSV_UART1, 10);
+    b
+}
+
+/// Messages 1 + 2 as a single VALSET (one ACK round-trip), if they fit in UBX_CFG_BUF_LEN
+pub fn ubx_cfg_gnss_nav_merged() -> Option<([u8; UBX_CFG_BUF_LEN], usize)> {
+    gnss_all_keys().merge(nav_sbas_rate_keys()).map(UbxBuilder::build)
+}
+
+/// Message 3: NAV-PVT on UART1 at every nav solution (binary, preferred over NMEA)
+pub fn ubx_cfg_enable_navpvt() -> ([u8; UBX_CFG_BUF_LEN], usize) {
+    let mut b = UbxBuilder::new();
+    b.add_u8(CFG_MSGOUT_UBX_NAV_PVT_UART1, 1);
     let len = b.finalize();
     (b.buf, len)
 }
 
-// ─── GPS State Machine (inspired by Betaflight gps.c) ───
+/// Message 4: switch UART1 to `baud` (sent at the detected rate, applies immediately,
+/// so there is no ACK at the old rate to wait for)
+pub fn ubx_cfg_uart1_baudrate(baud: u32) -> ([u8; UBX_CFG_BUF_LEN], usize) {
+    let mut b = UbxBuilder::new();
+    b.add_u32(CFG_UART1_BAUDRATE, baud);
+    let len = b.finalize();
+    (b.buf, len)
+}
+
+/// Factory-reset recovery: VALSET CFG-UART1-BAUDRATE to 115200 (send at 9600;
+/// the M10 has no CFG-PRT), then message 1 (send once the UART has followed).
+pub fn ubx_cfg_gnss_all_115200() -> (([u8; UBX_CFG_BUF_LEN], usize), ([u8; UBX_CFG_BUF_LEN], usize)) {
+    (ubx_cfg_uart1_baudrate(115_200), ubx_cfg_gnss_all())
+}
+
+// ─── UBX Receive ───
+pub const UBX_SYNC1: u8 = 0xB5;
+pub const UBX_SYNC2: u8 = 0x62;
+
+pub const UBX_CLASS_NAV: u8 = 0x01;
+pub const UBX_ID_NAV_PVT: u8 = 0x07;
+pub const UBX_CLASS_ACK: u8 = 0x05;
+pub const UBX_ID_ACK_NAK: u8 = 0x00;
+pub const UBX_ID_ACK_ACK: u8 = 0x01;
+pub const UBX_CLASS_CFG: u8 = 0x06;
+pub const UBX_ID_CFG_VALSET: u8 = 0x8A;
+pub const UBX_CLASS_MON: u8 = 0x0A;
+pub const UBX_ID_MON_VER: u8 = 0x04;
+const UBX_NAV_PVT_LEN: usize = 92;
+
+/// Largest payload we keep (NAV-PVT = 92, MON-VER = 40 + 30·N)
+const UBX_MAX_PAYLOAD: usize = 248;
+
+/// NAV-PVT is considered the active source while received within this window
+const UBX_PVT_TIMEOUT_MS: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NavPvtData {
+    pub fix_type: u8,           // 0=none 2=2D 3=3D 4=GNSS+DR 5=time only
+    pub fix_ok: bool,           // flags.gnssFixOK
+    pub num_sv: u8,
+    pub lat_deg7: i32,          // deg × 1e7
+    pub lon_deg7: i32,          // deg × 1e7
+    pub height_mm: i32,         // above ellipsoid
+    pub h_msl_mm: i32,          // above mean sea level
+    pub vel_ned_mm_s: [i32; 3], // N, E, D
+    pub g_speed_mm_s: i32,      // 2D ground speed
+    pub head_mot_deg5: i32,     // heading of motion, deg × 1e5
+    pub p_acc_mm: u32,          // 3D position accuracy √(hAcc² + vAcc²)
+    pub h_acc_mm: u32,          // horizontal accuracy
+    pub p_dop: u16,             // × 100
+}
+
+#[inline]
+fn le_u16(p: &[u8], i: usize) -> u16 {
+    u16::from_le_bytes([p[i], p[i + 1]])
+}
+
+#[inline]
+fn le_u32(p: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([p[i], p[i + 1], p[i + 2], p[i + 3]])
+}
+
+#[inline]
+fn le_i32(p: &[u8], i: usize) -> i32 {
+    le_u32(p, i) as i32
+}
+
+/// Decode a UBX-NAV-PVT payload (92 bytes, offsets per u-blox M10 interface description)
+pub fn parse_ubx_nav_pvt(payload: &[u8]) -> Option<NavPvtData> {
+    if payload.len() < UBX_NAV_PVT_LEN {
+        return None;
+    }
+    let h_acc = le_u32(payload, 40);
+    let v_acc = le_u32(payload, 44);
+    let p_acc = ((h_acc as f32) * (h_acc as f32) + (v_acc as f32) * (v_acc as f32)).sqrt();
+    Some(NavPvtData {
+        fix_type: payload[20],
+        fix_ok: payload[21] & 0x01 != 0,
+        num_sv: payload[23],
+        lon_deg7: le_i32(payload, 24),
+        lat_deg7: le_i32(payload, 28),
+        height_mm: le_i32(payload, 32),
+        h_msl_mm: le_i32(payload, 36),
+        h_acc_mm: h_acc,
+        vel_ned_mm_s: [le_i32(payload, 48), le_i32(payload, 52), le_i32(payload, 56)],
+        g_speed_mm_s: le_i32(payload, 60),
+        head_mot_deg5: le_i32(payload, 64),
+        p_dop: le_u16(payload, 76),
+        p_acc_mm: p_acc as u32,
+    })
+}
+
+/// UBX-MON-VER poll request (empty payload)
+pub fn ubx_poll_mon_ver() -> ([u8; 8], usize) {
+    let mut m = [UBX_SYNC1, UBX_SYNC2, UBX_CLASS_MON, UBX_ID_MON_VER, 0, 0, 0, 0];
+    let (ck_a, ck_b) = ubx_checksum(&m[2..6]);
+    m[6] = ck_a;
+    m[7] = ck_b;
+    (m, 8)
+}
+
+/// Receiver software / hardware version strings from MON-VER
+#[derive(Debug, Clone, Default)]
+pub struct FirmwareVersion {
+    pub sw_version: heapless::String<30>,
+    pub hw_version: heapless::String<10>,
+}
+
+/// Decode the fixed part of a MON-VER payload (swVersion[30] + hwVersion[10],
+/// NUL-padded ASCII). The `extension` strings that follow are ignored.
+pub fn parse_ubx_mon_ver(payload: &[u8]) -> Option<FirmwareVersion> {
+    if payload.len() < 40 {
+        return None;
+    }
+    fn ascii<const N: usize>(field: &[u8]) -> heapless::String<N> {
+        let mut s = heapless::String::new();
+        for &c in field.iter().take_while(|&&c| c != 0) {
+            let _ = s.push(if c.is_ascii_graphic() || c == b' ' { c as char } else { '?' });
+        }
+        s
+    }
+    Some(FirmwareVersion {
+        sw_version: ascii(&payload[0..30]),
+        hw_version: ascii(&payload[30..40]),
+    })
+}
+
+/// Receiver answer to a CFG message
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AckResult {
+    pub acked: bool,   // true = ACK-ACK, false = ACK-NAK
+    pub class_id: u8,  // class of the acknowledged message
+    pub msg_id: u8,    // id of the acknowledged message
+}
+
+/// Decode a complete ACK-ACK / ACK-NAK frame (B5 62 05 01|00 02 00 cls id ck_a ck_b).
+/// Returns `None` for any other message.
+pub fn parse_ubx_ack(buf: &[u8]) -> Option<AckResult> {
+    if buf.len() < 10 || buf[0] != UBX_SYNC1 || buf[1] != UBX_SYNC2 || buf[2] != UBX_CLASS_ACK {
+        return None;
+    }
+    if le_u16(buf, 4) != 2 {
+        return None;
+    }
+    let acked = match buf[3] {
+        UBX_ID_ACK_ACK => true,
+        UBX_ID_ACK_NAK => false,
+        _ => return None,
+    };
+    Some(AckResult { acked, class_id: buf[6], msg_id: buf[7] })
+}
+
+/// One complete, checksum-valid UBX frame
+pub struct UbxFrame<'a> {
+    pub class: u8,
+    pub id: u8,
+    pub payload: &'a [u8],
+}
+
+/// Byte-wise UBX framer: B5 62 | class | id | len(LE16) | payload | ck_a ck_b
+pub struct UbxFramer {
+    buf: [u8; 6 + UBX_MAX_PAYLOAD + 2],
+    len: usize,
+    pub checksum_errors: u16,
+    /// Latest ACK-ACK / ACK-NAK seen (consumed by `take_ack`)
+    last_ack: Option<AckResult>,
+}
+
+impl UbxFramer {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0u8; 6 + UBX_MAX_PAYLOAD + 2],
+            len: 0,
+            checksum_errors: 0,
+            last_ack: None,
+        }
+    }
+
+    pub fn take_ack(&mut self) -> Option<AckResult> {
+        self.last_ack.take()
+    }
+
+    /// True while a frame is being accumulated (bytes belong to UBX, not NMEA)
+    pub fn in_frame(&self) -> bool {
+        self.len > 0
+    }
+
+    pub fn push(&mut self, b: u8) -> Option<UbxFrame<'_>> {
+        match self.len {
+            0 => {
+                if b == UBX_SYNC1 {
+                    self.buf[0] = b;
+                    self.len = 1;
+                }
+                return None;
+            }
+            1 if b != UBX_SYNC2 => {
+                self.len = 0;
+                return None;
+            }
+            _ => {}
+        }
+
+        self.buf[self.len] = b;
+        self.len += 1;
+
+        if self.len < 6 {
+            return None;
+        }
+        let payload_len = le_u16(&self.buf, 4) as usize;
+        if payload_len > UBX_MAX_PAYLOAD {
+            self.len = 0; // too big for us (or garbage length) → resync
+            return None;
+        }
+        let total = 6 + payload_len + 2;
+        if self.len < total {
+            return None;
+        }
+
+        // Frame complete
+        self.len = 0;
+        let (ck_a, ck_b) = ubx_checksum(&self.buf[2..6 + payload_len]);
+        if ck_a != self.buf[total - 2] || ck_b != self.buf[total - 1] {
+            self.checksum_errors = self.checksum_errors.wrapping_add(1);
+            return None;
+        }
+        if self.buf[2] == UBX_CLASS_ACK {
+            if let Some(ack) = parse_ubx_ack(&self.buf[..total]) {
+                self.last_ack = Some(ack);
+            }
+        }
+        Some(UbxFrame {
+            class: self.buf[2],
+            id: self.buf[3],
+            payload: &self.buf[6..6 + payload_len],
+        })
+    }
+}
+
+impl Default for UbxFramer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ─── GPS State Machine (inspired by Betaflight gps.c) ───
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(u8)] // GpsData::default() zeroes memory → discriminant 0 must be Unknown
 pub enum GpsState {
+    #[default]
     Unknown,
     DetectBaud,
+    BaudProbing { tries: u8 },
     Initialised,
     ReceivingData,
     LostCommunication,
 }
 
-impl Default for GpsState {
-    fn default() -> Self {
-        GpsState::Unknown
-    }
-}
-
 // Per-satellite info (like Betaflight GPS_svinfo)
 #[derive(Debug, Clone, Copy, Default)]
 pub struct SvInfo {
     pub svid: u8,       // PRN / satellite ID
     pub cno: u8,        // C/N₀ (dB-Hz, 0-99)
     pub gnss: GnssSystem, // which constellation
+    pub elevation_deg: u8, // 0-90°
+    pub azimuth_deg: u16,  // 0-359° true
 }
 
+// 6 bytes per SvInfo → 288 bytes for the whole table inside GpsData
 pub const GPS_SV_MAXSATS: usize = 48;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum NmeaFrame {
+    #[default]
     None,
     Gga,
     Rmc,
@@ -250,18 +556,15 @@ pub enum NmeaFrame {
     Gll,
 }
 
-impl Default for NmeaFrame {
-    fn default() -> Self {
-        NmeaFrame::None
-    }
-}
-
 #[derive(Debug, Clone, Copy)]
 pub struct GpsData {
     // ── Position / Navigation ──
     pub lat: f32,          // Decimal Degrees
     pub lon: f32,          // Decimal Degrees
-    pub alt: f32,          // Metres (MSL)
+    pub alt: f32,          // Metres (MSL, as reported in GGA)
+    pub geoid_sep: f32,    // Metres, geoid height above WGS84 ellipsoid (GGA field 11)
+    pub alt_msl: f32,      // Metres above mean sea level
+    pub alt_ellipsoid: f32, // Metres above WGS84 ellipsoid (alt_msl + geoid_sep)
     pub speed: f32,        // Knots
     pub speed_cms: u32,    // cm/s  (Betaflight-style)
     pub course: f32,       // Degrees
@@ -270,6 +573,10 @@ pub struct GpsData {
     // ── Satellites ──
     pub sats: u8,
     pub sats_in_view: u8,
+    pub sats_gps: u8,       // in view per constellation (GSV msg 1 of each talker)
+    pub sats_glo: u8,
+    pub sats_gal: u8,
+    pub sats_bds: u8,
     pub fix_quality: u8,    // 0=no fix 1=GPS 2=DGPS …
     pub fix: bool,
 
@@ -291,8 +598,8 @@ pub struct GpsData {
     pub rmc_count: u16,          // RMC
     pub gsa_count: u16,          // GSA
     pub gsv_count: u16,          // GSV
-    pub vtg_count: u16,          // VTG (known, not parsed)
-    pub gll_count: u16,          // GLL (known, not parsed)
+    pub vtg_count: u16,          // VTG
+    pub gll_count: u16,          // GLL
     pub unknown_count: u16,      // unrecognised sentence IDs
     pub last_frame: NmeaFrame,   // which sentence was last parsed
 
@@ -304,6 +611,9 @@ pub struct GpsData {
     // ── State machine ──
     pub state: GpsState,
     pub timeouts: u16,            // number of communication timeouts
+    pub state_since_ms: u32,      // millis() when Unknown/DetectBaud/BaudProbing was entered
+    pub baud_idx: u8,             // index into BAUD_CANDIDATES of the active UART rate
+    pub probe_sentences: u16,     // sentences_rx snapshot at the start of a baud probe
 
     // ── UTC time & date from RMC ──
     pub utc_time: u32,            // hhmmss00  (Betaflight format)
@@ -313,6 +623,34 @@ pub struct GpsData {
     pub sv_count: u8,
     pub svinfo: [SvInfo; GPS_SV_MAXSATS],
     pub last_gsv_reset_ms: u32,   // millis() when sats_in_view was last reset
+
+    // ── UBX binary navigation ──
+    pub nav_pvt: NavPvtData,
+    pub nav_pvt_count: u16,
+    pub last_pvt_ms: u32,         // last_byte_ms when the last NAV-PVT arrived
+    pub ubx_ack_count: u16,       // ACK-ACK received
+    pub ubx_nak_count: u16,       // ACK-NAK received (rejected CFG)
+}
+
+/// PDOP (× 100) above which the geometry is too poor to trust the position
+pub const GPS_PDOP_MAX_I: u16 = 400;
+
+/// Coarse fix quality from PDOP / satellite count
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GpsQuality {
+    #[default]
+    Poor,      // no 3D-capable fix, or PDOP ≥ 6
+    Moderate,  // PDOP 4–6: usable for display only
+    Good,      // PDOP < 4
+    Excellent, // PDOP < 2 with ≥ 8 sats
+}
+
+/// Satellites in view per constellation, formatted "GPS:12 GAL:8 GLO:5 BDS:9"
+pub fn constellation_summary(gps: u8, gal: u8, glo: u8, bds: u8) -> heapless::String<32> {
+    use core::fmt::Write;
+    let mut s = heapless::String::new();
+    let _ = write!(s, "GPS:{} GAL:{} GLO:{} BDS:{}", gps, gal, glo, bds);
+    s
 }
 
 impl Default for GpsData {
@@ -323,14 +661,90 @@ impl Default for GpsData {
     }
 }
 
+impl GpsData {
+    /// "GPS:12 GAL:8 GLO:5 BDS:9"
+    #[allow(dead_code)]
+    pub fn constellation_summary(&self) -> heapless::String<32> {
+        constellation_summary(self.sats_gps, self.sats_gal, self.sats_glo, self.sats_bds)
+    }
+
+    /// Fix trustworthy enough to fuse or send to the ground station
+    pub fn pdop_ok(&self) -> bool {
+        self.fix && self.sats >= 4 && self.pdop_i < GPS_PDOP_MAX_I
+    }
+
+    pub fn gps_quality(&self) -> GpsQuality {
+        if !self.fix || self.sats < 4 {
+            GpsQuality::Poor
+        } else if self.pdop_i < 200 && self.sats >= 8 {
+            GpsQuality::Excellent
+        } else if self.pdop_i < GPS_PDOP_MAX_I {
+            GpsQuality::Good
+        } else if self.pdop_i < 600 {
+            GpsQuality::Moderate
+        } else {
+            GpsQuality::Poor
+        }
+    }
+
+    /// NAV-PVT received recently → it owns position/velocity, NMEA only fills the rest
+    pub fn pvt_active(&self) -> bool {
+        self.nav_pvt_count > 0
+            && self.last_byte_ms.wrapping_sub(self.last_pvt_ms) < UBX_PVT_TIMEOUT_MS
+    }
+
+    fn apply_nav_pvt(&mut self, pvt: NavPvtData) {
+        let now = self.last_byte_ms;
+        if self.nav_pvt_count > 0 {
+            self.nav_interval_ms = now.wrapping_sub(self.last_pvt_ms);
+        }
+        self.nav_pvt = pvt;
+        self.nav_pvt_count = self.nav_pvt_count.wrapping_add(1);
+        self.last_pvt_ms = now;
+        self.last_nav_msg_ms = now;
+
+        self.fix = pvt.fix_ok && pvt.fix_type >= 2;
+        self.sats = pvt.num_sv;
+        self.lat = pvt.lat_deg7 as f32 * 1e-7;
+        self.lon = pvt.lon_deg7 as f32 * 1e-7;
+        self.alt = pvt.h_msl_mm as f32 * 1e-3;
+        self.alt_msl = self.alt;
+        self.alt_ellipsoid = pvt.height_mm as f32 * 1e-3;
+        self.geoid_sep = self.alt_ellipsoid - self.alt_msl;
+        let gspeed_cms = pvt.g_speed_mm_s.max(0) as u32 / 10;
+        self.speed_cms = gspeed_cms;
+        self.speed = gspeed_cms as f32 / 51.44; // cm/s → knots
+        self.course = pvt.head_mot_deg5 as f32 * 1e-5;
+        self.ground_course = (self.course * 10.0) as u16;
+        self.pdop_i = pvt.p_dop;
+    }
+}
+
 /// Timeout before we declare lost communication (Betaflight: 2500 ms)
 pub const GPS_TIMEOUT_MS: u32 = 2500;
 
+/// Baud rates tried in order when nothing valid arrives (index 0 = boot default)
+pub const BAUD_CANDIDATES: [u32; 5] = [115200, 9600, 38400, 57600, 230400];
+
+/// No valid NMEA for this long at the current rate → start probing
+const GPS_BAUD_SILENCE_MS: u32 = 3000;
+
+/// Time spent listening on each candidate rate
+const GPS_BAUD_PROBE_MS: u32 = 1000;
+
 pub struct NmeaParser {
     buffer: heapless::String<128>,
     pub data: GpsData,
     // internal GSV accumulator
     gsv_sv_index: u8,
+    // binary UBX frames interleaved with NMEA on the same UART
+    ubx: UbxFramer,
+}
+
+impl Default for NmeaParser {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl NmeaParser {
@@ -339,6 +753,7 @@ impl NmeaParser {
             buffer: heapless::String::new(),
             data: GpsData::default(),
             gsv_sv_index: 0,
+            ubx: UbxFramer::new(),
         }
     }
 
@@ -353,12 +768,29 @@ impl NmeaParser {
             GpsState::Unknown => {
                 if bytes_this_tick > 0 {
                     self.data.state = GpsState::DetectBaud;
+                    self.data.state_since_ms = now_ms;
+                    self.data.probe_sentences = self.data.sentences_rx;
+                } else if now_ms.wrapping_sub(self.data.state_since_ms) > GPS_BAUD_SILENCE_MS {
+                    self.data.state = GpsState::BaudProbing { tries: 0 };
+                    self.try_next_baud(now_ms);
                 }
             }
             GpsState::DetectBaud => {
                 // We received valid NMEA → move to Initialised
-                if self.data.sentences_rx > 0 {
+                if self.data.sentences_rx != self.data.probe_sentences {
+                    self.data.state = GpsState::Initialised;
+                } else if now_ms.wrapping_sub(self.data.state_since_ms) > GPS_BAUD_SILENCE_MS {
+                    // Bytes but never a valid sentence → wrong baud rate
+                    self.data.state = GpsState::BaudProbing { tries: 0 };
+                    self.try_next_baud(now_ms);
+                }
+            }
+            GpsState::BaudProbing { .. } => {
+                if self.data.sentences_rx != self.data.probe_sentences {
+                    // NMEA framing OK at this rate → lock it in
                     self.data.state = GpsState::Initialised;
+                } else if now_ms.wrapping_sub(self.data.state_since_ms) >= GPS_BAUD_PROBE_MS {
+                    self.try_next_baud(now_ms);
                 }
             }
             GpsState::Initialised => {
@@ -381,14 +813,51 @@ impl NmeaParser {
                 // Any new bytes → try again
                 if bytes_this_tick > 0 {
                     self.data.state = GpsState::DetectBaud;
+                    self.data.state_since_ms = now_ms;
+                    self.data.probe_sentences = self.data.sentences_rx;
                 }
             }
         }
     }
 
+    /// Move to the next candidate in BAUD_CANDIDATES and return it.
+    /// The caller must reconfigure the UART to `baudrate()` afterwards.
+    pub fn try_next_baud(&mut self, now_ms: u32) -> u32 {
+        self.data.baud_idx = ((self.data.baud_idx as usize + 1) % BAUD_CANDIDATES.len()) as u8;
+        if let GpsState::BaudProbing { tries } = self.data.state {
+            self.data.state = GpsState::BaudProbing { tries: tries.saturating_add(1) };
+        }
+        self.data.state_since_ms = now_ms;
+        self.data.probe_sentences = self.data.sentences_rx;
+        // Half a sentence received at the old rate is garbage
+        self.buffer.clear();
+        self.baudrate()
+    }
+
+    /// UART rate the parser currently expects
+    pub fn baudrate(&self) -> u32 {
+        BAUD_CANDIDATES[self.data.baud_idx as usize]
+    }
+
+    /// Restart detection at `BAUD_CANDIDATES[0]` (after asking the module to switch to it)
+    pub fn reset_baud(&mut self, now_ms: u32) {
+        self.data.baud_idx = 0;
+        self.data.state = GpsState::Unknown;
+        self.data.state_since_ms = now_ms;
+        self.buffer.clear();
+    }
+
     /// Process incoming bytes from UART
     pub fn push_data(&mut self, data: &[u8]) {
         for &b in data {
+            // UBX sync (0xB5) can never appear in ASCII NMEA → divert to framer
+            if self.ubx.in_frame() || b == UBX_SYNC1 {
+                if let Some(frame) = self.ubx.push(b) {
+                    handle_ubx_frame(&mut self.data, &frame);
+                }
+                continue;
+            }
+
             if b == b'$' {
                 self.buffer.clear();
             }
@@ -445,12 +914,8 @@ impl NmeaParser {
             NmeaFrame::Rmc => self.parse_rmc(s),
             NmeaFrame::Gsa => self.parse_gsa(s),
             NmeaFrame::Gsv => self.parse_gsv(s),
-            NmeaFrame::Vtg => {
-                self.data.vtg_count = self.data.vtg_count.wrapping_add(1);
-            }
-            NmeaFrame::Gll => {
-                self.data.gll_count = self.data.gll_count.wrapping_add(1);
-            }
+            NmeaFrame::Vtg => self.parse_vtg(s),
+            NmeaFrame::Gll => self.parse_gll(s),
             NmeaFrame::None => {
                 self.data.unknown_count = self.data.unknown_count.wrapping_add(1);
             }
@@ -460,6 +925,9 @@ impl NmeaParser {
     // ────── GGA ──────
     fn parse_gga(&mut self, s: &str) {
         self.data.gga_count = self.data.gga_count.wrapping_add(1);
+        if self.data.pvt_active() {
+            return; // NAV-PVT owns position, fix and nav timing
+        }
         // $xxGGA,time,lat,NS,lon,EW,qual,sats,hdop,alt,M,geoid,M,…*CS
         let mut parts = s.split(',');
         parts.next(); // ID
@@ -484,8 +952,13 @@ impl NmeaParser {
         // HDOP (field 8)
         let hdop_str = parts.next().unwrap_or("");
 
-        // Alt (field 9)
+        // Alt (field 9) + unit (field 10)
         let alt_str = parts.next().unwrap_or("");
+        parts.next();
+
+        // Geoid separation (field 11)
+        let sep_raw = parts.next().unwrap_or("");
+        let sep_str = sep_raw.split('*').next().unwrap_or(sep_raw);
 
         // Parse fix quality
         if let Ok(q) = u8::from_str(qual_str) {
@@ -505,28 +978,23 @@ impl NmeaParser {
             self.data.hdop_i = (h * 100.0) as u16;
         }
 
+        if let Ok(g) = f32::from_str(sep_str) {
+            self.data.geoid_sep = g;
+        }
+
         if let Ok(a) = f32::from_str(alt_str) {
             self.data.alt = a;
+            // NMEA 0183: GGA altitude is already referenced to the geoid (MSL);
+            // the separation only gives us the ellipsoidal height (h = H + N).
+            self.data.alt_msl = a;
+            self.data.alt_ellipsoid = a + self.data.geoid_sep;
         }
 
         // Lat/Lon (Betaflight GPS_coord_to_degrees style, but as f32)
-        if let Ok(l_val) = f32::from_str(lat_raw) {
-            let lat_deg = (l_val / 100.0).floor();
-            let lat_min = l_val - (lat_deg * 100.0);
-            let mut latitude = lat_deg + (lat_min / 60.0);
-            if ns == "S" {
-                latitude = -latitude;
-            }
+        if let Some(latitude) = nmea_coord_to_degrees(lat_raw, ns == "S") {
             self.data.lat = latitude;
         }
-
-        if let Ok(o_val) = f32::from_str(lon_raw) {
-            let lon_deg = (o_val / 100.0).floor();
-            let lon_min = o_val - (lon_deg * 100.0);
-            let mut longitude = lon_deg + (lon_min / 60.0);
-            if ew == "W" {
-                longitude = -longitude;
-            }
+        if let Some(longitude) = nmea_coord_to_degrees(lon_raw, ew == "W") {
             self.data.lon = longitude;
         }
 
@@ -576,6 +1044,9 @@ impl NmeaParser {
             self.data.utc_date = d;
         }
 
+        if self.data.pvt_active() {
+            return; // NAV-PVT owns speed and course
+        }
         if let Ok(spd) = f32::from_str(speed_raw) {
             self.data.speed = spd; // Knots
             // cm/s: (knots × 5144) / 1000  (Betaflight formula)
@@ -587,6 +1058,72 @@ impl NmeaParser {
         }
     }
 
+    // ────── VTG ──────
+    fn parse_vtg(&mut self, s: &str) {
+        self.data.vtg_count = self.data.vtg_count.wrapping_add(1);
+        // $xxVTG,course_true,T,course_mag,M,speed_kn,N,speed_kmh,K,mode*CS
+        // RMC carries the same data: only use VTG when no RMC was ever received
+        if self.data.rmc_count > 0 {
+            return;
+        }
+        let mut parts = s.split(',');
+        parts.next(); // ID
+
+        // True course (field 1)
+        let course_raw = parts.next().unwrap_or("");
+
+        // Skip T, magnetic course, M, speed knots, N (fields 2-6)
+        for _ in 0..5 {
+            parts.next();
+        }
+
+        // Speed km/h (field 7)
+        let kmh_raw = parts.next().unwrap_or("");
+
+        if let Ok(crs) = f32::from_str(course_raw) {
+            self.data.course = crs;
+            self.data.ground_course = (crs * 10.0) as u16; // deg×10
+        }
+        if let Ok(kmh) = f32::from_str(kmh_raw) {
+            self.data.speed = kmh / 1.852; // Knots
+            self.data.speed_cms = (kmh * 100.0 / 3.6) as u32;
+        }
+    }
+
+    // ────── GLL ──────
+    fn parse_gll(&mut self, s: &str) {
+        self.data.gll_count = self.data.gll_count.wrapping_add(1);
+        // $xxGLL,lat,NS,lon,EW,time,status,mode*CS
+        let mut parts = s.split(',');
+        parts.next(); // ID
+
+        let lat_raw = parts.next().unwrap_or("");
+        let ns = parts.next().unwrap_or("");
+        let lon_raw = parts.next().unwrap_or("");
+        let ew = parts.next().unwrap_or("");
+        let _time_str = parts.next().unwrap_or("");
+
+        // Status (field 6) — A=active, V=void (may carry *CS if mode is absent)
+        let status_raw = parts.next().unwrap_or("");
+        let status = status_raw.split('*').next().unwrap_or(status_raw);
+
+        if status == "V" {
+            self.data.fix = false;
+        }
+
+        // GGA has altitude + fix quality: GLL position is only a fallback
+        if self.data.gga_count > 0 || status != "A" {
+            return;
+        }
+        self.data.fix = true;
+        if let Some(latitude) = nmea_coord_to_degrees(lat_raw, ns == "S") {
+            self.data.lat = latitude;
+        }
+        if let Some(longitude) = nmea_coord_to_degrees(lon_raw, ew == "W") {
+            self.data.lon = longitude;
+        }
+    }
+
     // ────── GSA ──────
     fn parse_gsa(&mut self, s: &str) {
         self.data.gsa_count = self.data.gsa_count.wrapping_add(1);
@@ -665,6 +1202,13 @@ impl NmeaParser {
         // accumulation overflow when GPS GSV sentence is missed.
         if msg_num == 1 {
             if let Ok(n) = u8::from_str(siv_str) {
+                match gnss {
+                    GnssSystem::Gps => self.data.sats_gps = n,
+                    GnssSystem::Glonass => self.data.sats_glo = n,
+                    GnssSystem::Galileo => self.data.sats_gal = n,
+                    GnssSystem::Beidou => self.data.sats_bds = n,
+                    _ => {}
+                }
                 let since_reset = self.data.last_byte_ms.wrapping_sub(self.data.last_gsv_reset_ms);
                 if gnss == GnssSystem::Gps || since_reset > 500 {
                     // Start of new full cycle
@@ -685,8 +1229,8 @@ impl NmeaParser {
                 Some(s) => s,
                 None => break,
             };
-            let _elev_s = parts.next().unwrap_or("");
-            let _azim_s = parts.next().unwrap_or("");
+            let elev_s = parts.next().unwrap_or("");
+            let azim_s = parts.next().unwrap_or("");
             let cno_s_raw = parts.next().unwrap_or("");
             // cno field may contain *checksum on last satellite
             let cno_s = cno_s_raw.split('*').next().unwrap_or(cno_s_raw);
@@ -705,6 +1249,8 @@ impl NmeaParser {
                 svid,
                 cno: u8::from_str(cno_s).unwrap_or(0),
                 gnss,
+                elevation_deg: u8::from_str(elev_s).unwrap_or(0),
+                azimuth_deg: u16::from_str(azim_s).unwrap_or(0),
             };
             self.gsv_sv_index += 1;
             self.data.sv_count = self.gsv_sv_index;
@@ -712,6 +1258,32 @@ impl NmeaParser {
     }
 }
 
+fn handle_ubx_frame(data: &mut GpsData, frame: &UbxFrame<'_>) {
+    match (frame.class, frame.id) {
+        (UBX_CLASS_NAV, UBX_ID_NAV_PVT) => {
+            if let Some(pvt) = parse_ubx_nav_pvt(frame.payload) {
+                data.apply_nav_pvt(pvt);
+            }
+        }
+        (UBX_CLASS_ACK, UBX_ID_ACK_ACK) => {
+            data.ubx_ack_count = data.ubx_ack_count.wrapping_add(1);
+        }
+        (UBX_CLASS_ACK, UBX_ID_ACK_NAK) => {
+            data.ubx_nak_count = data.ubx_nak_count.wrapping_add(1);
+        }
+        _ => {}
+    }
+}
+
+/// NMEA `ddmm.mmmm` / `dddmm.mmmm` → signed decimal degrees
+fn nmea_coord_to_degrees(raw: &str, negative: bool) -> Option<f32> {
+    let val = f32::from_str(raw).ok()?;
+    let deg = (val / 100.0).floor();
+    let min = val - (deg * 100.0);
+    let dec = deg + (min / 60.0);
+    Some(if negative { -dec } else { dec })
+}
+
 fn verify_checksum(s: &str) -> bool {
     if let Some((content, check_str)) = s.split_once('*') {
         let content = content.strip_prefix('$').unwrap_or(content);
@@ -727,3 +1299,297 @@ fn verify_checksum(s: &str) -> bool {
     }
     false
 }
+
+// ─── Position history (post-flight trajectory) ───
+
+/// Mean Earth radius (m) used by the Haversine formula
+const EARTH_RADIUS_M: f32 = 6_371_000.0;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GpsPoint {
+    pub lat: f32,   // Decimal Degrees
+    pub lon: f32,   // Decimal Degrees
+    pub alt: f32,   // Metres (MSL)
+    pub ts_ms: u32,
+}
+
+impl GpsPoint {
+    pub const ZERO: Self = Self { lat: 0.0, lon: 0.0, alt: 0.0, ts_ms: 0 };
+}
+
+/// Great-circle distance between two positions (m), decimal degrees, ignoring altitude.
+/// f32 lat/lon resolution is ~1 m at these magnitudes; fine below ~10 km.
+pub fn haversine_m(lat1: f32, lon1: f32, lat2: f32, lon2: f32) -> f32 {
+    let phi1 = lat1.to_radians();
+    let phi2 = lat2.to_radians();
+    let dlat = phi2 - phi1;
+    let dlon = (lon2 - lon1).to_radians();
+
+    let s_dlat = (dlat * 0.5).sin();
+    let s_dlon = (dlon * 0.5).sin();
+    let h = s_dlat * s_dlat + phi1.cos() * phi2.cos() * s_dlon * s_dlon;
+    2.0 * EARTH_RADIUS_M * h.sqrt().min(1.0).asin()
+}
+
+/// Initial bearing from point 1 to point 2 (degrees, 0 = north, clockwise, 0–360)
+#[allow(dead_code)]
+pub fn bearing_deg(lat1: f32, lon1: f32, lat2: f32, lon2: f32) -> f32 {
+    let phi1 = lat1.to_radians();
+    let phi2 = lat2.to_radians();
+    let dlon = (lon2 - lon1).to_radians();
+
+    let y = dlon.sin() * phi2.cos();
+    let x = phi1.cos() * phi2.sin() - phi1.sin() * phi2.cos() * dlon.cos();
+    let deg = y.atan2(x).to_degrees();
+    if deg < 0.0 { deg + 360.0 } else { deg }
+}
+
+// ─── Dead reckoning (short fix outages) ───
+
+/// Longest outage bridged by dead reckoning; past this the last fix is held
+pub const GPS_DR_MAX_MS: u32 = 10_000;
+
+/// Last fix + ground velocity, propagated forward while the fix is lost
+/// (tunnel, tree cover). Flat-Earth step, fine over a few hundred metres.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GpsDeadReckoning {
+    pub last_lat: f32,
+    pub last_lon: f32,
+    pub last_speed_cms: u32,
+    pub last_course_deg: f32,
+    pub last_fix_ms: u32,
+    valid: bool,
+}
+
+impl GpsDeadReckoning {
+    pub const fn new() -> Self {
+        Self {
+            last_lat: 0.0,
+            last_lon: 0.0,
+            last_speed_cms: 0,
+            last_course_deg: 0.0,
+            last_fix_ms: 0,
+            valid: false,
+        }
+    }
+
+    /// Record a good fix (call on every fixed sample)
+    pub fn set_fix(&mut self, lat: f32, lon: f32, speed_cms: u32, course_deg: f32, now_ms: u32) {
+        self.last_lat = lat;
+        self.last_lon = lon;
+        self.last_speed_cms = speed_cms;
+        self.last_course_deg = course_deg;
+        self.last_fix_ms = now_ms;
+        self.valid = true;
+    }
+
+    /// True while a fix has been seen less than GPS_DR_MAX_MS ago
+    pub fn is_active(&self, now_ms: u32) -> bool {
+        self.valid && now_ms.wrapping_sub(self.last_fix_ms) < GPS_DR_MAX_MS
+    }
+
+    /// Estimated (lat, lon): last fix moved `speed × elapsed` along the last
+    /// course. Beyond GPS_DR_MAX_MS the last fix is returned unchanged.
+    pub fn update(&mut self, now_ms: u32) -> (f32, f32) {
+        if !self.is_active(now_ms) {
+            return (self.last_lat, self.last_lon);
+        }
+        let elapsed_s = now_ms.wrapping_sub(self.last_fix_ms) as f32 * 1e-3;
+        let dist_m = self.last_speed_cms as f32 * 0.01 * elapsed_s;
+        let course = self.last_course_deg.to_radians();
+        let north_m = dist_m * course.cos();
+        let east_m = dist_m * course.sin();
+
+        let dlat = (north_m / EARTH_RADIUS_M).to_degrees();
+        let cos_lat = self.last_lat.to_radians().cos().max(0.01);
+        let dlon = (east_m / (EARTH_RADIUS_M * cos_lat)).to_degrees();
+        (self.last_lat + dlat, self.last_lon + dlon)
+    }
+}
+
+/// Fixed-size ring of the last N positions (no heap, overwrites oldest)
+pub struct PositionRing<const N: usize> {
+    points: [GpsPoint; N],
+    idx: usize,      // next write slot
+    is_full: bool,
+}
+
+impl<const N: usize> Default for PositionRing<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> PositionRing<N> {
+    pub const fn new() -> Self {
+        Self {
+            points: [GpsPoint::ZERO; N],
+            idx: 0,
+            is_full: false,
+        }
+    }
+
+    pub fn push(&mut self, p: GpsPoint) {
+        self.points[self.idx] = p;
+        self.idx += 1;
+        if self.idx >= N {
+            self.idx = 0;
+            self.is_full = true;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        if self.is_full { N } else { self.idx }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.idx = 0;
+        self.is_full = false;
+    }
+
+    /// i-th stored point, 0 = oldest
+    pub fn get(&self, i: usize) -> Option<GpsPoint> {
+        if i >= self.len() {
+            return None;
+        }
+        let start = if self.is_full { self.idx } else { 0 };
+        Some(self.points[(start + i) % N])
+    }
+
+    /// Oldest-to-newest iterator
+    pub fn iter(&self) -> impl Iterator<Item = GpsPoint> + '_ {
+        (0..self.len()).filter_map(move |i| self.get(i))
+    }
+
+    /// Sum of Haversine distances between consecutive points (m)
+    pub fn distance_traveled_m(&self) -> f32 {
+        let mut total = 0.0f32;
+        let mut prev: Option<GpsPoint> = None;
+        for p in self.iter() {
+            if let Some(q) = prev {
+                total += haversine_m(q.lat, q.lon, p.lat, p.lon);
+            }
+            prev = Some(p);
+        }
+        total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `$<body>*CS\r\n` with the XOR checksum of `body`
+    fn sentence(body: &str) -> heapless::String<128> {
+        use core::fmt::Write;
+        let cs = body.bytes().fold(0u8, |acc, b| acc ^ b);
+        let mut s = heapless::String::new();
+        let _ = write!(s, "${}*{:02X}\r\n", body, cs);
+        s
+    }
+
+    fn parse(lines: &[&str]) -> NmeaParser {
+        let mut parser = NmeaParser::new();
+        for body in lines {
+            parser.push_data(sentence(body).as_bytes());
+        }
+        parser
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    // ── VTG ──
+
+    #[test]
+    fn vtg_valid() {
+        let p = parse(&["GPVTG,054.7,T,034.4,M,005.5,N,010.2,K,A"]);
+        assert_eq!(p.data.vtg_count, 1);
+        assert!(close(p.data.course, 54.7));
+        assert_eq!(p.data.ground_course, 547);
+        assert!(close(p.data.speed, 10.2 / 1.852));
+        assert_eq!(p.data.speed_cms, 283);
+    }
+
+    #[test]
+    fn vtg_empty_fields_keep_previous_values() {
+        let p = parse(&["GPVTG,054.7,T,034.4,M,005.5,N,010.2,K,A", "GPVTG,,T,,M,,N,,K,N"]);
+        assert_eq!(p.data.vtg_count, 2);
+        assert_eq!(p.data.checksum_errors, 0);
+        assert!(close(p.data.course, 54.7));
+        assert_eq!(p.data.speed_cms, 283);
+    }
+
+    #[test]
+    fn vtg_bad_checksum_is_dropped() {
+        let mut p = NmeaParser::new();
+        p.push_data(b"$GPVTG,054.7,T,034.4,M,005.5,N,010.2,K,A*00\r\n");
+        assert_eq!(p.data.checksum_errors, 1);
+        assert_eq!(p.data.vtg_count, 0);
+        assert_eq!(p.data.course, 0.0);
+        assert_eq!(p.data.speed_cms, 0);
+    }
+
+    #[test]
+    fn vtg_ignored_once_rmc_seen() {
+        let p = parse(&[
+            "GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W",
+            "GPVTG,054.7,T,034.4,M,005.5,N,010.2,K,A",
+        ]);
+        assert_eq!(p.data.vtg_count, 1);
+        assert!(close(p.data.course, 84.4));
+    }
+
+    // ── GLL ──
+
+    #[test]
+    fn gll_active_north_east() {
+        let p = parse(&["GPGLL,4807.038,N,01131.000,E,123519,A,A"]);
+        assert_eq!(p.data.gll_count, 1);
+        assert!(p.data.fix);
+        assert!(close(p.data.lat, 48.0 + 7.038 / 60.0));
+        assert!(close(p.data.lon, 11.0 + 31.0 / 60.0));
+    }
+
+    #[test]
+    fn gll_south_west_are_negative() {
+        let p = parse(&["GPGLL,3351.000,S,15112.000,W,010203,A,A"]);
+        assert!(p.data.fix);
+        assert!(close(p.data.lat, -(33.0 + 51.0 / 60.0)));
+        assert!(close(p.data.lon, -(151.0 + 12.0 / 60.0)));
+    }
+
+    #[test]
+    fn gll_status_without_mode_field() {
+        // NMEA 2.x: no mode field, the checksum follows the status directly
+        let p = parse(&["GPGLL,4807.038,N,01131.000,E,123519,A"]);
+        assert!(p.data.fix);
+        assert!(close(p.data.lat, 48.0 + 7.038 / 60.0));
+    }
+
+    #[test]
+    fn gll_void_clears_fix_keeps_position() {
+        let p = parse(&[
+            "GPGLL,4807.038,N,01131.000,E,123519,A,A",
+            "GPGLL,3351.000,S,15112.000,W,123520,V,N",
+        ]);
+        assert_eq!(p.data.gll_count, 2);
+        assert!(!p.data.fix);
+        assert!(close(p.data.lat, 48.0 + 7.038 / 60.0));
+        assert!(close(p.data.lon, 11.0 + 31.0 / 60.0));
+    }
+
+    #[test]
+    fn gll_position_ignored_once_gga_seen() {
+        let p = parse(&[
+            "GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,",
+            "GPGLL,3351.000,S,15112.000,W,123520,A,A",
+        ]);
+        assert!(close(p.data.lat, 48.0 + 7.038 / 60.0));
+    }
+}
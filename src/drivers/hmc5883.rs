@@ -1,13 +1,113 @@
 use embassy_stm32::i2c::{I2c, Instance, RxDma, TxDma};
 use embassy_time::Timer;
+use micromath::F32Ext;
 
 pub const HMC5883L_ADDR: u8 = 0x1E;
 
-pub struct Hmc5883;
+/// Field per LSB at the default ±1.3 Ga gain (1090 LSB/Ga, 1 Ga = 100 µT)
+pub const HMC5883_UT_PER_LSB: f32 = 100.0 / 1090.0;
+
+/// Configuration Register B gain (GN2..0) — full-scale range in gauss.
+/// Raise it if the field saturates (−4096 reads) near motors or at high latitude.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Hmc5883Gain {
+    Ga0_88,
+    #[default]
+    Ga1_3,
+    Ga1_9,
+    Ga2_5,
+    Ga4_0,
+    Ga4_7,
+    Ga5_6,
+    Ga8_1,
+}
+
+impl Hmc5883Gain {
+    /// Value for Configuration Register B (GN in bits 7:5)
+    pub fn register_bits(&self) -> u8 {
+        (*self as u8) << 5
+    }
+
+    /// Datasheet digital resolution
+    pub fn lsb_per_gauss(&self) -> f32 {
+        match self {
+            Self::Ga0_88 => 1370.0,
+            Self::Ga1_3 => 1090.0,
+            Self::Ga1_9 => 820.0,
+            Self::Ga2_5 => 660.0,
+            Self::Ga4_0 => 440.0,
+            Self::Ga4_7 => 390.0,
+            Self::Ga5_6 => 330.0,
+            Self::Ga8_1 => 230.0,
+        }
+    }
+}
+
+/// (window variance, throttle) pairs kept for the interference correlation
+const INTERF_HISTORY: usize = 16;
+/// Pearson correlation above which the field is considered throttle-driven
+const INTERF_CORR_THRESHOLD: f32 = 0.7;
+
+/// HMC5883L on a DMA-backed async I2C bus (no executor blocking during transfers)
+pub struct Hmc5883 {
+    gain: Hmc5883Gain,
+    var_hist: [f32; INTERF_HISTORY],
+    thr_hist: [f32; INTERF_HISTORY],
+    hist_idx: usize,
+    hist_len: usize,
+}
 
 impl Hmc5883 {
     pub fn new() -> Self {
-        Self
+        Self {
+            gain: Hmc5883Gain::default(),
+            var_hist: [0.0; INTERF_HISTORY],
+            thr_hist: [0.0; INTERF_HISTORY],
+            hist_idx: 0,
+            hist_len: 0,
+        }
+    }
+
+    /// ESC switching interference check, once per window of N samples.
+    ///
+    /// The summed per-axis variance of `samples` is stored with `throttle` (0–1);
+    /// returns true when the Pearson correlation between the two over the last
+    /// INTERF_HISTORY windows exceeds INTERF_CORR_THRESHOLD.
+    pub fn detect_interference<const N: usize>(&mut self, samples: &[[i16; 3]; N], throttle: f32) -> bool {
+        if N < 2 {
+            return false;
+        }
+        let mut var = 0.0f32;
+        for ax in 0..3 {
+            let mean = samples.iter().map(|s| s[ax] as f32).sum::<f32>() / N as f32;
+            var += samples.iter().map(|s| { let d = s[ax] as f32 - mean; d * d }).sum::<f32>()
+                / (N - 1) as f32;
+        }
+        self.var_hist[self.hist_idx] = var;
+        self.thr_hist[self.hist_idx] = throttle;
+        self.hist_idx = (self.hist_idx + 1) % INTERF_HISTORY;
+        self.hist_len = (self.hist_len + 1).min(INTERF_HISTORY);
+        if self.hist_len < INTERF_HISTORY {
+            return false;
+        }
+
+        let n = INTERF_HISTORY as f32;
+        let mv = self.var_hist.iter().sum::<f32>() / n;
+        let mt = self.thr_hist.iter().sum::<f32>() / n;
+        let (mut cov, mut sv, mut st) = (0.0f32, 0.0f32, 0.0f32);
+        for i in 0..INTERF_HISTORY {
+            let dv = self.var_hist[i] - mv;
+            let dt = self.thr_hist[i] - mt;
+            cov += dv * dt;
+            sv += dv * dv;
+            st += dt * dt;
+        }
+        // Constant throttle → no evidence either way
+        if sv < 1e-6 || st < 1e-6 {
+            return false;
+        }
+        cov / (sv * st).sqrt() > INTERF_CORR_THRESHOLD
     }
 
     pub async fn init<T: Instance, Tx: TxDma<T>, Rx: RxDma<T>>(
@@ -15,25 +115,54 @@ impl Hmc5883 {
         i2c: &mut I2c<'_, T, Tx, Rx>,
     ) -> Result<(), embassy_stm32::i2c::Error> {
         // Configuration Register A: 8-average, 15Hz default, normal measurement
-        i2c.blocking_write(HMC5883L_ADDR, &[0x00, 0x70])?;
+        i2c.write(HMC5883L_ADDR, &[0x00, 0x70]).await?;
 
-        // Configuration Register B: Gain 1.3 Ga (default)
-        i2c.blocking_write(HMC5883L_ADDR, &[0x01, 0x20])?;
+        // Configuration Register B: gain (±1.3 Ga unless set_gain was called)
+        i2c.write(HMC5883L_ADDR, &[0x01, self.gain.register_bits()]).await?;
 
         // Mode Register: Continuous-measurement mode
-        i2c.blocking_write(HMC5883L_ADDR, &[0x02, 0x00])?;
+        i2c.write(HMC5883L_ADDR, &[0x02, 0x00]).await?;
 
         Timer::after_millis(10).await;
         Ok(())
     }
 
+    /// Change the measurement range. The chip applies it from the second
+    /// measurement on, so one sample after the call still uses the old gain.
+    #[allow(dead_code)]
+    pub async fn set_gain<T: Instance, Tx: TxDma<T>, Rx: RxDma<T>>(
+        &mut self,
+        i2c: &mut I2c<'_, T, Tx, Rx>,
+        gain: Hmc5883Gain,
+    ) -> Result<(), embassy_stm32::i2c::Error> {
+        i2c.write(HMC5883L_ADDR, &[0x01, gain.register_bits()]).await?;
+        self.gain = gain;
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn gain(&self) -> Hmc5883Gain {
+        self.gain
+    }
+
+    /// Field in gauss [x, y, z], scaled by the current gain
+    #[allow(dead_code)]
+    pub async fn read_mag_gauss<T: Instance, Tx: TxDma<T>, Rx: RxDma<T>>(
+        &mut self,
+        i2c: &mut I2c<'_, T, Tx, Rx>,
+    ) -> Result<[f32; 3], embassy_stm32::i2c::Error> {
+        let raw = self.read_mag(i2c).await?;
+        let k = 1.0 / self.gain.lsb_per_gauss();
+        Ok([raw[0] as f32 * k, raw[1] as f32 * k, raw[2] as f32 * k])
+    }
+
     pub async fn read_mag<T: Instance, Tx: TxDma<T>, Rx: RxDma<T>>(
         &mut self,
         i2c: &mut I2c<'_, T, Tx, Rx>,
     ) -> Result<[i16; 3], embassy_stm32::i2c::Error> {
         let mut data = [0u8; 6];
         // Read starting from 0x03 (Data Output X MSB Register)
-        i2c.blocking_write_read(HMC5883L_ADDR, &[0x03], &mut data)?;
+        i2c.write_read(HMC5883L_ADDR, &[0x03], &mut data).await?;
 
         // Note: HMC5883L layout is X, Z, Y
         let x = i16::from_be_bytes([data[0], data[1]]);
@@ -1,27 +1,70 @@
 use embassy_stm32::dma::NoDma;
 use embassy_stm32::gpio::{AnyPin, Output};
-use embassy_stm32::spi::{Error, Instance, Spi};
+use embassy_stm32::spi::{Error as SpiError, Instance, Spi};
 use embassy_time::{Duration, Timer};
 
+const REG_WHO_AM_I: u8 = 0x75;
+/// Bank select, reachable from every bank
+const REG_BANK_SEL: u8 = 0x76;
+const ICM42688P_WHO_AM_I: u8 = 0x47;
+
+#[allow(dead_code)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum ImuError {
+    Spi(SpiError),
+    /// WHO_AM_I mismatch (0xFF / 0x00: nothing answering on the bus)
+    WrongDevice { found: u8 },
+    Timeout,
+}
+
+impl From<SpiError> for ImuError {
+    fn from(e: SpiError) -> Self {
+        ImuError::Spi(e)
+    }
+}
+
+// Interrupt configuration registers (bank 0)
+const REG_INT_CONFIG: u8 = 0x14;
+const REG_INT_CONFIG0: u8 = 0x63;
+const REG_INT_CONFIG1: u8 = 0x64;
+const REG_INT_SOURCE0: u8 = 0x65;
+
+/// INT_CONFIG: INT1 latched, push-pull, active high
+const INT1_LATCHED_PP_HIGH: u8 = 0b0000_0111;
+/// INT_CONFIG0: UI_DRDY_INT_CLEAR = 0b10 → cleared by the sensor register read
+const UI_DRDY_CLEAR_ON_DATA_READ: u8 = 0b10 << 4;
+/// INT_SOURCE0: UI_DRDY_INT1_EN
+const UI_DRDY_INT1_EN: u8 = 1 << 3;
+
 pub struct Icm42688<'d, T: Instance> {
     spi: Spi<'d, T, NoDma, NoDma>,
     cs: Output<'d, AnyPin>,
+    bank: u8, // last value written to REG_BANK_SEL (0 after reset)
 }
 
 impl<'d, T: Instance> Icm42688<'d, T> {
     pub fn new(spi: Spi<'d, T, NoDma, NoDma>, cs: Output<'d, AnyPin>) -> Self {
-        Self { spi, cs }
+        Self { spi, cs, bank: 0 }
+    }
+
+    async fn write_reg(&mut self, reg: u8, value: u8) -> Result<(), ImuError> {
+        self.write_reg_blocking(reg, value)
     }
 
-    async fn write_reg(&mut self, reg: u8, value: u8) -> Result<(), Error> {
+    async fn read_reg(&mut self, reg: u8) -> Result<u8, ImuError> {
+        self.read_reg_blocking(reg)
+    }
+
+    /// Register accessors usable inside `with_bank` closures
+    fn write_reg_blocking(&mut self, reg: u8, value: u8) -> Result<(), ImuError> {
         let buf = [reg & 0x7F, value];
         self.cs.set_low();
         let res = self.spi.blocking_write(&buf);
         self.cs.set_high();
-        res
+        Ok(res?)
     }
 
-    async fn read_reg(&mut self, reg: u8) -> Result<u8, Error> {
+    fn read_reg_blocking(&mut self, reg: u8) -> Result<u8, ImuError> {
         let tx = [reg | 0x80, 0x00];
         let mut rx = [0u8; 2];
 
@@ -33,13 +76,50 @@ impl<'d, T: Instance> Icm42688<'d, T> {
         Ok(rx[1])
     }
 
-    pub async fn init(&mut self) -> Result<(), Error> {
+    // ── Register banks ───────────────────────────────────────────────────────
+    //  Bank 0 holds the UI registers used in flight; banks 1–4 hold self-test,
+    //  AAF and APEX configuration. Every other method assumes bank 0.
+
+    #[allow(dead_code)]
+    pub async fn select_bank(&mut self, bank: u8) -> Result<(), ImuError> {
+        self.select_bank_blocking(bank)
+    }
+
+    fn select_bank_blocking(&mut self, bank: u8) -> Result<(), ImuError> {
+        let bank = bank & 0x07;
+        self.write_reg_blocking(REG_BANK_SEL, bank)?;
+        self.bank = bank;
+        Ok(())
+    }
+
+    /// Run `f` with `bank` selected, then restore the previous bank — also
+    /// when `f` fails, so an error never leaves the driver off bank 0.
+    #[allow(dead_code)]
+    pub fn with_bank<R, F: FnOnce(&mut Self) -> Result<R, ImuError>>(
+        &mut self,
+        bank: u8,
+        f: F,
+    ) -> Result<R, ImuError> {
+        let saved = self.bank;
+        self.select_bank_blocking(bank)?;
+        let res = f(self);
+        let restored = self.select_bank_blocking(saved);
+        let value = res?;
+        restored?;
+        Ok(value)
+    }
+
+    pub async fn init(&mut self) -> Result<(), ImuError> {
         // Soft reset (Device Config register 0x11, bit 0)
         self.write_reg(0x11, 0x01).await?;
+        self.bank = 0; // the reset also returns REG_BANK_SEL to 0
         Timer::after(Duration::from_millis(10)).await;
 
-        // Verify WHO_AM_I = 0x47 for ICM-42688-P
-        let _id = self.read_reg(0x75).await?;
+        // Verify WHO_AM_I = 0x47 for ICM-42688-P before configuring anything
+        let id = self.read_reg(REG_WHO_AM_I).await?;
+        if id != ICM42688P_WHO_AM_I {
+            return Err(ImuError::WrongDevice { found: id });
+        }
 
         // ── Set ODR to 1 kHz and configure DLPF ──────────────────────────────
 
@@ -70,12 +150,22 @@ impl<'d, T: Instance> Icm42688<'d, T> {
         Ok(())
     }
 
+    /// Data-ready on INT1 at the configured ODR (1 kHz). Latched until the
+    /// next `read_all`, so a missed edge is recovered by the caller's timeout.
+    pub async fn configure_drdy_interrupt(&mut self) -> Result<(), ImuError> {
+        self.write_reg(REG_INT_CONFIG, INT1_LATCHED_PP_HIGH).await?;
+        self.write_reg(REG_INT_CONFIG0, UI_DRDY_CLEAR_ON_DATA_READ).await?;
+        // INT_ASYNC_RESET must be cleared for INT1 to behave (datasheet §14.5)
+        self.write_reg(REG_INT_CONFIG1, 0x00).await?;
+        self.write_reg(REG_INT_SOURCE0, UI_DRDY_INT1_EN).await
+    }
+
     #[allow(dead_code)]
-    pub async fn read_who_am_i(&mut self) -> Result<u8, Error> {
-        self.read_reg(0x75).await
+    pub async fn read_who_am_i(&mut self) -> Result<u8, ImuError> {
+        self.read_reg(REG_WHO_AM_I).await
     }
 
-    pub async fn read_all(&mut self) -> Result<([i16; 3], [i16; 3]), Error> {
+    pub async fn read_all(&mut self) -> Result<([i16; 3], [i16; 3]), ImuError> {
         let mut tx = [0u8; 13];
         tx[0] = 0x1F | 0x80;
         let mut rx = [0u8; 13];
@@ -1,3 +1,37 @@
+/// Nominal baro measurement noise R (m²) of both vertical filters
+pub const R_BARO_NOMINAL: f32 = 50.0;
+
+/// EMA weight of the newest innovation² in the adaptive baro noise estimate
+const R_ADAPT_ALPHA: f32 = 0.05;
+/// Switch to the estimated noise once it exceeds this multiple of the nominal one
+const R_ADAPT_RATIO: f32 = 4.0;
+
+/// Apogee: minimum altitude (m, same reference as update()) to accept apogee
+const APOGEE_MIN_ALT_M: f32 = 10.0;
+/// Apogee: climb speed (m/s) that counts as "ascending"
+const APOGEE_ASCENT_VEL_MS: f32 = 0.5;
+/// Apogee: consecutive ascending updates required (20 Hz baro → 1 s of climb)
+const APOGEE_MIN_ASCENT_UPDATES: u8 = 20;
+
+/// Landing: AGL (m) and |velocity| (m/s) below these, and the accel input
+/// variance ((m/s²)²) below LAND_ACCEL_VAR_MAX (vehicle at rest on the ground)
+const LAND_ALT_M: f32 = 5.0;
+const LAND_VEL_MS: f32 = 2.0;
+const LAND_ACCEL_VAR_MAX: f32 = 0.1;
+/// EMA weight of the accel variance tracker (~100 ms at the 1 kHz predict rate)
+const ACCEL_VAR_ALPHA: f32 = 0.01;
+
+/// Baro gating: reject when NIS = y²/S exceeds this (χ², 1 DOF → 3σ)
+const NIS_THRESHOLD: f32 = 9.0;
+/// More consecutive rejections than this → sensor considered stuck / faulty
+const BARO_STUCK_REJECTS: u8 = 10;
+
+#[derive(Default, Clone, Copy)]
+pub struct KalmanDiag {
+    pub baro_fault_count: u8, // consecutive rejected baro updates
+    pub baro_stuck: bool,
+}
+
 #[derive(Default)]
 pub struct KalmanState {
     pub position: f32, // Altitude (m)
@@ -14,9 +48,23 @@ pub struct VerticalKalman {
     // Process noise covariance Q
     q: [f32; 2],
 
-    // Measurement noise covariance R
-    #[allow(dead_code)]
+    // Measurement noise covariance R (currently applied)
     r: f32,
+
+    // Adaptive R (Mehra 1972): nominal value and EMA of innovation²
+    r_nominal: f32,
+    r_est: f32,
+
+    // Consecutive baro updates with vel > APOGEE_ASCENT_VEL_MS (latched once
+    // APOGEE_MIN_ASCENT_UPDATES is reached so the slow-down before apogee keeps it)
+    prev_vel_positive_count: u8,
+
+    // Innovation gating diagnostics
+    data: KalmanDiag,
+
+    // EMA mean / variance of the accel input (land detection)
+    acc_mean: f32,
+    acc_var: f32,
 }
 
 impl VerticalKalman {
@@ -33,14 +81,61 @@ impl VerticalKalman {
 
             // R: Measurement noise (trust in barometer)
             // Higher R = less trust in baro, smoother but laggy
-            r: 50.0,
+            r: R_BARO_NOMINAL,
+
+            r_nominal: R_BARO_NOMINAL,
+            r_est: R_BARO_NOMINAL,
+
+            prev_vel_positive_count: 0,
+
+            data: KalmanDiag::default(),
+
+            acc_mean: 0.0,
+            acc_var: 0.0,
         }
     }
 
-    /// Predict state based on acceleration (model)
+    /// Zero the state, restore the initial P and drop adaptive estimates
+    #[allow(dead_code)]
+    pub fn reset(&mut self) {
+        self.reset_to(0.0, 0.0);
+    }
+
+    /// Restart from a known position / velocity (e.g. on the pad at arming)
+    pub fn reset_to(&mut self, pos: f32, vel: f32) {
+        self.x = [pos, vel];
+        self.p = [[100.0, 0.0], [0.0, 100.0]];
+        self.r = self.r_nominal;
+        self.r_est = self.r_nominal;
+        self.prev_vel_positive_count = 0;
+        self.data = KalmanDiag::default();
+    }
+
+    /// Set the nominal barometer noise (m²); adaptation never goes below it
+    #[allow(dead_code)]
+    pub fn set_baro_noise(&mut self, r: f32) {
+        self.r_nominal = r;
+        self.r = r;
+        self.r_est = r;
+    }
+
+    /// Former `predict`: propagation for callers that still pair it with an
+    /// `update` on every call. New code should use `predict_only` at the
+    /// IMU rate and `update` whenever a baro sample arrives.
+    #[allow(dead_code)]
+    pub fn predict_and_prepare(&mut self, dt: f32, accel_z: f32) {
+        self.predict_only(dt, accel_z);
+    }
+
+    /// Propagate state and covariance with the accel model (no measurement).
+    /// Called every fast_loop tick; `update` runs only at the baro rate.
     /// dt: time step in seconds
     /// accel_z: vertical acceleration in m/s^2 (Earth frame, gravity removed)
-    pub fn predict(&mut self, dt: f32, accel_z: f32) {
+    pub fn predict_only(&mut self, dt: f32, accel_z: f32) {
+        let d = accel_z - self.acc_mean;
+        self.acc_mean += ACCEL_VAR_ALPHA * d;
+        self.acc_var = (1.0 - ACCEL_VAR_ALPHA) * (self.acc_var + ACCEL_VAR_ALPHA * d * d);
+
         // State transition FMatrix:
         // pos = pos + vel*dt + 0.5*acc*dt^2
         // vel = vel + acc*dt
@@ -73,6 +168,30 @@ impl VerticalKalman {
     /// meas_alt: measured altitude in meters
     #[allow(dead_code)]
     pub fn update(&mut self, meas_alt: f32) {
+        let y = meas_alt - self.x[0];
+
+        // Adaptive R: EMA of innovation², switch up when it clearly exceeds
+        // nominal. Runs before the gate so that a baro that became noisier
+        // raises R (and with it S) instead of being rejected forever.
+        self.r_est += R_ADAPT_ALPHA * (y * y - self.r_est);
+        if self.r_est > R_ADAPT_RATIO * self.r_nominal {
+            self.r = self.r_est;
+        } else if self.r_est < self.r_nominal {
+            self.r = self.r_nominal;
+        }
+
+        // Fault gating: normalised innovation squared (adapted R) against χ² threshold
+        let nis = y * y / (self.p[0][0] + self.r);
+        if nis > NIS_THRESHOLD {
+            self.data.baro_fault_count = self.data.baro_fault_count.saturating_add(1);
+            if self.data.baro_fault_count > BARO_STUCK_REJECTS {
+                self.data.baro_stuck = true;
+            }
+            return;
+        }
+        self.data.baro_fault_count = 0;
+        self.data.baro_stuck = false;
+
         // H = [1, 0] (Measured position only)
         // K = P * H' / (H * P * H' + R)
         // S = P[0][0] + R
@@ -82,8 +201,7 @@ impl VerticalKalman {
         let k0 = self.p[0][0] / s;
         let k1 = self.p[1][0] / s;
 
-        // Innovation y = z - Hx
-        let y = meas_alt - self.x[0];
+        // Innovation y = z - Hx (computed above)
 
         // Update State x = x + Ky
         self.x[0] += k0 * y;
@@ -102,6 +220,58 @@ impl VerticalKalman {
         self.p[0][1] -= k0 * p01;
         self.p[1][0] -= k1 * p00;
         self.p[1][1] -= k1 * p01;
+
+        if self.x[1] > APOGEE_ASCENT_VEL_MS {
+            self.prev_vel_positive_count = self.prev_vel_positive_count.saturating_add(1);
+        } else if self.prev_vel_positive_count < APOGEE_MIN_ASCENT_UPDATES {
+            self.prev_vel_positive_count = 0;
+        }
+    }
+
+    /// Velocity crossed zero downward, above APOGEE_MIN_ALT_M, after a sustained
+    /// climb. Diagnostic only: the flight apogee is FlightPhase::Apogee.
+    #[allow(dead_code)]
+    pub fn is_apogee(&self) -> bool {
+        self.x[1] < 0.0
+            && self.x[0] > APOGEE_MIN_ALT_M
+            && self.prev_vel_positive_count >= APOGEE_MIN_ASCENT_UPDATES
+    }
+
+    /// On the ground and at rest: `alt_agl` < 5 m, |v| < 2 m/s and a quiet
+    /// accel input (a descent under canopy still swings the accel)
+    pub fn land_detect(&self, alt_agl: f32) -> bool {
+        alt_agl < LAND_ALT_M
+            && self.x[1].abs() < LAND_VEL_MS
+            && self.acc_var < LAND_ACCEL_VAR_MAX
+    }
+
+    /// Update state with a vertical velocity measurement (GPS, up positive)
+    /// vz_ms: measured vertical speed in m/s, r_vz: its variance (m²/s²)
+    pub fn update_gps_vz(&mut self, vz_ms: f32, r_vz: f32) {
+        // H = [0, 1] (Measured velocity only)
+        let s = self.p[1][1] + r_vz;
+
+        let k0 = self.p[0][1] / s;
+        let k1 = self.p[1][1] / s;
+
+        let y = vz_ms - self.x[1];
+
+        self.x[0] += k0 * y;
+        self.x[1] += k1 * y;
+
+        // P = (I - KH)P
+        let p10 = self.p[1][0];
+        let p11 = self.p[1][1];
+
+        self.p[0][0] -= k0 * p10;
+        self.p[0][1] -= k0 * p11;
+        self.p[1][0] -= k1 * p10;
+        self.p[1][1] -= k1 * p11;
+    }
+
+    /// Baro gating counters for telemetry
+    pub fn diagnostics(&self) -> KalmanDiag {
+        self.data
     }
 
     pub fn state(&self) -> KalmanState {
@@ -111,3 +281,116 @@ impl VerticalKalman {
         }
     }
 }
+
+// ── Three-state filter [pos, vel, acc] ───────────────────────────────────────
+
+#[allow(dead_code)]
+#[derive(Default, Clone, Copy)]
+pub struct KalmanState3 {
+    pub position: f32,     // Altitude (m)
+    pub velocity: f32,     // Vertical Velocity (m/s)
+    pub acceleration: f32, // Vertical Acceleration (m/s^2)
+}
+
+impl KalmanState3 {
+    pub fn accel_ms2(&self) -> f32 {
+        self.acceleration
+    }
+}
+
+/// Baro-only vertical filter with acceleration as a first-order Markov state.
+/// Burn = high acc + rising alt; at apogee acc < 0 while vel ≈ 0.
+pub struct VerticalKalman3 {
+    // State vector [pos, vel, acc]
+    x: [f32; 3],
+
+    // Covariance matrix P (3x3)
+    p: [[f32; 3]; 3],
+
+    // Process noise covariance Q (diagonal)
+    q: [f32; 3],
+
+    // Measurement noise covariance R (barometer)
+    r: f32,
+
+    // Acceleration decay rate (1/s): acc → 0 without measurements
+    tau_acc: f32,
+}
+
+impl VerticalKalman3 {
+    /// `initial_alt`: first barometer altitude, avoids a 100 m-scale transient
+    pub fn new(initial_alt: f32) -> Self {
+        Self {
+            x: [initial_alt, 0.0, 0.0],
+            p: [[100.0, 0.0, 0.0], [0.0, 100.0, 0.0], [0.0, 0.0, 100.0]],
+            // Acceleration carries most of the model uncertainty (unknown jerk)
+            q: [0.01, 0.1, 0.5],
+            r: R_BARO_NOMINAL,
+            tau_acc: 0.5,
+        }
+    }
+
+    /// Predict with the constant-jerk / Markov-acceleration model
+    pub fn predict(&mut self, dt: f32) {
+        let f = [
+            [1.0, dt, 0.5 * dt * dt],
+            [0.0, 1.0, dt],
+            [0.0, 0.0, 1.0 - self.tau_acc * dt],
+        ];
+
+        let mut x = [0.0f32; 3];
+        for i in 0..3 {
+            for k in 0..3 {
+                x[i] += f[i][k] * self.x[k];
+            }
+        }
+        self.x = x;
+
+        // P = F*P*F' + Q
+        let mut fp = [[0.0f32; 3]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                for k in 0..3 {
+                    fp[i][j] += f[i][k] * self.p[k][j];
+                }
+            }
+        }
+        let mut p = [[0.0f32; 3]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                for k in 0..3 {
+                    p[i][j] += fp[i][k] * f[j][k];
+                }
+            }
+            p[i][i] += self.q[i];
+        }
+        self.p = p;
+    }
+
+    /// Update with a barometer altitude (H = [1, 0, 0])
+    pub fn update(&mut self, meas_alt: f32) {
+        let s = self.p[0][0] + self.r;
+        let k = [self.p[0][0] / s, self.p[1][0] / s, self.p[2][0] / s];
+
+        let y = meas_alt - self.x[0];
+        for i in 0..3 {
+            self.x[i] += k[i] * y;
+        }
+
+        // P = (I - KH)P = P - K * P[0][:]
+        let p0 = self.p[0];
+        for i in 0..3 {
+            for j in 0..3 {
+                self.p[i][j] -= k[i] * p0[j];
+            }
+        }
+    }
+
+    pub fn state(&self) -> KalmanState3 {
+        KalmanState3 {
+            position: self.x[0],
+            velocity: self.x[1],
+            acceleration: self.x[2],
+        }
+    }
+}
@@ -1,12 +1,15 @@
+pub mod ahrs;
 pub mod ekf;
 
 pub mod crsf;
 pub mod dshot;
 pub mod filter;
 pub mod flash;
-pub mod gps;
+pub use flight_controller_rust::gps;
 pub mod hmc5883;
 pub mod icm42688;
 pub mod kalman;
 pub mod roll;
 pub mod spl06;
+
+pub use ahrs::Mahony;
@@ -1,12 +1,21 @@
 use core::f32::consts::PI;
 
+use micromath::F32Ext;
+
+use super::filter::Pt1Filter;
+
 #[derive(Clone, Copy)]
+#[allow(dead_code)] // R5 / R15: linkages not reachable from the 3-position switch
 pub enum GearRatio {
+    R5,
     R10,
+    R15,
     R20,
+    R30,
 }
 
 impl GearRatio {
+    #[allow(dead_code)]
     pub fn from_aux_channel(ch_value: u16) -> Self {
         if ch_value > 1500 {
             Self::R20
@@ -15,38 +24,146 @@ impl GearRatio {
         }
     }
 
+    /// 3-position switch: 0–2047 split in thirds → R10 / R20 / R30
+    pub fn from_aux_channel_3pos(ch_value: u16) -> Self {
+        match ch_value {
+            0..=682 => Self::R10,
+            683..=1365 => Self::R20,
+            _ => Self::R30,
+        }
+    }
+
     pub fn as_f32(self) -> f32 {
         match self {
+            Self::R5 => 5.0,
             Self::R10 => 10.0,
+            Self::R15 => 15.0,
             Self::R20 => 20.0,
+            Self::R30 => 30.0,
         }
     }
 
     pub fn as_u8(self) -> u8 {
         match self {
+            Self::R5 => 5,
             Self::R10 => 10,
+            Self::R15 => 15,
             Self::R20 => 20,
+            Self::R30 => 30,
         }
     }
 }
 
+/// Roll PID gains as tuned over USB and persisted in flash
+/// (`W25qxx::write_pid_gains`): six little-endian f32, 24 bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PidGains {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+    pub kff: f32,
+    pub i_limit: f32,
+    pub out_limit: f32,
+}
+
+pub const PID_GAINS_SIZE: usize = 24;
+
+impl PidGains {
+    /// Bench-tuned defaults, used until gains are saved to flash
+    pub const DEFAULT: PidGains = PidGains {
+        kp: 4.0,
+        ki: 0.8,
+        kd: 0.08,
+        kff: 0.05,
+        i_limit: 0.4,
+        out_limit: 1.0,
+    };
+
+    pub fn to_bytes(&self) -> [u8; PID_GAINS_SIZE] {
+        let mut b = [0u8; PID_GAINS_SIZE];
+        let fields = [self.kp, self.ki, self.kd, self.kff, self.i_limit, self.out_limit];
+        for (chunk, v) in b.chunks_exact_mut(4).zip(fields) {
+            chunk.copy_from_slice(&v.to_le_bytes());
+        }
+        b
+    }
+
+    pub fn from_bytes(b: &[u8; PID_GAINS_SIZE]) -> Self {
+        let f = |i: usize| f32::from_le_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]]);
+        Self {
+            kp: f(0),
+            ki: f(4),
+            kd: f(8),
+            kff: f(12),
+            i_limit: f(16),
+            out_limit: f(20),
+        }
+    }
+}
+
+impl Default for PidGains {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Partial gain update from one USB line ("KP=4.2 KI=0.9"), unset fields kept
+#[derive(Clone, Copy, Default)]
+pub struct PidGainsUpdate {
+    pub kp: Option<f32>,
+    pub ki: Option<f32>,
+    pub kd: Option<f32>,
+    pub kff: Option<f32>,
+    pub i_limit: Option<f32>,
+    pub out_limit: Option<f32>,
+}
+
+impl PidGainsUpdate {
+    pub fn apply(&self, g: &mut PidGains) {
+        let set = |dst: &mut f32, v: Option<f32>| {
+            if let Some(v) = v {
+                *dst = v;
+            }
+        };
+        set(&mut g.kp, self.kp);
+        set(&mut g.ki, self.ki);
+        set(&mut g.kd, self.kd);
+        set(&mut g.kff, self.kff);
+        set(&mut g.i_limit, self.i_limit);
+        set(&mut g.out_limit, self.out_limit);
+    }
+}
+
 pub struct RollController {
     kp: f32,
     ki: f32,
     kd: f32,
+    kb: f32, // back-calculation anti-windup gain
     integral: f32,
     integral_limit: f32,
     output_limit: f32,
+    kff: f32,
+    prev_setpoint: f32,
+    ff_primed: bool, // false until prev_setpoint holds a real setpoint
+    ff_filter: Pt1Filter,
+    d_filter: Pt1Filter,
 }
 
+/// Feed-forward low-pass: the setpoint only moves once per CRSF frame, so its
+/// raw derivative is a train of 1-cycle spikes
+const FF_LPF_CUTOFF_HZ: f32 = 10.0;
+
 pub struct GearedTabController {
     kp_motor_pos: f32,
     kd_motor_pos: f32,
+    kff_motor_pos: f32,
     max_tab_deg: f32,
+    tab_hard_stop_deg: f32,     // physical stop, beyond the commanded max_tab_deg
     max_motor_cmd: f32,
     max_motor_deg_s: f32,
     motor_pos_est_deg: f32,
     prev_motor_pos_est_deg: f32,
+    prev_motor_target_deg: f32,
 }
 
 impl GearedTabController {
@@ -54,23 +171,30 @@ impl GearedTabController {
         kp_motor_pos: f32,
         kd_motor_pos: f32,
         max_tab_deg: f32,
+        tab_hard_stop_deg: f32,
         max_motor_cmd: f32,
         max_motor_deg_s: f32,
+        kff_motor_pos: f32,
     ) -> Self {
+        let tab_hard_stop_deg = tab_hard_stop_deg.abs().max(max_tab_deg.abs());
         Self {
             kp_motor_pos,
             kd_motor_pos,
+            kff_motor_pos,
             max_tab_deg: max_tab_deg.abs(),
+            tab_hard_stop_deg,
             max_motor_cmd: max_motor_cmd.abs(),
             max_motor_deg_s: max_motor_deg_s.abs(),
             motor_pos_est_deg: 0.0,
             prev_motor_pos_est_deg: 0.0,
+            prev_motor_target_deg: 0.0,
         }
     }
 
     pub fn reset(&mut self) {
         self.motor_pos_est_deg = 0.0;
         self.prev_motor_pos_est_deg = 0.0;
+        self.prev_motor_target_deg = 0.0;
     }
 
     pub fn update(
@@ -84,38 +208,82 @@ impl GearedTabController {
         let motor_target_deg = tab_target_deg * ratio;
 
         let motor_error_deg = motor_target_deg - self.motor_pos_est_deg;
-        let motor_rate_est_deg_s = if dt > 0.0 {
-            (self.motor_pos_est_deg - self.prev_motor_pos_est_deg) / dt
+        let (motor_rate_est_deg_s, motor_target_rate_deg_s) = if dt > 0.0 {
+            (
+                (self.motor_pos_est_deg - self.prev_motor_pos_est_deg) / dt,
+                (motor_target_deg - self.prev_motor_target_deg) / dt,
+            )
         } else {
-            0.0
+            (0.0, 0.0)
         };
+        self.prev_motor_target_deg = motor_target_deg;
 
         let motor_cmd = (self.kp_motor_pos * motor_error_deg
-            - self.kd_motor_pos * motor_rate_est_deg_s)
+            - self.kd_motor_pos * motor_rate_est_deg_s
+            + self.kff_motor_pos * motor_target_rate_deg_s)
             .clamp(-self.max_motor_cmd, self.max_motor_cmd);
 
         self.prev_motor_pos_est_deg = self.motor_pos_est_deg;
         self.motor_pos_est_deg += motor_cmd * self.max_motor_deg_s * dt;
 
-        let tab_est_deg = (self.motor_pos_est_deg / ratio).clamp(-self.max_tab_deg, self.max_tab_deg);
+        // Mechanical stop at the current ratio: the estimate cannot run past
+        // it; zero the rate estimate so the D-term does not bounce off the stop
+        let lim = self.tab_hard_stop_deg * ratio;
+        if self.motor_pos_est_deg.abs() >= lim {
+            self.motor_pos_est_deg = self.motor_pos_est_deg.clamp(-lim, lim);
+            self.prev_motor_pos_est_deg = self.motor_pos_est_deg;
+        }
+
+        let tab_est_deg = (self.motor_pos_est_deg / ratio)
+            .clamp(-self.tab_hard_stop_deg, self.tab_hard_stop_deg);
         (tab_est_deg, motor_cmd)
     }
 }
 
 impl RollController {
-    pub fn new(kp: f32, ki: f32, kd: f32, integral_limit: f32, output_limit: f32) -> Self {
+    pub fn new(
+        kp: f32,
+        ki: f32,
+        kd: f32,
+        kb: f32,
+        integral_limit: f32,
+        output_limit: f32,
+        kff: f32,
+        d_lpf_cutoff_hz: f32,
+        loop_freq_hz: f32,
+    ) -> Self {
         Self {
             kp,
             ki,
             kd,
+            kb,
             integral: 0.0,
             integral_limit: integral_limit.abs(),
             output_limit: output_limit.abs(),
+            kff,
+            prev_setpoint: 0.0,
+            ff_primed: false,
+            ff_filter: Pt1Filter::new(FF_LPF_CUTOFF_HZ, loop_freq_hz),
+            d_filter: Pt1Filter::new(d_lpf_cutoff_hz, loop_freq_hz),
         }
     }
 
+    /// Live tuning (USB KP= KI= KD= KFF= ILIM= OLIM=); kb and the D filter are kept
+    pub fn set_gains(&mut self, g: &PidGains) {
+        self.kp = g.kp;
+        self.ki = g.ki;
+        self.kd = g.kd;
+        self.kff = g.kff;
+        self.integral_limit = g.i_limit.abs();
+        self.output_limit = g.out_limit.abs();
+        self.integral = self.integral.clamp(-self.integral_limit, self.integral_limit);
+    }
+
     pub fn reset(&mut self) {
         self.integral = 0.0;
+        self.ff_primed = false;
+        self.ff_filter.reset();
+        self.d_filter.reset();
     }
 
     pub fn update(
@@ -125,18 +293,42 @@ impl RollController {
         roll_measured_rad: f32,
         roll_rate_rad_s: f32,
     ) -> f32 {
-        let error = roll_setpoint_rad - roll_measured_rad;
+        let error = roll_error_rad(roll_setpoint_rad, roll_measured_rad);
 
         self.integral += error * dt;
         self.integral = self
             .integral
             .clamp(-self.integral_limit, self.integral_limit);
 
-        let output = self.kp * error + self.ki * self.integral - self.kd * roll_rate_rad_s;
-        output.clamp(-self.output_limit, self.output_limit)
+        // Feedforward on the setpoint rate (skipped on the first update after reset)
+        let setpoint_rate = if self.ff_primed && dt > 0.0 {
+            (roll_setpoint_rad - self.prev_setpoint) / dt
+        } else {
+            0.0
+        };
+        self.prev_setpoint = roll_setpoint_rad;
+        self.ff_primed = true;
+        let setpoint_rate = self.ff_filter.filter(setpoint_rate);
+
+        let roll_rate_filt = self.d_filter.filter(roll_rate_rad_s);
+
+        let output_unsat = self.kp * error + self.ki * self.integral - self.kd * roll_rate_filt
+            + self.kff * setpoint_rate;
+        let output_sat = output_unsat.clamp(-self.output_limit, self.output_limit);
+
+        // Back-calculation: bleed the integral while the output is saturated
+        self.integral += (output_sat - output_unsat) * self.kb * dt;
+        output_sat
     }
 }
 
+/// Shortest-way roll error, wrapped to [−π, π]: setpoint 3.1 rad with
+/// measured −3.1 rad gives ≈ −0.08 rad, not +6.2.
+pub fn roll_error_rad(setpoint: f32, measured: f32) -> f32 {
+    let d = setpoint - measured;
+    d.sin().atan2(d.cos())
+}
+
 pub fn crsf_to_unit(ch_value: u16) -> f32 {
     let normalized = (ch_value as f32 - 992.0) / 820.0;
     normalized.clamp(-1.0, 1.0)
@@ -171,8 +363,22 @@ pub fn signed_unit_to_dshot_3d(unit_cmd: f32, armed: bool) -> u16 {
     }
 }
 
-pub fn max_roll_setpoint_from_stick(stick: f32, max_roll_deg: f32) -> f32 {
-    let deg = stick.clamp(-1.0, 1.0) * max_roll_deg;
+/// Expo curve: `stick·(1−expo) + stick³·expo`, expo ∈ [0, 1] (0 = linear).
+pub fn stick_expo(stick: f32, expo: f32) -> f32 {
+    let expo = expo.clamp(0.0, 1.0);
+    stick * (1.0 - expo) + stick * stick * stick * expo
+}
+
+/// Betaflight-style rate curve: `stick·[rate·(1−expo) + rate·expo·stick²]`.
+#[allow(dead_code)]
+pub fn stick_rate_curve(stick: f32, rate: f32, expo: f32) -> f32 {
+    let expo = expo.clamp(0.0, 1.0);
+    stick * (rate * (1.0 - expo) + rate * expo * stick * stick)
+}
+
+/// Stick → roll setpoint (rad), with `expo` softening the centre (0 = linear).
+pub fn max_roll_setpoint_from_stick(stick: f32, max_roll_deg: f32, expo: f32) -> f32 {
+    let deg = stick_expo(stick.clamp(-1.0, 1.0), expo) * max_roll_deg;
     deg * PI / 180.0
 }
 
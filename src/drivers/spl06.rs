@@ -2,15 +2,19 @@ use embassy_stm32::i2c::{Error, I2c, Instance, RxDma, TxDma};
 use embassy_time::{Duration, Timer};
 use micromath::F32Ext;
 
-const ADDR: u8 = 0x76;
+/// I2C address with SDO low (default wiring)
+pub const SPL06_ADDR_SDO_LOW: u8 = 0x76;
+/// I2C address with SDO high (second sensor)
+#[allow(dead_code)]
+pub const SPL06_ADDR_SDO_HIGH: u8 = 0x77;
 const REG_CHIP_ID: u8 = 0x0D;
 const REG_PRESS_DATA: u8 = 0x00;
 const REG_TEMP_DATA: u8 = 0x03;
 const REG_PRS_CFG: u8 = 0x06;
 const REG_TMP_CFG: u8 = 0x07;
 const REG_MEAS_CFG: u8 = 0x08;
-#[allow(dead_code)]
 const REG_CFG_REG: u8 = 0x09;
+const REG_INT_STS: u8 = 0x0A;
 #[allow(dead_code)]
 const REG_RESET: u8 = 0x0C;
 const REG_COEF: u8 = 0x10;
@@ -18,6 +22,15 @@ const REG_COEF: u8 = 0x10;
 #[allow(dead_code)]
 const CHIP_ID: u8 = 0x10;
 
+/// MEAS_CFG status bits
+const MEAS_PRS_RDY: u8 = 1 << 4;
+#[allow(dead_code)]
+const MEAS_TMP_RDY: u8 = 1 << 5;
+
+/// CFG_REG: INT_HL (active high) | INT_PRS (interrupt on pressure ready)
+const CFG_INT_HL: u8 = 1 << 7;
+const CFG_INT_PRS: u8 = 1 << 4;
+
 #[derive(Default, Debug, Clone, Copy)]
 pub struct Spl06Coeffs {
     c0: i16,
@@ -31,22 +44,69 @@ pub struct Spl06Coeffs {
     c30: i16,
 }
 
+/// Running altitude statistics (Welford, O(1) per sample, no buffer)
+#[derive(Default, Debug, Clone, Copy)]
+pub struct AltitudeStats {
+    pub mean: f32,
+    pub variance: f32, // sample variance (n − 1), m²
+    pub n: u32,
+}
+
 pub struct Spl06 {
+    addr: u8,
     coeffs: Spl06Coeffs,
     // Scaling factors based on oversampling (assuming defaults for now)
     k_p: f32,
     k_t: f32,
+    qnh_pa: f32, // sea-level reference pressure for the altitude formula
+    stats: AltitudeStats,
+    stats_m2: f32, // Σ (x − mean)² accumulator
 }
 
 impl Spl06 {
-    pub fn new() -> Self {
+    /// `addr`: SPL06_ADDR_SDO_LOW (0x76) or SPL06_ADDR_SDO_HIGH (0x77)
+    pub fn new(addr: u8) -> Self {
         Self {
+            addr,
             coeffs: Spl06Coeffs::default(),
             k_p: 7864320.0, // Default for 32x oversampling (datasheet typically varies)
             k_t: 7864320.0,
+            qnh_pa: 101325.0,
+            stats: AltitudeStats::default(),
+            stats_m2: 0.0,
         }
     }
 
+    #[allow(dead_code)]
+    pub fn new_default() -> Self {
+        Self::new(SPL06_ADDR_SDO_LOW)
+    }
+
+    /// Add one altitude sample to the running mean / variance.
+    /// On the pad, `variance.sqrt()` after ~100 samples is the sensor noise floor.
+    pub fn update_stats(&mut self, alt: f32) -> AltitudeStats {
+        let st = &mut self.stats;
+        st.n += 1;
+        let delta = alt - st.mean;
+        st.mean += delta / st.n as f32;
+        self.stats_m2 += delta * (alt - st.mean);
+        if st.n > 1 {
+            st.variance = self.stats_m2 / (st.n - 1) as f32;
+        }
+        *st
+    }
+
+    #[allow(dead_code)]
+    pub fn reset_stats(&mut self) {
+        self.stats = AltitudeStats::default();
+        self.stats_m2 = 0.0;
+    }
+
+    /// Set the altitude reference pressure (Pa), e.g. the local QNH.
+    pub fn set_qnh(&mut self, qnh_pa: f32) {
+        self.qnh_pa = qnh_pa;
+    }
+
     pub async fn init<T: Instance, Tx: TxDma<T>, Rx: RxDma<T>>(
         &mut self,
         i2c: &mut I2c<'_, T, Tx, Rx>,
@@ -87,12 +147,38 @@ impl Spl06 {
         Ok(())
     }
 
+    /// Drive the SDO/INT pin high on each new pressure result (CFG_REG INT_PRS).
+    /// In I2C mode the interrupt shares the SDO pad.
+    pub async fn set_drdy_interrupt_pin<T: Instance, Tx: TxDma<T>, Rx: RxDma<T>>(
+        &mut self,
+        i2c: &mut I2c<'_, T, Tx, Rx>,
+    ) -> Result<(), Error> {
+        self.write_reg(i2c, REG_CFG_REG, CFG_INT_HL | CFG_INT_PRS).await
+    }
+
+    /// Read a sample only if MEAS_CFG reports a new pressure result; clears INT_STS.
+    /// TMP_RDY (bit 5) only rises at the 1 Hz temperature rate, the last
+    /// temperature is reused in between.
+    pub async fn read_if_ready<T: Instance, Tx: TxDma<T>, Rx: RxDma<T>>(
+        &mut self,
+        i2c: &mut I2c<'_, T, Tx, Rx>,
+    ) -> Result<Option<(f32, f32, f32)>, Error> {
+        let mut status = [0u8; 1];
+        i2c.blocking_write_read(self.addr, &[REG_MEAS_CFG], &mut status)?;
+        let mut int_sts = [0u8; 1];
+        i2c.blocking_write_read(self.addr, &[REG_INT_STS], &mut int_sts)?;
+        if status[0] & MEAS_PRS_RDY == 0 {
+            return Ok(None);
+        }
+        self.read_pressure_altitude(i2c).await.map(Some)
+    }
+
     pub async fn read_id<T: Instance, Tx: TxDma<T>, Rx: RxDma<T>>(
         &mut self,
         i2c: &mut I2c<'_, T, Tx, Rx>,
     ) -> Result<u8, Error> {
         let mut buf = [0u8; 1];
-        i2c.blocking_write_read(ADDR, &[REG_CHIP_ID], &mut buf)?;
+        i2c.blocking_write_read(self.addr, &[REG_CHIP_ID], &mut buf)?;
         Ok(buf[0])
     }
 
@@ -103,7 +189,7 @@ impl Spl06 {
         reg: u8,
     ) -> Result<i32, Error> {
         let mut buf = [0u8; 3];
-        i2c.blocking_write_read(ADDR, &[reg], &mut buf)?;
+        i2c.blocking_write_read(self.addr, &[reg], &mut buf)?;
         // Combine: MSB, byte1, LSB
         let val = ((buf[0] as i32) << 16) | ((buf[1] as i32) << 8) | (buf[2] as i32);
         // Sign extend if needed (24 bit 2's complement)
@@ -120,7 +206,7 @@ impl Spl06 {
         i2c: &mut I2c<'_, T, Tx, Rx>,
     ) -> Result<(), Error> {
         let mut buf = [0u8; 18];
-        i2c.blocking_write_read(ADDR, &[REG_COEF], &mut buf)?;
+        i2c.blocking_write_read(self.addr, &[REG_COEF], &mut buf)?;
 
         let c0_raw = ((buf[0] as i16) << 4) | ((buf[1] as i16) >> 4);
         self.coeffs.c0 = if c0_raw & 0x800 != 0 {
@@ -167,7 +253,7 @@ impl Spl06 {
         reg: u8,
         val: u8,
     ) -> Result<(), Error> {
-        i2c.blocking_write(ADDR, &[reg, val])
+        i2c.blocking_write(self.addr, &[reg, val])
     }
 
     pub async fn read_pressure_altitude<T: Instance, Tx: TxDma<T>, Rx: RxDma<T>>(
@@ -199,12 +285,117 @@ impl Spl06 {
 
         // Convert to Altitude (Hypsometric Formula)
         // Alt = 44330 * (1.0 - (P / P0)^(1/5.255))
-        // P0 = 101325 Pa
+        // P0 = QNH (101325 Pa unless set_qnh was called)
 
-        let p0 = 101325.0;
+        let p0 = self.qnh_pa;
         let power = 1.0 / 5.255;
         let alt = 44330.0 * (1.0 - (pressure / p0).powf(power));
 
         Ok((alt, pressure, _temp))
     }
 }
+
+/// Samples kept by `PressureTrend` (one per minute → 1 h window)
+pub const PRESSURE_TREND_LEN: usize = 60;
+/// |trend| above this (hPa/h) → weather warning (≥ 3 hPa/h is a storm front)
+pub const WEATHER_WARNING_HPA_H: f32 = 2.0;
+/// Minutes of history before a warning can fire (a 2-point slope is mostly noise)
+const PRESSURE_TREND_MIN_SAMPLES: usize = 10;
+
+/// Hourly pressure tendency for pad weather: one sample per minute, slope by
+/// least squares over whatever part of the hour has been filled.
+pub struct PressureTrend {
+    samples: [f32; PRESSURE_TREND_LEN],
+    idx: usize,
+    is_full: bool,
+}
+
+impl PressureTrend {
+    pub const fn new() -> Self {
+        Self { samples: [0.0; PRESSURE_TREND_LEN], idx: 0, is_full: false }
+    }
+
+    /// Add one pressure reading (hPa); call once per minute
+    pub fn push(&mut self, pressure_hpa: f32) {
+        self.samples[self.idx] = pressure_hpa;
+        self.idx = (self.idx + 1) % PRESSURE_TREND_LEN;
+        if self.idx == 0 {
+            self.is_full = true;
+        }
+    }
+
+    pub fn sample_count(&self) -> usize {
+        if self.is_full { PRESSURE_TREND_LEN } else { self.idx }
+    }
+
+    /// Linear regression slope in hPa/hour, 0 until two samples are in
+    pub fn trend_hpa_per_hour(&self) -> f32 {
+        let n = self.sample_count();
+        if n < 2 {
+            return 0.0;
+        }
+        // Oldest sample first; x in minutes, centred so Σx = 0
+        let start = if self.is_full { self.idx } else { 0 };
+        let x_mean = (n - 1) as f32 * 0.5;
+        let y_mean = (0..n)
+            .map(|i| self.samples[(start + i) % PRESSURE_TREND_LEN])
+            .sum::<f32>()
+            / n as f32;
+        let mut sxy = 0.0f32;
+        let mut sxx = 0.0f32;
+        for i in 0..n {
+            let dx = i as f32 - x_mean;
+            sxy += dx * (self.samples[(start + i) % PRESSURE_TREND_LEN] - y_mean);
+            sxx += dx * dx;
+        }
+        sxy / sxx * 60.0
+    }
+
+    pub fn weather_warning(&self) -> bool {
+        self.sample_count() >= PRESSURE_TREND_MIN_SAMPLES
+            && self.trend_hpa_per_hour().abs() > WEATHER_WARNING_HPA_H
+    }
+}
+
+/// Baro samples that must each be lower than the previous one to confirm apogee
+/// (~0.3 s at 16 Hz)
+pub const APOGEE_CONFIRM_SAMPLES: u8 = 5;
+
+/// Apogee from the baro altitude with hysteresis: near apogee the pressure
+/// wobbles with airflow, so a single sample below the peak proves nothing.
+/// Fires once `threshold` consecutive drops have been seen; repeated identical
+/// readings (the same sample polled twice) neither count nor reset.
+pub struct ApogeeDetector {
+    peak_alt: f32,
+    last_alt: f32,
+    confirmed_count: u8,
+    threshold: u8,
+}
+
+impl ApogeeDetector {
+    pub const fn new(threshold: u8) -> Self {
+        Self { peak_alt: f32::MIN, last_alt: f32::MIN, confirmed_count: 0, threshold }
+    }
+
+    pub fn update(&mut self, current_alt: f32) -> bool {
+        if current_alt > self.peak_alt {
+            self.peak_alt = current_alt;
+            self.confirmed_count = 0;
+        } else if current_alt < self.last_alt {
+            self.confirmed_count = self.confirmed_count.saturating_add(1);
+        } else if current_alt > self.last_alt {
+            self.confirmed_count = 0;
+        }
+        self.last_alt = current_alt;
+        self.confirmed_count >= self.threshold
+    }
+
+    #[allow(dead_code)]
+    pub fn peak_alt(&self) -> f32 {
+        self.peak_alt
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new(self.threshold);
+    }
+}
@@ -0,0 +1,12 @@
+//! Hardware-independent firmware modules, built as a library so their unit
+//! tests run on the host:
+//!
+//! ```text
+//! cargo test --lib --target x86_64-unknown-linux-gnu
+//! ```
+//!
+//! The firmware binary re-exports them under `drivers::`.
+#![cfg_attr(not(test), no_std)]
+
+#[path = "drivers/gps.rs"]
+pub mod gps;
@@ -7,39 +7,159 @@ mod state;
 mod tasks;
 mod usb;
 
-use core::sync::atomic::{AtomicU16, Ordering};
+// Constants from bin/calibrate (`mod calibration { ... }`): sensor biases and
+// EKF noise
+include!("calibration.rs");
+
+use core::fmt::Write;
+use core::cell::{Cell, RefCell};
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU16, AtomicU32, AtomicU8, Ordering};
 use embassy_executor::Spawner;
-use embassy_stm32::gpio::{Level, Output, Pin, Speed};
+use embassy_futures::select::{select, Either};
+use embassy_stm32::exti::ExtiInput;
+use embassy_stm32::gpio::{Input, Level, Output, Pin, Pull, Speed};
 use embassy_stm32::i2c::I2c;
+use embassy_stm32::rtc::Rtc;
 use embassy_stm32::spi::{Config as SpiConfig, Spi};
 use embassy_stm32::time::Hertz as TimeHertz;
 use embassy_stm32::usart::{Config as UsartConfig, Uart};
 use embassy_stm32::{bind_interrupts, peripherals};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
 use embassy_sync::channel::Channel;
-use embassy_time::{Duration, Timer};
+use embassy_time::{Duration, Instant, Timer};
+use micromath::F32Ext;
 use {defmt_rtt as _, panic_probe as _};
 
-use crate::board::Board;
-use crate::drivers::dshot::Dshot300;
+use crate::board::{Board, ResetCause};
+use crate::drivers::Mahony;
+use crate::drivers::dshot::{Dshot300, DshotQuad, ESC_OUTPUT_LOCKED, MOTOR_COUNT};
+use crate::drivers::flash::{FlightLogger, FsError, LogCursor, LogError, LogRecord, W25qxx};
 use crate::drivers::gps;
-use crate::drivers::icm42688::Icm42688;
-use crate::state::{AttitudeState, BaroData, GpsData, RcData};
+use crate::drivers::gps::PositionRing;
+use crate::drivers::icm42688::{Icm42688, ImuError};
+use crate::drivers::roll::PidGains;
+use crate::drivers::spl06::AltitudeStats;
+use crate::state::{
+    AtomicAttitudeState, BaroData, BatteryState, FlightEventLog, FlightPhaseDurations, GpsNav,
+    LinkData, MagData, RcData,
+};
 use crate::tasks::fast_loop::{fast_loop_task, FastLoopConfig};
+use crate::tasks::baro_task::BARO_CHAN_DEPTH;
+use crate::tasks::logger_task::LOG_CHAN_DEPTH;
+use crate::usb::{Command, UsbSerial, USB_CMD_CHAN_DEPTH};
+
+// ── DShot shared commands ─────────────────────────────────────────────────────
+//  Indexed by motor slot: dshot::MOTOR_TAB (PB0), dshot::MOTOR_MAIN (PB1), …
+pub static MOTOR_DSHOT_CMD: [AtomicU16; MOTOR_COUNT] = [
+    AtomicU16::new(0), AtomicU16::new(0), AtomicU16::new(0), AtomicU16::new(0),
+];
+
+/// Motor RPM per slot, from bidirectional DShot eRPM (0 until a bidir output is used)
+pub static MOTOR_RPM: [AtomicU16; MOTOR_COUNT] = [
+    AtomicU16::new(0), AtomicU16::new(0), AtomicU16::new(0), AtomicU16::new(0),
+];
+
+// ── GPS trajectory ────────────────────────────────────────────────────────────
+//  Filled by gps_task @ 1 Hz, frozen by fast_loop at apogee, dumped over USB
+//  by telemetry_task after landing. 512 points × 16 B = 8 KiB.
+pub const GPS_RING_LEN: usize = 512;
+pub static GPS_RING: Mutex<CriticalSectionRawMutex, RefCell<PositionRing<GPS_RING_LEN>>> =
+    Mutex::new(RefCell::new(PositionRing::new()));
+pub static GPS_RING_FROZEN: AtomicBool = AtomicBool::new(false);
+
+// ── Attitude ──────────────────────────────────────────────────────────────────
+//  Stored by fast_loop after each EKF update, loaded by telemetry_task every tick.
+pub static ATTITUDE: AtomicAttitudeState = AtomicAttitudeState::new();
+
+// ── Flight events ─────────────────────────────────────────────────────────────
+//  Phase transitions and durations from fast_loop, printed by telemetry_task once landed.
+pub static FLIGHT_EVENTS: Mutex<CriticalSectionRawMutex, RefCell<FlightEventLog>> =
+    Mutex::new(RefCell::new(FlightEventLog::new()));
+/// Boost / coast / descent durations of the last flight (state::PhaseTimer in fast_loop)
+pub static PHASE_DURATIONS: Mutex<CriticalSectionRawMutex, Cell<FlightPhaseDurations>> =
+    Mutex::new(Cell::new(FlightPhaseDurations::new()));
+
+// ── Battery ───────────────────────────────────────────────────────────────────
+//  Written by adc_task: pack voltage for the flight log, under-voltage for arming.
+pub static VBAT_MV: AtomicU16 = AtomicU16::new(0);
+pub static BATTERY_LOW: AtomicBool = AtomicBool::new(false);
+
+/// Brown-out resets (RTC BKP1R count) above which the boot report warns
+const BROWNOUT_WARN_COUNT: u32 = 3;
+
+// ── Wall clock ────────────────────────────────────────────────────────────────
+//  Unix time at uptime 0, from the RTC (main task): log timestamp = offset + uptime.
+//  0 while the RTC has not been set.
+pub static RTC_UNIX_OFFSET: AtomicU32 = AtomicU32::new(0);
+/// RTC readings before 2024-01-01 are the power-on default, not a real time
+const RTC_VALID_AFTER: u32 = 1_704_067_200;
+
+// ── Baro altitude ─────────────────────────────────────────────────────────────
+//  Latest SPL06 altitude (cm, QNH-referenced), written by baro_task for the
+//  fast_loop apogee detector.
+pub static BARO_ALT_CM: AtomicI32 = AtomicI32::new(0);
+
+// ── Weather ───────────────────────────────────────────────────────────────────
+//  Written once a minute by baro_task (spl06::PressureTrend), printed by telemetry_task.
+/// Pressure tendency, hPa/hour (f32 bits)
+pub static PRESSURE_TREND: AtomicU32 = AtomicU32::new(0);
+pub static WEATHER_WARNING: AtomicBool = AtomicBool::new(false);
+
+// ── System health ─────────────────────────────────────────────────────────────
+//  One bit per subsystem (state::HEALTH_*), written lock-free by the owning task.
+pub static HEALTH_FLAGS: AtomicU8 = AtomicU8::new(0);
+/// Set at boot when the IMU fails its WHO_AM_I check: arming refused until power cycle
+pub static IMU_FAULT: AtomicBool = AtomicBool::new(false);
+
+// ── USB output mode ───────────────────────────────────────────────────────────
+//  false = ASCII debug lines, true = binary frames (usb::UsbBinaryFrame, tools/).
+//  Switched with BINARY=0/1 over USB.
+pub static USB_BINARY_MODE: AtomicBool = AtomicBool::new(false);
 
-// ── DShot shared command ──────────────────────────────────────────────────────
-pub static TAB_MOTOR_DSHOT_CMD: AtomicU16 = AtomicU16::new(0);
+// ── USB commands ──────────────────────────────────────────────────────────────
+//  Applied by the main task, consumed by the owning tasks.
+/// Current roll gains: loaded from flash at boot, patched by KP= / KI= / …
+pub static ROLL_GAINS: Mutex<CriticalSectionRawMutex, RefCell<PidGains>> =
+    Mutex::new(RefCell::new(PidGains::DEFAULT));
+/// Set with every gain change: fast_loop reloads ROLL_GAINS into the controller
+pub static ROLL_GAINS_UPDATED: AtomicBool = AtomicBool::new(false);
+/// Set with every gain change: logger_task writes ROLL_GAINS to flash (outside a flight)
+pub static ROLL_GAINS_SAVE: AtomicBool = AtomicBool::new(false);
+/// QNH in Pa (f32 bits), 0 = keep the standard atmosphere
+pub static QNH_PA: AtomicU32 = AtomicU32::new(0);
+/// Set by ARMED=0: arming refused whatever the switch says
+pub static USB_ARM_INHIBIT: AtomicBool = AtomicBool::new(false);
+/// Set by RESET_CALIB: fast_loop re-zeroes ground altitude and filters when disarmed
+pub static RECALIB_REQUEST: AtomicBool = AtomicBool::new(false);
+/// Set by ERASE_LOG: logger_task formats the flight log outside a flight
+pub static LOG_ERASE_REQUEST: AtomicBool = AtomicBool::new(false);
+/// Published by fast_loop every cycle
+pub static ARMED: AtomicBool = AtomicBool::new(false);
+
+// ── Watchdog ──────────────────────────────────────────────────────────────────
+//  Alive bits (watchdog_task::ALIVE_*) set by fast_loop / gps_task / baro_task,
+//  cleared by watchdog_task on each pet.
+pub static TASK_ALIVE: AtomicU8 = AtomicU8::new(0);
 
 // ── Inter-task channels ───────────────────────────────────────────────────────
 //  Cap=1: the fast_loop always wants the LATEST sample; older values are dropped.
-static BARO_CHAN:    Channel<CriticalSectionRawMutex, BaroData,     1> = Channel::new();
-static GPS_CHAN:     Channel<CriticalSectionRawMutex, GpsData,      1> = Channel::new();
+//  Baro is the exception: each sample is a Kalman measurement, none is skipped.
+static BARO_CHAN:    Channel<CriticalSectionRawMutex, BaroData,     BARO_CHAN_DEPTH> = Channel::new();
+static MAG_CHAN:     Channel<CriticalSectionRawMutex, MagData,      1> = Channel::new();
+static GPS_CHAN:     Channel<CriticalSectionRawMutex, GpsNav,       1> = Channel::new();
 static CRSF_CHAN:    Channel<CriticalSectionRawMutex, RcData,       1> = Channel::new();
+static LINK_CHAN:    Channel<CriticalSectionRawMutex, LinkData,     1> = Channel::new();
+static BATT_CHAN:    Channel<CriticalSectionRawMutex, BatteryState, 1> = Channel::new();
+static ALT_STATS_CHAN: Channel<CriticalSectionRawMutex, AltitudeStats, 1> = Channel::new();
+static USB_CMD_CHAN: Channel<CriticalSectionRawMutex, Command, USB_CMD_CHAN_DEPTH> = Channel::new();
+
+// Telemetry task reads sensor data from its own copies
+static BARO_TEL_CHAN: Channel<CriticalSectionRawMutex, BaroData, 1> = Channel::new();
+static GPS_TEL_CHAN:  Channel<CriticalSectionRawMutex, GpsNav,   1> = Channel::new();
 
-// Telemetry task reads attitude from fast_loop and sensor data from its own copies
-static ATT_TEL_CHAN:  Channel<CriticalSectionRawMutex, AttitudeState, 1> = Channel::new();
-static BARO_TEL_CHAN: Channel<CriticalSectionRawMutex, BaroData,      1> = Channel::new();
-static GPS_TEL_CHAN:  Channel<CriticalSectionRawMutex, GpsData,       1> = Channel::new();
+// Flight log: fast_loop → logger_task (deeper, absorbs flash erase stalls)
+static LOG_CHAN:      Channel<CriticalSectionRawMutex, LogRecord, LOG_CHAN_DEPTH> = Channel::new();
 
 // ── Interrupt bindings ────────────────────────────────────────────────────────
 bind_interrupts!(struct Irqs {
@@ -50,20 +170,128 @@ bind_interrupts!(struct Irqs {
 });
 
 // ── DShot task ────────────────────────────────────────────────────────────────
+/// Motor output — swap for Dshot600 / Dshot1200 / DshotTimerOutput.
+/// Embassy tasks cannot be generic, the selection is this alias.
+type MotorDshot = Dshot300;
+
 #[embassy_executor::task]
-async fn dshot_tab_task(mut dshot: Dshot300) {
-    const ESC_OUTPUT_LOCKED: bool = true;
+async fn dshot_task(mut motors: DshotQuad<MotorDshot>) {
+    motors.arm_all().await;
     loop {
-        let cmd = if ESC_OUTPUT_LOCKED {
-            0
-        } else {
-            TAB_MOTOR_DSHOT_CMD.load(Ordering::Relaxed)
-        };
-        dshot.send_command(cmd, false);
+        let mut cmds = [0u16; MOTOR_COUNT];
+        if !ESC_OUTPUT_LOCKED {
+            for (cmd, shared) in cmds.iter_mut().zip(MOTOR_DSHOT_CMD.iter()) {
+                *cmd = shared.load(Ordering::Relaxed);
+            }
+        }
+        motors.send_all(cmds).await;
         Timer::after(Duration::from_micros(1000)).await;
     }
 }
 
+// ── Boot calibration ──────────────────────────────────────────────────────────
+/// Boot gyro average further than this from calibration::GYRO_BIAS on any
+/// axis (LSB, 2 °/s) → the board moved, the bench bias is used instead
+const GYRO_BIAS_MAX_DEV_LSB: f32 = 2.0 * 16.4;
+
+// ── AHRS pad alignment ────────────────────────────────────────────────────────
+/// Pad alignment: Mahony proportional gain while settling on the rail
+const AHRS_ALIGN_KP: f32 = 2.0;
+/// The attitude must stay converged, with the board still, for this long
+const AHRS_SETTLE_MIN_MS: u64 = 1000;
+/// Still: gyro below this (rad/s, ≈ 3 °/s)…
+const AHRS_STILL_GYRO_RAD_S: f32 = 0.05;
+/// …and |accel| within this of 1 g
+const AHRS_STILL_ACCEL_G: f32 = 0.05;
+/// Arming is released after this even if the AHRS never reports convergence
+const AHRS_CONVERGE_TIMEOUT_MS: u64 = 10_000;
+
+// ── GPS config helper ─────────────────────────────────────────────────────────
+/// How long the M10 gets to answer a CFG-VALSET with ACK-ACK / ACK-NAK
+const UBX_ACK_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Send one UBX CFG-VALSET and wait for its acknowledgement.
+/// `Some(true)` = ACK, `Some(false)` = NAK, `None` = no answer within the timeout.
+async fn gps_send_cfg(
+    uart: &mut Uart<'static, peripherals::USART3, peripherals::DMA1_CH3, peripherals::DMA1_CH1>,
+    framer: &mut gps::UbxFramer,
+    msg: &[u8],
+) -> Option<bool> {
+    let _ = uart.write(msg).await;
+    let deadline = Instant::now() + UBX_ACK_TIMEOUT;
+    let mut rx = [0u8; 128];
+    loop {
+        match select(uart.read_until_idle(&mut rx), Timer::at(deadline)).await {
+            Either::First(Ok(n)) => {
+                for &b in &rx[..n] {
+                    let _ = framer.push(b);
+                }
+                if let Some(ack) = framer.take_ack() {
+                    if ack.class_id == gps::UBX_CLASS_CFG && ack.msg_id == gps::UBX_ID_CFG_VALSET {
+                        return Some(ack.acked);
+                    }
+                }
+            }
+            Either::First(Err(_)) => {}
+            Either::Second(_) => return None,
+        }
+    }
+}
+
+/// Poll UBX-MON-VER and wait for the answer (same timeout as a CFG ACK).
+async fn gps_poll_version(
+    uart: &mut Uart<'static, peripherals::USART3, peripherals::DMA1_CH3, peripherals::DMA1_CH1>,
+    framer: &mut gps::UbxFramer,
+) -> Option<gps::FirmwareVersion> {
+    let (poll, len) = gps::ubx_poll_mon_ver();
+    let _ = uart.write(&poll[..len]).await;
+    let deadline = Instant::now() + UBX_ACK_TIMEOUT;
+    let mut rx = [0u8; 128];
+    loop {
+        match select(uart.read_until_idle(&mut rx), Timer::at(deadline)).await {
+            Either::First(Ok(n)) => {
+                for &b in &rx[..n] {
+                    if let Some(f) = framer.push(b) {
+                        if f.class == gps::UBX_CLASS_MON && f.id == gps::UBX_ID_MON_VER {
+                            return gps::parse_ubx_mon_ver(f.payload);
+                        }
+                    }
+                }
+            }
+            Either::First(Err(_)) => {}
+            Either::Second(_) => return None,
+        }
+    }
+}
+
+// ── Flight log dump helper ────────────────────────────────────────────────────
+async fn write_log_record(usb_serial: &mut UsbSerial<'static>, r: &LogRecord) {
+    let mut line = heapless::String::<96>::new();
+    let _ = write!(line,
+        "{},{},{},{},{},{},{},{},{},{},{}\r\n",
+        r.ts_ms,
+        r.accel[0], r.accel[1], r.accel[2],
+        r.gyro[0], r.gyro[1], r.gyro[2],
+        r.baro_alt_cm, r.vbat_mv, r.flags, r.unix_s,
+    );
+    let b = line.as_bytes();
+    let mut off = 0;
+    while off < b.len() {
+        let end = (off + 64).min(b.len());
+        let _ = usb_serial.write_packet(&b[off..end]).await;
+        off = end;
+    }
+}
+
+/// RTC_UNIX_OFFSET from the current RTC time, left at 0 while the calendar is unset
+fn publish_rtc_offset(rtc: &Rtc) {
+    let unix_s = Board::rtc_unix_timestamp(rtc);
+    if unix_s >= RTC_VALID_AFTER {
+        let uptime_s = Instant::now().as_secs() as u32;
+        RTC_UNIX_OFFSET.store(unix_s - uptime_s, Ordering::Relaxed);
+    }
+}
+
 // ── Main ──────────────────────────────────────────────────────────────────────
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
@@ -72,9 +300,19 @@ async fn main(spawner: Spawner) {
     let p = board.p;
 
     // 2. USB (CDC-ACM for debug)
-    let (usb_dev, usb_serial) = usb::init(p.USB_OTG_FS, p.PA12, p.PA11);
+    let (usb_dev, mut usb_serial) = usb::init(p.USB_OTG_FS, p.PA12, p.PA11);
     spawner.spawn(usb::usb_task(usb_dev)).unwrap();
 
+    // 2b. Reset cause: reported once USB is up; brown-outs are counted in BKP1R
+    let reset_cause = Board::reset_cause();
+    Board::clear_reset_flags();
+    let brownouts = if reset_cause == ResetCause::BrownOut { Board::record_brownout() } else { 0 };
+
+    // 2c. RTC on the LSE: wall-clock time for the flight log (kept across warm
+    //     resets, set over USB with SETTIME=<unix>)
+    let mut rtc = Board::init_rtc(p.RTC, 2000, 1, 1, 0, 0, 0);
+    publish_rtc_offset(&rtc);
+
     // 3. I2C1 @ 400 kHz — SPL06 Baro (SCL=PB8, SDA=PB9)
     let i2c = I2c::new(
         p.I2C1,
@@ -101,8 +339,14 @@ async fn main(spawner: Spawner) {
     let mut imu = Icm42688::new(spi, cs_gyro);
 
     // 5. DShot tab motor on PB0 (MOTOR1 resource)
-    let dshot_tab_motor = Dshot300::new(p.PB0.degrade());
-    spawner.spawn(dshot_tab_task(dshot_tab_motor)).unwrap();
+    //    MOTOR2..4 (PB1 main motor, PA3, PA2) driven from the same task
+    let motors = DshotQuad::new([
+        MotorDshot::new(p.PB0.degrade()),
+        MotorDshot::new(p.PB1.degrade()),
+        MotorDshot::new(p.PA3.degrade()),
+        MotorDshot::new(p.PA2.degrade()),
+    ]);
+    spawner.spawn(dshot_task(motors)).unwrap();
 
     // 6. GPS USART3 @ 115200 (TX=PB10, RX=PB11)
     let mut gps_config = UsartConfig::default();
@@ -126,45 +370,204 @@ async fn main(spawner: Spawner) {
     ).unwrap();
     let (crsf_uart_tx, crsf_uart_rx) = crsf_uart.split();
 
+    // 7b. SPI3 @ 10 MHz — W25Qxx flight-log flash (SCK=PC10, MOSI=PC12, MISO=PC11, CS=PB3)
+    let mut flash_config = SpiConfig::default();
+    flash_config.frequency = TimeHertz(10_000_000);
+    let flash_spi = Spi::new(
+        p.SPI3,
+        p.PC10, p.PC12, p.PC11,
+        NoDma, NoDma,
+        flash_config,
+    );
+    let flash_cs = Output::new(p.PB3.degrade(), Level::High, Speed::VeryHigh);
+    let mut logger = FlightLogger::new(W25qxx::new(flash_spi, flash_cs));
+
     // 8. Heartbeat LED (PC13)
     let mut led = Output::new(p.PC13, Level::High, Speed::Low);
 
     // 9. IMU hardware init (DLPF 258 Hz, ODR 1 kHz set inside)
     Timer::after(Duration::from_millis(100)).await;
-    let _ = imu.init().await;
+    let imu_init = imu.init().await;
+    if imu_init.is_ok() {
+        let _ = imu.configure_drdy_interrupt().await;
+    } else {
+        // Wrong or missing chip: keep booting for USB diagnostics, never arm
+        IMU_FAULT.store(true, Ordering::Relaxed);
+    }
 
-    // 10. GPS UBX configuration (one-shot at startup)
+    // 10. GPS UBX configuration (one-shot at startup, each CFG waits for its ACK).
+    //     GNSS + NAV/SBAS/RATE go as one VALSET when they fit in a single message.
     Timer::after(Duration::from_millis(200)).await;
     {
-        let (buf, len) = gps::ubx_cfg_gnss_all();
-        let _ = gps_uart.write(&buf[..len]).await;
-        Timer::after(Duration::from_millis(200)).await;
-        let (buf, len) = gps::ubx_cfg_nav_sbas_rate();
-        let _ = gps_uart.write(&buf[..len]).await;
-        Timer::after(Duration::from_millis(200)).await;
+        let mut framer = gps::UbxFramer::new();
+        let mut cfgs: heapless::Vec<(&str, ([u8; gps::UBX_CFG_BUF_LEN], usize)), 2> =
+            heapless::Vec::new();
+        match gps::ubx_cfg_gnss_nav_merged() {
+            Some(msg) => { let _ = cfgs.push(("GNSS+NAV", msg)); }
+            None => {
+                // Key list outgrew UBX_CFG_BUF_LEN: still configured, one more round-trip
+                if usb_serial.dtr() {
+                    let _ = usb_serial.write_packet(b"[GPS] CFG merge too long, sent split\r\n").await;
+                }
+                let _ = cfgs.push(("GNSS", gps::ubx_cfg_gnss_all()));
+                let _ = cfgs.push(("NAV/SBAS/RATE", gps::ubx_cfg_nav_sbas_rate()));
+            }
+        }
+        for (name, (buf, len)) in cfgs.iter() {
+            let res = gps_send_cfg(&mut gps_uart, &mut framer, &buf[..*len]).await;
+            if res != Some(true) && usb_serial.dtr() {
+                let mut m = heapless::String::<64>::new();
+                let _ = write!(m, "[GPS] CFG {} {}\r\n",
+                    name, if res == Some(false) { "NAK" } else { "no ACK" });
+                let _ = usb_serial.write_packet(m.as_bytes()).await;
+            }
+        }
+
+        // Receiver firmware version (M10 GNSS config behaviour differs between releases)
+        let ver = gps_poll_version(&mut gps_uart, &mut framer).await;
+        if usb_serial.dtr() {
+            let mut m = heapless::String::<64>::new();
+            match ver {
+                Some(v) => { let _ = write!(m, "[GPS] SW={} HW={}\r\n", v.sw_version, v.hw_version); }
+                None => { let _ = write!(m, "[GPS] MON-VER no answer\r\n"); }
+            }
+            let _ = usb_serial.write_packet(m.as_bytes()).await;
+        }
     }
 
-    // 11. Static gyro/accel calibration: 100 samples × 10 ms = 1 s
-    let mut gyro_bias  = [0.0f32; 3];
-    let mut accel_bias = [0.0f32; 3];
+    // 11. Static gyro calibration and pad attitude: 100 samples × 10 ms = 1 s.
+    //     The accel bias comes from bin/calibrate (src/calibration.rs): on a
+    //     tilted rail the static average cannot tell the tilt from the offset.
+    let mut gyro_bias = [0.0f32; 3];
+    let mut pad_accel = [0.0f32; 3];
     const CALIB_N: usize = 100;
     for i in 0..CALIB_N {
         if let Ok((accel, gyro)) = imu.read_all().await {
             for j in 0..3 {
-                accel_bias[j] += accel[j] as f32;
-                gyro_bias[j]  += gyro[j]  as f32;
+                pad_accel[j] += accel[j] as f32;
+                gyro_bias[j] += gyro[j] as f32;
             }
         }
         if i % 10 == 0 { led.toggle(); }
         Timer::after(Duration::from_millis(10)).await;
     }
     for j in 0..3 {
-        accel_bias[j] /= CALIB_N as f32;
-        gyro_bias[j]  /= CALIB_N as f32;
+        pad_accel[j] /= CALIB_N as f32;
+        gyro_bias[j] /= CALIB_N as f32;
+    }
+    // Board moved during the average (handled, wind on the rail): the bench
+    // value is closer than a rate-polluted one
+    if (0..3).any(|j| (gyro_bias[j] - calibration::GYRO_BIAS[j]).abs() > GYRO_BIAS_MAX_DEV_LSB) {
+        gyro_bias = calibration::GYRO_BIAS;
+    }
+    let accel_bias = calibration::ACCEL_BIAS;
+    // Pad attitude from the raw static average: the rocket sits tilted on the
+    // rail. Same average seeds the fast_loop EKF (AttitudeEkf::initialize_from_accel)
+    let mut ahrs = Mahony::new(AHRS_ALIGN_KP);
+    ahrs.reset_to_accel(pad_accel[0], pad_accel[1], pad_accel[2]);
+
+    // 11a. AHRS settle: fast_loop (and therefore arming) is only started once
+    //      the attitude has converged, or after AHRS_CONVERGE_TIMEOUT_MS
+    let settle_start = Instant::now();
+    let mut settled_since = settle_start;
+    loop {
+        let mut settled = false;
+        if let Ok((accel, gyro)) = imu.read_all().await {
+            let g = |j: usize| ((gyro[j] as f32 - gyro_bias[j]) / 16.4).to_radians();
+            let (ax, ay, az) = (accel[0] as f32, accel[1] as f32, accel[2] as f32);
+            ahrs.update(0.01, g(0), g(1), g(2), ax, ay, az);
+            let gyro_norm = (g(0) * g(0) + g(1) * g(1) + g(2) * g(2)).sqrt();
+            let accel_g = (ax * ax + ay * ay + az * az).sqrt() / 2048.0;
+            settled = ahrs.is_converged()
+                && gyro_norm < AHRS_STILL_GYRO_RAD_S
+                && (accel_g - 1.0).abs() < AHRS_STILL_ACCEL_G;
+        }
+        if !settled {
+            settled_since = Instant::now();
+        }
+        if settled_since.elapsed().as_millis() >= AHRS_SETTLE_MIN_MS
+            || settle_start.elapsed().as_millis() >= AHRS_CONVERGE_TIMEOUT_MS
+        {
+            break;
+        }
+        Timer::after(Duration::from_millis(10)).await;
     }
-    accel_bias[2] -= 2048.0; // Remove gravity (1G = 2048 LSB at ±16G)
     led.set_high(); // Calibration done
 
+    // 11b. Flight log: mount the file system, dump previous flights over USB before arming
+    let log_init = logger.init().await;
+    // Saved roll gains (defaults until the first KP= / KI= … over USB)
+    let gains_from_flash = match logger.read_pid_gains().await {
+        Ok(g) => {
+            ROLL_GAINS.lock(|c| *c.borrow_mut() = g);
+            true
+        }
+        Err(_) => false,
+    };
+    for _ in 0..20u32 {
+        if usb_serial.dtr() { break; }
+        Timer::after(Duration::from_millis(100)).await;
+    }
+    if usb_serial.dtr() && logger.record_count() > 0 {
+        let _ = usb_serial
+            .write_packet(b"# LOG ts_ms,ax,ay,az,gx,gy,gz,baro_alt_cm,vbat_mv,flags,unix_s\r\n")
+            .await;
+        // One record read per line written: the USB host paces the dump
+        let mut cursor = LogCursor::default();
+        let mut sent = 0u32;
+        while let Ok(Some(r)) = logger.next_record(&mut cursor).await {
+            if !usb_serial.dtr() {
+                break;
+            }
+            write_log_record(&mut usb_serial, &r).await;
+            sent += 1;
+        }
+        let mut m = heapless::String::<64>::new();
+        let _ = write!(m, "# LOG END {} of {} records\r\n", sent, logger.record_count());
+        let _ = usb_serial.write_packet(m.as_bytes()).await;
+    }
+
+    if usb_serial.dtr() {
+        let mut m = heapless::String::<64>::new();
+        let _ = write!(m, "# RESET: {}\r\n", reset_cause.as_str());
+        let _ = usb_serial.write_packet(m.as_bytes()).await;
+        if brownouts > BROWNOUT_WARN_COUNT {
+            let mut m = heapless::String::<64>::new();
+            let _ = write!(m, "# WARN: {} brown-out resets, check the power supply\r\n", brownouts);
+            let _ = usb_serial.write_packet(m.as_bytes()).await;
+        }
+
+        let mut m = heapless::String::<64>::new();
+        let _ = match imu_init {
+            Ok(()) => write!(m, "# IMU: ICM-42688-P OK\r\n"),
+            Err(ImuError::WrongDevice { found }) => {
+                write!(m, "# IMU: WHO_AM_I=0x{:02X} (expected 0x47), arming disabled\r\n", found)
+            }
+            Err(e) => write!(m, "# IMU: init failed ({:?}), arming disabled\r\n", e),
+        };
+        let _ = usb_serial.write_packet(m.as_bytes()).await;
+
+        let g = ROLL_GAINS.lock(|c| *c.borrow());
+        let mut m = heapless::String::<64>::new();
+        let _ = write!(m, "# PID kp={} ki={} kd={} ({})\r\n",
+            g.kp, g.ki, g.kd, if gains_from_flash { "flash" } else { "defaults" });
+        let _ = usb_serial.write_packet(m.as_bytes()).await;
+
+        match log_init {
+            Ok(()) => {}
+            Err(LogError::Fs(FsError::Unformatted)) => {
+                let _ = usb_serial
+                    .write_packet(b"# LOG: unknown flash content, not recording (ERASE_LOG)\r\n")
+                    .await;
+            }
+            Err(e) => {
+                let mut m = heapless::String::<96>::new();
+                let _ = write!(m, "# LOG: init failed ({:?})\r\n", e);
+                let _ = usb_serial.write_packet(m.as_bytes()).await;
+            }
+        }
+    }
+
     // 12. Build IMU for 'static use via a leaked Box-equivalent
     //     Embassy tasks require 'static resources. Since we own `imu` and the
     //     program never ends, leaking is the correct embedded approach.
@@ -175,41 +578,113 @@ async fn main(spawner: Spawner) {
     };
 
     // 13. Spawn all task
+    // ICM-42688 INT1 → PB13 (GYRO_EXTI), paces fast_loop
+    let imu_drdy = ExtiInput::new(Input::new(p.PB13, Pull::Down), p.EXTI13);
     spawner.spawn(fast_loop_task(
         unsafe { core::ptr::read(imu_ref) },
-        FastLoopConfig { gyro_bias, accel_bias },
+        imu_drdy,
+        FastLoopConfig { gyro_bias, accel_bias, pad_accel },
         BARO_CHAN.receiver(),
+        MAG_CHAN.receiver(),
         GPS_CHAN.receiver(),
         CRSF_CHAN.receiver(),
-        ATT_TEL_CHAN.sender(),
+        LOG_CHAN.sender(),
     )).unwrap();
 
+    spawner.spawn(tasks::logger_task::logger_task(
+        logger,
+        LOG_CHAN.receiver(),
+    )).unwrap();
+
+    // SPL06 DRDY (SDO/INT wired to the RSSI pad PC0)
+    let baro_drdy = ExtiInput::new(Input::new(p.PC0, Pull::Down), p.EXTI0);
     spawner.spawn(tasks::baro_task::baro_task(
         i2c,
+        baro_drdy,
         BARO_CHAN.sender(),
+        MAG_CHAN.sender(),
+        ALT_STATS_CHAN.sender(),
+    )).unwrap();
+
+    spawner.spawn(tasks::adc_task::adc_task(
+        p.ADC1, p.PC3, p.PC2,
+        BATT_CHAN.sender(),
     )).unwrap();
 
     spawner.spawn(tasks::gps_task::gps_task(
         gps_uart,
         GPS_CHAN.sender(),
+        GPS_TEL_CHAN.sender(),
     )).unwrap();
 
     spawner.spawn(tasks::crsf_task::crsf_task(
         crsf_uart_rx,
         CRSF_CHAN.sender(),
+        LINK_CHAN.sender(),
     )).unwrap();
 
+    let (usb_tx, usb_rx) = usb_serial.split();
+    spawner.spawn(usb::usb_rx_task(usb_rx, USB_CMD_CHAN.sender())).unwrap();
+
     spawner.spawn(tasks::telemetry_task::telemetry_task(
         crsf_uart_tx,
-        usb_serial,
-        ATT_TEL_CHAN.receiver(),
+        usb_tx,
         GPS_TEL_CHAN.receiver(),
         BARO_TEL_CHAN.receiver(),
+        LINK_CHAN.receiver(),
+        BATT_CHAN.receiver(),
+        ALT_STATS_CHAN.receiver(),
     )).unwrap();
 
-    // 14. Main task: LED heartbeat @ 1 Hz
+    spawner.spawn(tasks::watchdog_task::watchdog_task(p.IWDG)).unwrap();
+
+    // 14. Main task: LED heartbeat @ 1 Hz + USB commands
     loop {
-        led.toggle();
-        Timer::after(Duration::from_millis(500)).await;
+        match select(USB_CMD_CHAN.receive(), Timer::after(Duration::from_millis(500))).await {
+            Either::First(cmd) => apply_command(cmd, &mut rtc),
+            Either::Second(_) => led.toggle(),
+        }
+    }
+}
+
+/// Hand a USB command to the task that owns the affected state.
+fn apply_command(cmd: Command, rtc: &mut Rtc) {
+    match cmd {
+        Command::SetTime(unix_s) => {
+            if Board::set_rtc_unix(rtc, unix_s).is_ok() {
+                publish_rtc_offset(rtc);
+            }
+        }
+        Command::Qnh(pa) => {
+            // A QNH step in flight would be seen as an altitude jump
+            if pa > 0.0 && !ARMED.load(Ordering::Relaxed) {
+                QNH_PA.store(pa.to_bits(), Ordering::Relaxed);
+            }
+        }
+        Command::Gains(update) => {
+            ROLL_GAINS.lock(|g| update.apply(&mut g.borrow_mut()));
+            ROLL_GAINS_UPDATED.store(true, Ordering::Relaxed);
+            ROLL_GAINS_SAVE.store(true, Ordering::Relaxed);
+        }
+        Command::Armed(allow) => USB_ARM_INHIBIT.store(!allow, Ordering::Relaxed),
+        Command::BinaryMode(on) => USB_BINARY_MODE.store(on, Ordering::Relaxed),
+        Command::ResetCalib => RECALIB_REQUEST.store(true, Ordering::Relaxed),
+        Command::DumpLog => {
+            // The flight log is dumped by the boot sequence: reboot into it,
+            // never while armed
+            if !ARMED.load(Ordering::Relaxed) {
+                cortex_m::peripheral::SCB::sys_reset();
+            }
+        }
+        Command::EraseLog => {
+            if !ARMED.load(Ordering::Relaxed) {
+                LOG_ERASE_REQUEST.store(true, Ordering::Relaxed);
+            }
+        }
+        Command::Dfu => {
+            if !ARMED.load(Ordering::Relaxed) {
+                Board::reboot_to_dfu();
+            }
+        }
     }
 }
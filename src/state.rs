@@ -1,3 +1,12 @@
+use core::cell::Cell;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+
+use crate::drivers::ekf::{EkfCovarDiag, EkfDebug};
+use crate::drivers::gps::GpsQuality;
+
 /// Shared state types for inter-task communication via Embassy channels.
 ///
 /// All types are `Copy` to minimise overhead when sent through channels.
@@ -9,33 +18,370 @@ pub struct BaroData {
     pub alt_m: f32,
     pub pressure_hpa: f32,
     pub temp_c: f32,
+    pub kf_vel_ms: f32,   // baro-only VerticalKalman3 velocity
+    pub kf_acc_ms2: f32,  // baro-only VerticalKalman3 acceleration
 }
 
+/// Navigation subset of `drivers::gps::GpsData`, mapped by gps_task before
+/// sending. The parser's counters and buffers stay inside gps_task.
 #[derive(Clone, Copy, Default)]
-pub struct GpsData {
+pub struct GpsNav {
     pub lat: f32,
     pub lon: f32,
     pub alt: f32,
+    pub alt_msl: f32,
     pub sats: u8,
+    pub sats_gps: u8, // in view per constellation (GSV)
+    pub sats_glo: u8,
+    pub sats_gal: u8,
+    pub sats_bds: u8,
     pub fix: bool,
     pub speed_kts: f32,
     pub course_deg: f32,
+    pub vz_ms: f32,     // vertical speed, up positive (NAV-PVT only)
+    pub vz_valid: bool, // false when only NMEA is flowing
+    pub pdop_ok: bool,  // fix + ≥ 4 sats + PDOP < 4 (gates fusion and CRSF GPS)
+    pub quality: GpsQuality,
+    pub hdop: f32,
+    pub timestamp_ms: u32, // Instant::now() when the fix was parsed
 }
 
 #[derive(Clone, Copy)]
 pub struct RcData {
     pub channels: [u16; 16],
+    pub failsafe: bool,
+    pub frame_age_ms: u32, // time since the last CRSF frame when this was sent
 }
 
 impl Default for RcData {
     fn default() -> Self {
-        Self { channels: [0u16; 16] }
+        Self { channels: [0u16; 16], failsafe: true, frame_age_ms: u32::MAX }
+    }
+}
+
+/// Raw HMC5883 sample (LSB, body axes).
+#[derive(Clone, Copy, Default)]
+pub struct MagData {
+    pub x: i16,
+    pub y: i16,
+    pub z: i16,
+}
+
+/// Battery pack, from adc_task (10 Hz).
+#[derive(Clone, Copy, Default)]
+pub struct BatteryState {
+    pub voltage_v: f32,
+    pub current_a: f32,
+    pub consumed_mah: u16,
+    pub percent: u8,
+}
+
+/// Radio link quality, from CRSF LINK_STATISTICS (0x14).
+#[derive(Clone, Copy, Default)]
+#[allow(dead_code)]
+pub struct LinkData {
+    pub rssi_dbm: i16,
+    pub lq: u8,
+    pub snr: i8,
+    pub rf_mode: u8,
+    pub tx_power: u8,
+    pub diag: LinkDiagnostics,
+}
+
+/// CRSF UART frame statistics from CrsfParser (bad cable / noise detection).
+#[derive(Clone, Copy, Default)]
+#[allow(dead_code)]
+pub struct LinkDiagnostics {
+    pub crc_errors: u16,
+    pub frame_errors: u16,
+    pub good_frames: u16,
+    pub loss_pct: f32,
+}
+
+/// Flight phase, tracked by fast_loop.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FlightPhase {
+    #[default]
+    Idle,
+    Armed,
+    Boost,
+    Coast,
+    Apogee,
+    Descent,
+    Landed,
+}
+
+impl FlightPhase {
+    /// Label used for the CRSF flight-mode frame and USB logs
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Idle => "IDLE",
+            Self::Armed => "ARMED",
+            Self::Boost => "BOOST",
+            Self::Coast => "COAST",
+            Self::Apogee => "APOGEE",
+            Self::Descent => "DESCENT",
+            Self::Landed => "LANDED",
+        }
+    }
+}
+
+/// One flight phase transition.
+#[derive(Clone, Copy, Default)]
+pub struct FlightEvent {
+    pub phase: FlightPhase,
+    pub timestamp_ms: u32,
+}
+
+pub const FLIGHT_EVENT_LOG_LEN: usize = 32;
+
+/// Last FLIGHT_EVENT_LOG_LEN phase transitions (fixed ring, oldest overwritten).
+#[derive(Clone, Copy)]
+pub struct FlightEventLog {
+    events: [FlightEvent; FLIGHT_EVENT_LOG_LEN],
+    head: usize,
+    len: usize,
+}
+
+impl FlightEventLog {
+    pub const fn new() -> Self {
+        Self {
+            events: [FlightEvent { phase: FlightPhase::Idle, timestamp_ms: 0 }; FLIGHT_EVENT_LOG_LEN],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, phase: FlightPhase, now_ms: u32) {
+        self.events[self.head] = FlightEvent { phase, timestamp_ms: now_ms };
+        self.head = (self.head + 1) % FLIGHT_EVENT_LOG_LEN;
+        self.len = (self.len + 1).min(FLIGHT_EVENT_LOG_LEN);
+    }
+
+    /// Visit events oldest first.
+    pub fn dump(&self, mut f: impl FnMut(&FlightEvent)) {
+        let start = (self.head + FLIGHT_EVENT_LOG_LEN - self.len) % FLIGHT_EVENT_LOG_LEN;
+        for i in 0..self.len {
+            f(&self.events[(start + i) % FLIGHT_EVENT_LOG_LEN]);
+        }
+    }
+}
+
+/// Time spent in each phase of the last flight, filled from `PhaseTimer`.
+#[derive(Clone, Copy, Default)]
+pub struct FlightPhaseDurations {
+    pub boost_ms: u32,
+    pub coast_ms: u32,
+    pub descent_ms: u32, // apogee hold + descent
+}
+
+impl FlightPhaseDurations {
+    pub const fn new() -> Self {
+        Self { boost_ms: 0, coast_ms: 0, descent_ms: 0 }
+    }
+
+    /// Store one completed phase; other phases are ignored. Apogee overwrites
+    /// descent_ms and Descent adds to it, so a new flight starts from zero.
+    pub fn record(&mut self, phase: FlightPhase, duration_ms: u32) {
+        match phase {
+            FlightPhase::Boost => self.boost_ms = duration_ms,
+            FlightPhase::Coast => self.coast_ms = duration_ms,
+            FlightPhase::Apogee => self.descent_ms = duration_ms,
+            FlightPhase::Descent => self.descent_ms = self.descent_ms.wrapping_add(duration_ms),
+            _ => {}
+        }
+    }
+}
+
+/// Measures how long each flight phase lasts. Call `update` every cycle.
+pub struct PhaseTimer {
+    start_ms: u32,
+    current_phase: FlightPhase,
+}
+
+impl PhaseTimer {
+    pub const fn new() -> Self {
+        Self { start_ms: 0, current_phase: FlightPhase::Idle }
+    }
+
+    /// On a transition, returns the phase just left and its duration (ms)
+    pub fn update(&mut self, phase: FlightPhase, now_ms: u32) -> Option<(FlightPhase, u32)> {
+        if phase == self.current_phase {
+            return None;
+        }
+        let completed = (self.current_phase, now_ms.wrapping_sub(self.start_ms));
+        self.current_phase = phase;
+        self.start_ms = now_ms;
+        Some(completed)
+    }
+}
+
+// ── System health ─────────────────────────────────────────────────────────────
+
+/// Health flag bits — each task owns one bit of `crate::HEALTH_FLAGS`
+pub const HEALTH_IMU: u8 = 1 << 0;
+pub const HEALTH_BARO: u8 = 1 << 1;
+pub const HEALTH_MAG: u8 = 1 << 2;
+pub const HEALTH_GPS: u8 = 1 << 3;
+pub const HEALTH_CRSF: u8 = 1 << 4;
+pub const HEALTH_FLASH: u8 = 1 << 5;
+pub const HEALTH_BATTERY: u8 = 1 << 6;
+/// Not a health bit: set by baro_task while the mag field tracks throttle (ESC noise)
+pub const FLAG_MAG_INTERFERENCE: u8 = 1 << 7;
+
+/// Set or clear one health bit (lock-free, callable from any task).
+pub fn set_health(flags: &AtomicU8, bit: u8, ok: bool) {
+    if ok {
+        flags.fetch_or(bit, Ordering::Relaxed);
+    } else {
+        flags.fetch_and(!bit, Ordering::Relaxed);
     }
 }
 
-/// Shared EKF state readable by the telemetry task (written only by fast_loop).
-/// Protected by a mutex, but since fast_loop is the only writer and telemetry
-/// only reads, using an AtomicCell pattern is acceptable (we'll use a signal).
+#[derive(Clone, Copy, Default)]
+pub struct SystemHealth {
+    pub imu_ok: bool,
+    pub baro_ok: bool,
+    pub mag_ok: bool,
+    pub gps_ok: bool,
+    pub crsf_ok: bool,
+    pub flash_ok: bool,
+    pub battery_ok: bool,
+    pub mag_interference: bool, // informational, not part of all_ok()
+}
+
+impl SystemHealth {
+    pub fn from_flags(bits: u8) -> Self {
+        Self {
+            imu_ok: bits & HEALTH_IMU != 0,
+            baro_ok: bits & HEALTH_BARO != 0,
+            mag_ok: bits & HEALTH_MAG != 0,
+            gps_ok: bits & HEALTH_GPS != 0,
+            crsf_ok: bits & HEALTH_CRSF != 0,
+            flash_ok: bits & HEALTH_FLASH != 0,
+            battery_ok: bits & HEALTH_BATTERY != 0,
+            mag_interference: bits & FLAG_MAG_INTERFERENCE != 0,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn all_ok(&self) -> bool {
+        self.imu_ok
+            && self.baro_ok
+            && self.mag_ok
+            && self.gps_ok
+            && self.crsf_ok
+            && self.flash_ok
+            && self.battery_ok
+    }
+
+    /// One digit per subsystem, IMU first: "1111111" when all healthy
+    pub fn digits(&self) -> [u8; 7] {
+        let d = |ok: bool| if ok { b'1' } else { b'0' };
+        [
+            d(self.imu_ok),
+            d(self.baro_ok),
+            d(self.mag_ok),
+            d(self.gps_ok),
+            d(self.crsf_ok),
+            d(self.flash_ok),
+            d(self.battery_ok),
+        ]
+    }
+}
+
+// ── Arming ────────────────────────────────────────────────────────────────────
+
+/// CRSF link considered lost after this long without a frame
+pub const ARM_LINK_TIMEOUT_MS: u32 = 500;
+/// Raw CRSF throttle must be below this to arm
+pub const ARM_THROTTLE_MAX: u16 = 200;
+/// GPS satellites required to arm
+pub const ARM_MIN_SATS: u8 = 4;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum ArmingState {
+    #[default]
+    Disarmed,
+    PreArm,   // all checks pass, switch low — ready to arm
+    Armed,
+    FailSafe, // a check failed while armed; cleared by switching off
+}
+
+/// Arming interlock, run every fast_loop cycle.
+///
+/// Pre-arm needs link, attitude, throttle low, GPS and no battery under-voltage
+/// (`set_battery_low`, fed from adc_task). Once armed only the
+/// link and attitude checks can trigger FailSafe: throttle rises and sats
+/// drop in flight by design.
+pub struct ArmingChecker {
+    state: ArmingState,
+    battery_low: bool,
+}
+
+impl ArmingChecker {
+    pub const fn new() -> Self {
+        Self { state: ArmingState::Disarmed, battery_low: false }
+    }
+
+    pub fn set_battery_low(&mut self, low: bool) {
+        self.battery_low = low;
+    }
+
+    pub fn update(
+        &mut self,
+        arm_switch: bool,
+        link_age_ms: u32,
+        attitude_ok: bool,
+        throttle_raw: u16,
+        gps_sats: u8,
+    ) -> ArmingState {
+        let in_flight_ok = link_age_ms < ARM_LINK_TIMEOUT_MS && attitude_ok;
+        let pre_arm_ok = in_flight_ok
+            && throttle_raw < ARM_THROTTLE_MAX
+            && gps_sats >= ARM_MIN_SATS
+            && !self.battery_low;
+
+        self.state = match self.state {
+            ArmingState::Disarmed | ArmingState::PreArm => {
+                if arm_switch {
+                    // Switch must be raised from PreArm; raising it first does nothing
+                    if self.state == ArmingState::PreArm && pre_arm_ok {
+                        ArmingState::Armed
+                    } else {
+                        ArmingState::Disarmed
+                    }
+                } else if pre_arm_ok {
+                    ArmingState::PreArm
+                } else {
+                    ArmingState::Disarmed
+                }
+            }
+            ArmingState::Armed => {
+                if !arm_switch {
+                    ArmingState::Disarmed
+                } else if !in_flight_ok {
+                    ArmingState::FailSafe
+                } else {
+                    ArmingState::Armed
+                }
+            }
+            ArmingState::FailSafe => {
+                if arm_switch { ArmingState::FailSafe } else { ArmingState::Disarmed }
+            }
+        };
+        self.state
+    }
+
+    #[allow(dead_code)]
+    pub fn state(&self) -> ArmingState {
+        self.state
+    }
+}
+
+/// Shared EKF state readable by the telemetry task (written only by fast_loop,
+/// through `AtomicAttitudeState`).
 #[derive(Clone, Copy, Default)]
 pub struct AttitudeState {
     pub roll_rad: f32,
@@ -44,4 +390,48 @@ pub struct AttitudeState {
     pub alt_m: f32,
     pub vel_ms: f32,
     pub is_high_g: bool,
+    pub ekf_debug: EkfDebug,
+    pub ekf_p_trace: f32, // trace(P) / N
+    pub ekf_covar: EkfCovarDiag,
+    pub ekf_resets: u16, // EKF divergence resets since boot
+    pub baro_fault_count: u8, // consecutive baro updates rejected by the Kalman gate
+    pub baro_stuck: bool,
+    pub phase: FlightPhase,
+}
+
+/// Latest `AttitudeState`, overwritten by fast_loop after each EKF update and
+/// read by telemetry. The struct is copied inside a critical section, so a
+/// reader preempted by fast_loop never sees half of one update.
+pub struct AtomicAttitudeState {
+    inner: Mutex<CriticalSectionRawMutex, Cell<AttitudeState>>,
+}
+
+impl AtomicAttitudeState {
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(Cell::new(AttitudeState {
+                roll_rad: 0.0,
+                pitch_rad: 0.0,
+                yaw_rad: 0.0,
+                alt_m: 0.0,
+                vel_ms: 0.0,
+                is_high_g: false,
+                ekf_debug: EkfDebug { is_high_g: false, accel_mag_g: 0.0 },
+                ekf_p_trace: 0.0,
+                ekf_covar: EkfCovarDiag { q: 0.0, gb: 0.0, ab: 0.0 },
+                ekf_resets: 0,
+                baro_fault_count: 0,
+                baro_stuck: false,
+                phase: FlightPhase::Idle,
+            })),
+        }
+    }
+
+    pub fn load(&self) -> AttitudeState {
+        self.inner.lock(|c| c.get())
+    }
+
+    pub fn store(&self, s: AttitudeState) {
+        self.inner.lock(|c| c.set(s));
+    }
 }
@@ -0,0 +1,90 @@
+use core::sync::atomic::Ordering;
+
+use embassy_executor::task;
+use embassy_stm32::adc::{Adc, SampleTime};
+use embassy_stm32::peripherals::{ADC1, PC2, PC3};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Sender;
+use embassy_time::{Delay, Duration, Ticker};
+
+use crate::state::{set_health, BatteryState, HEALTH_BATTERY};
+use crate::{BATTERY_LOW, HEALTH_FLAGS, VBAT_MV};
+
+/// ADC sample rate (Hz)
+const ADC_RATE_HZ: u64 = 10;
+const ADC_DT_S: f32 = 1.0 / ADC_RATE_HZ as f32;
+
+/// 12-bit ADC, 3.3 V reference
+const ADC_FULL_SCALE: f32 = 4095.0;
+const ADC_VREF: f32 = 3.3;
+/// VBAT divider on PC3 (10k / 1k)
+const VBAT_DIVIDER: f32 = 11.0;
+/// Current sensor scale from the Betaflight dump (`ibata_scale`, 0.1 mV/A)
+const IBATA_SCALE: f32 = 170.0;
+/// Current sensor on PC2 (A per volt at the pin, ≈ 58.8)
+const CURRENT_SCALE_A_PER_V: f32 = 10_000.0 / IBATA_SCALE;
+
+/// LiPo per-cell limits
+const CELL_FULL_V: f32 = 4.2;
+const CELL_EMPTY_V: f32 = 3.3;
+const CELL_MAX_V: f32 = 4.35;
+/// Under-voltage → arming blocked
+const CELL_LOW_V: f32 = 3.2;
+/// Below this VBAT no pack is connected (USB power only): not an under-voltage
+const VBAT_PRESENT_V: f32 = 2.0;
+
+/// Battery task — reads VBAT (PC3) and current (PC2) at 10 Hz,
+/// integrates consumed mAh and sends BatteryState to telemetry.
+/// Publishes VBAT_MV for the flight log and BATTERY_LOW for the arming checker.
+/// With no pack connected BATTERY_LOW stays clear (bench on USB) and only the
+/// health bit reports it; the cell count is detected when a pack appears.
+#[task]
+pub async fn adc_task(
+    adc1: ADC1,
+    mut vbat_pin: PC3,
+    mut curr_pin: PC2,
+    batt_tx: Sender<'static, CriticalSectionRawMutex, BatteryState, 1>,
+) {
+    let mut adc = Adc::new(adc1, &mut Delay);
+    adc.set_sample_time(SampleTime::Cycles480);
+
+    let to_pin_v = |raw: u16| raw as f32 * ADC_VREF / ADC_FULL_SCALE;
+
+    // 0 while no pack is connected
+    let mut cells = 0u8;
+    let mut consumed_mah = 0.0f32;
+    let mut ticker = Ticker::every(Duration::from_hz(ADC_RATE_HZ));
+    loop {
+        ticker.next().await;
+
+        let voltage_v = to_pin_v(adc.read(&mut vbat_pin)) * VBAT_DIVIDER;
+        let current_a = to_pin_v(adc.read(&mut curr_pin)) * CURRENT_SCALE_A_PER_V;
+
+        if voltage_v < VBAT_PRESENT_V {
+            cells = 0;
+        } else if cells == 0 {
+            // Cell count from the first reading (pack assumed charged-ish when plugged)
+            cells = (voltage_v / CELL_MAX_V) as u8 + 1;
+        }
+        let present = cells > 0;
+        if present {
+            consumed_mah += current_a * ADC_DT_S * 1000.0 / 3600.0;
+        }
+
+        let cell_v = voltage_v / cells.max(1) as f32;
+        let percent = ((cell_v - CELL_EMPTY_V) / (CELL_FULL_V - CELL_EMPTY_V) * 100.0)
+            .clamp(0.0, 100.0) as u8;
+        let low = present && cell_v < CELL_LOW_V;
+
+        VBAT_MV.store((voltage_v * 1000.0) as u16, Ordering::Relaxed);
+        BATTERY_LOW.store(low, Ordering::Relaxed);
+        set_health(&HEALTH_FLAGS, HEALTH_BATTERY, present && !low);
+
+        let _ = batt_tx.try_send(BatteryState {
+            voltage_v,
+            current_a,
+            consumed_mah: consumed_mah as u16,
+            percent,
+        });
+    }
+}
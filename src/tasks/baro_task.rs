@@ -1,37 +1,146 @@
+use core::sync::atomic::Ordering;
+
 use embassy_executor::task;
+use embassy_futures::select::select;
+use embassy_stm32::exti::ExtiInput;
 use embassy_stm32::i2c::I2c;
-use embassy_stm32::peripherals::{DMA1_CH0, DMA1_CH7, I2C1};
+use embassy_stm32::peripherals::{DMA1_CH0, DMA1_CH7, I2C1, PC0};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::Sender;
-use embassy_time::{Duration, Ticker};
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::drivers::dshot::MOTOR_MAIN;
+use crate::drivers::hmc5883::Hmc5883;
+use crate::drivers::kalman::VerticalKalman3;
+use crate::drivers::spl06::{AltitudeStats, PressureTrend, Spl06, SPL06_ADDR_SDO_LOW};
+use crate::state::{
+    set_health, BaroData, MagData, FLAG_MAG_INTERFERENCE, HEALTH_BARO, HEALTH_MAG,
+};
+use crate::tasks::watchdog_task::ALIVE_BARO;
+use crate::{
+    BARO_ALT_CM, HEALTH_FLAGS, MOTOR_DSHOT_CMD, PRESSURE_TREND, QNH_PA, TASK_ALIVE, WEATHER_WARNING,
+};
+
+/// baro_task → fast_loop depth: one spare slot so a sample is not lost when
+/// the next one lands before the fast loop has drained the previous
+pub const BARO_CHAN_DEPTH: usize = 2;
+
+/// SPL06 pressure rate set in `Spl06::init` (PM_RATE = 16 meas/s)
+const BARO_RATE_HZ: f32 = 16.0;
+
+/// Poll anyway if DRDY stays silent this long (INT not wired / missed edge)
+const DRDY_TIMEOUT: Duration = Duration::from_millis(100);
 
-use crate::drivers::spl06::Spl06;
-use crate::state::BaroData;
+/// AltitudeStats published every STATS_DIVIDER samples (~1 Hz)
+const STATS_DIVIDER: u32 = 16;
 
-/// Barometer task — reads SPL06 at 20 Hz and sends BaroData to the fast loop.
+/// One PressureTrend sample per minute
+const TREND_PERIOD: Duration = Duration::from_secs(60);
+
+/// Magnetometer read every MAG_DIVIDER baro samples (16 Hz / 2 = 8 Hz)
+const MAG_DIVIDER: u32 = 2;
+
+/// Mag samples per interference window (1 s at 8 Hz)
+const MAG_WINDOW: usize = 8;
+
+/// DShot throttle range (0 = disarmed, 1..47 = commands)
+const DSHOT_THROTTLE_MIN: u16 = 48;
+const DSHOT_THROTTLE_MAX: u16 = 2047;
+
+/// Barometer task — waits on the SPL06 DRDY interrupt (SDO/INT → PC0, the RSSI pad;
+/// BARO_EOC is not routed on the JHEF405PRO), reads the new sample and HMC5883
+/// every other sample (shared I2C1), sends BaroData / MagData to the fast loop
+/// and the running AltitudeStats to telemetry at ~1 Hz. Feeds the pressure
+/// trend once a minute (PRESSURE_TREND / WEATHER_WARNING).
 #[task]
 pub async fn baro_task(
     mut i2c: I2c<'static, I2C1, DMA1_CH7, DMA1_CH0>,
-    baro_tx: Sender<'static, CriticalSectionRawMutex, BaroData, 1>,
+    mut drdy: ExtiInput<'static, PC0>,
+    baro_tx: Sender<'static, CriticalSectionRawMutex, BaroData, BARO_CHAN_DEPTH>,
+    mag_tx: Sender<'static, CriticalSectionRawMutex, MagData, 1>,
+    stats_tx: Sender<'static, CriticalSectionRawMutex, AltitudeStats, 1>,
 ) {
-    let mut baro = Spl06::new();
+    let mut baro = Spl06::new(SPL06_ADDR_SDO_LOW);
     // SPL06 init
     if baro.init(&mut i2c).await.is_err() {
         // If init fails we still loop but data will be zero
     }
+    let _ = baro.set_drdy_interrupt_pin(&mut i2c).await;
+    let mut mag = Hmc5883::new();
+    let mag_ok = mag.init(&mut i2c).await.is_ok();
+    set_health(&HEALTH_FLAGS, HEALTH_MAG, mag_ok);
+    let mut tick: u32 = 0;
+    let mut qnh_bits: u32 = 0;
+    let mut kf3: Option<VerticalKalman3> = None;
+    let mut last_sample = Instant::now();
+    let mut mag_window = [[0i16; 3]; MAG_WINDOW];
+    let mut mag_n: usize = 0;
+    let mut trend = PressureTrend::new();
+    let mut last_trend: Option<Instant> = None;
 
-    let mut ticker = Ticker::every(Duration::from_hz(20));
     loop {
-        ticker.next().await;
-
-        if let Ok((alt_m, press_pa, temp_c)) = baro.read_pressure_altitude(&mut i2c).await {
-            let data = BaroData {
-                alt_m,
-                pressure_hpa: press_pa / 100.0,
-                temp_c,
-            };
-            // Overwrite any unread value — always send latest
-            let _ = baro_tx.try_send(data);
+        let _ = select(drdy.wait_for_rising_edge(), Timer::after(DRDY_TIMEOUT)).await;
+        TASK_ALIVE.fetch_or(ALIVE_BARO, Ordering::Relaxed);
+
+        // QNH update from USB (QNH=<Pa>)
+        let bits = QNH_PA.load(Ordering::Relaxed);
+        if bits != qnh_bits {
+            qnh_bits = bits;
+            baro.set_qnh(f32::from_bits(bits));
+        }
+
+        let baro_res = baro.read_if_ready(&mut i2c).await;
+        set_health(&HEALTH_FLAGS, HEALTH_BARO, baro_res.is_ok());
+        let Ok(Some((alt_m, press_pa, temp_c))) = baro_res else { continue };
+        tick = tick.wrapping_add(1);
+        let now = Instant::now();
+        let dt = (now - last_sample).as_micros() as f32 * 1e-6;
+        last_sample = now;
+        let kf = kf3.get_or_insert_with(|| VerticalKalman3::new(alt_m));
+        kf.predict(dt.clamp(0.5 / BARO_RATE_HZ, 2.0 / BARO_RATE_HZ));
+        kf.update(alt_m);
+        let kf_state = kf.state();
+
+        let data = BaroData {
+            alt_m,
+            pressure_hpa: press_pa / 100.0,
+            temp_c,
+            kf_vel_ms: kf_state.velocity,
+            kf_acc_ms2: kf_state.accel_ms2(),
+        };
+        // Queue for the fast loop (dropped only if two samples are already pending)
+        let _ = baro_tx.try_send(data);
+        BARO_ALT_CM.store((alt_m * 100.0) as i32, Ordering::Relaxed);
+
+        if last_trend.is_none_or(|t| now - t >= TREND_PERIOD) {
+            last_trend = Some(now);
+            trend.push(data.pressure_hpa);
+            PRESSURE_TREND.store(trend.trend_hpa_per_hour().to_bits(), Ordering::Relaxed);
+            WEATHER_WARNING.store(trend.weather_warning(), Ordering::Relaxed);
+        }
+
+        let stats = baro.update_stats(alt_m);
+        if tick % STATS_DIVIDER == 0 {
+            let _ = stats_tx.try_send(stats);
+        }
+
+        if mag_ok && tick % MAG_DIVIDER == 0 {
+            let mag_res = mag.read_mag(&mut i2c).await;
+            set_health(&HEALTH_FLAGS, HEALTH_MAG, mag_res.is_ok());
+            if let Ok([x, y, z]) = mag_res {
+                let _ = mag_tx.try_send(MagData { x, y, z });
+
+                mag_window[mag_n] = [x, y, z];
+                mag_n += 1;
+                if mag_n == MAG_WINDOW {
+                    mag_n = 0;
+                    let cmd = MOTOR_DSHOT_CMD[MOTOR_MAIN].load(Ordering::Relaxed);
+                    let throttle = cmd.saturating_sub(DSHOT_THROTTLE_MIN) as f32
+                        / (DSHOT_THROTTLE_MAX - DSHOT_THROTTLE_MIN) as f32;
+                    let interf = mag.detect_interference(&mag_window, throttle);
+                    set_health(&HEALTH_FLAGS, FLAG_MAG_INTERFERENCE, interf);
+                }
+            }
         }
     }
 }
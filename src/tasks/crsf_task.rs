@@ -3,26 +3,88 @@ use embassy_stm32::peripherals::{DMA1_CH2, UART4};
 use embassy_stm32::usart::UartRx;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::Sender;
+use embassy_time::{Duration, Instant, Timer};
+use embassy_futures::select::{select, Either};
 
-use crate::drivers::crsf::CrsfParser;
-use crate::state::RcData;
+use crate::drivers::crsf::{ChannelSmoother, CrsfParser};
+use crate::state::{LinkData, LinkDiagnostics, RcData};
 
-/// CRSF/ELRS task — reads UART4 RX continuously and sends RcData on each parsed frame.
+/// Republish interval while no CRSF bytes arrive
+const FAILSAFE_POLL_MS: u64 = 100;
+
+/// RC channel smoothing (0.0 = bypass, 0.9 = heavy)
+const RC_SMOOTH_ALPHA: f32 = 0.3;
+
+/// LinkData is also republished at this interval with fresh frame statistics
+const LINK_DIAG_PERIOD_MS: u32 = 1000;
+
+/// CRSF/ELRS task — reads UART4 RX continuously and sends RcData (smoothed
+/// channels) on each parsed frame,
+/// LinkData on each LINK_STATISTICS frame and every LINK_DIAG_PERIOD_MS
+/// (CRC / framing counters keep flowing even without link statistics).
+/// When the link goes silent RcData keeps flowing every FAILSAFE_POLL_MS so the
+/// failsafe flag reaches fast_loop.
 #[task]
 pub async fn crsf_task(
     mut crsf_rx: UartRx<'static, UART4, DMA1_CH2>,
     crsf_tx: Sender<'static, CriticalSectionRawMutex, RcData, 1>,
+    link_tx: Sender<'static, CriticalSectionRawMutex, LinkData, 1>,
 ) {
     let mut parser = CrsfParser::new();
+    let mut smoother = ChannelSmoother::new(RC_SMOOTH_ALPHA);
+    let mut channels = [0u16; 16];
     let mut buf = [0u8; 64];
+    let mut link_count: u16 = 0;
+    let mut last_diag_ms: u32 = 0;
 
     loop {
         // CRSF frames are small (26 bytes max). Read whatever arrives.
-        if let Ok(()) = crsf_rx.read(&mut buf).await {
+        let rx = select(
+            crsf_rx.read(&mut buf),
+            Timer::after(Duration::from_millis(FAILSAFE_POLL_MS)),
+        )
+        .await;
+        parser.now_ms = Instant::now().as_millis() as u32;
+
+        if let Either::First(Ok(())) = rx {
             if let Some(parsed) = parser.push_bytes(&buf) {
-                let data = RcData { channels: parsed.channels };
+                parser.last_frame_ms = parser.now_ms;
+                channels = smoother.smooth(parsed.channels);
+                let data = RcData {
+                    channels,
+                    failsafe: parser.is_failsafe(),
+                    frame_age_ms: 0,
+                };
                 let _ = crsf_tx.try_send(data);
             }
+        } else {
+            // Silence: republish the last channels with the current failsafe state
+            let data = RcData {
+                channels,
+                failsafe: parser.is_failsafe(),
+                frame_age_ms: parser.now_ms.wrapping_sub(parser.last_frame_ms),
+            };
+            let _ = crsf_tx.try_send(data);
+        }
+
+        let diag_due = parser.now_ms.wrapping_sub(last_diag_ms) >= LINK_DIAG_PERIOD_MS;
+        if parser.link_stats_count != link_count || diag_due {
+            link_count = parser.link_stats_count;
+            last_diag_ms = parser.now_ms;
+            let ls = &parser.link_stats;
+            let _ = link_tx.try_send(LinkData {
+                rssi_dbm: ls.rssi_dbm(),
+                lq: ls.uplink_lq,
+                snr: ls.uplink_snr,
+                rf_mode: ls.rf_mode,
+                tx_power: ls.uplink_tx_power,
+                diag: LinkDiagnostics {
+                    crc_errors: parser.crc_errors,
+                    frame_errors: parser.frame_errors,
+                    good_frames: parser.good_frames,
+                    loss_pct: parser.frame_loss_pct(),
+                },
+            });
         }
     }
 }
@@ -1,25 +1,48 @@
 use embassy_executor::task;
-use embassy_stm32::peripherals::SPI1;
+use embassy_futures::select::select;
+use embassy_stm32::exti::ExtiInput;
+use embassy_stm32::peripherals::{PB13, SPI1};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::{Receiver, Sender};
-use embassy_time::{Duration, Instant, Ticker};
+use embassy_time::{Duration, Instant, Timer};
 
-use crate::drivers::ekf::AttitudeEkf;
+use crate::drivers::dshot::{ESC_OUTPUT_LOCKED, MOTOR_MAIN, MOTOR_TAB};
+use crate::drivers::ekf::{AttitudeEkf, MAG_FIELD_TOLERANCE_UT};
 use crate::drivers::filter::BiquadFilter;
+use crate::drivers::hmc5883::HMC5883_UT_PER_LSB;
+use crate::drivers::flash::{
+    LogRecord, LOG_FLAG_APOGEE, LOG_FLAG_ARMED, LOG_FLAG_HIGH_G, LOG_FLAG_LANDED,
+    LOG_FLAG_LAUNCHED,
+};
 use crate::drivers::icm42688::Icm42688;
 use crate::drivers::kalman::VerticalKalman;
 use crate::drivers::roll::{
     crsf_to_unit, max_roll_setpoint_from_stick, roll_output_to_tab_target_deg,
     signed_unit_to_dshot_3d, unit_to_dshot, GearRatio, GearedTabController, RollController,
 };
-use crate::state::{AttitudeState, BaroData, GpsData, RcData};
-use crate::TAB_MOTOR_DSHOT_CMD;
+use crate::drivers::spl06::{ApogeeDetector, APOGEE_CONFIRM_SAMPLES};
+use crate::state::{
+    set_health, ArmingChecker, ArmingState, AttitudeState, BaroData, FlightPhase, GpsNav,
+    MagData, PhaseTimer, RcData, FLAG_MAG_INTERFERENCE, HEALTH_CRSF, HEALTH_IMU,
+};
+use crate::tasks::baro_task::BARO_CHAN_DEPTH;
+use crate::tasks::logger_task::LOG_CHAN_DEPTH;
+use crate::tasks::watchdog_task::ALIVE_FAST_LOOP;
+use crate::calibration;
+use crate::{
+    ARMED, ATTITUDE, BARO_ALT_CM, BATTERY_LOW, FLIGHT_EVENTS, GPS_RING, GPS_RING_FROZEN,
+    HEALTH_FLAGS, IMU_FAULT, MOTOR_DSHOT_CMD, PHASE_DURATIONS, RECALIB_REQUEST, ROLL_GAINS,
+    ROLL_GAINS_UPDATED, RTC_UNIX_OFFSET, TASK_ALIVE, USB_ARM_INHIBIT, VBAT_MV,
+};
 use core::sync::atomic::Ordering;
+use micromath::F32Ext;
 
 // ── Filter chain constants ────────────────────────────────────────────────────
 
 /// Fast loop target: 1 kHz
 const FAST_LOOP_HZ: u64 = 1000;
+/// Run the loop anyway if the IMU data-ready interrupt stays silent this long
+const IMU_DRDY_TIMEOUT: Duration = Duration::from_millis(2);
 /// Nominal sample rate for Biquad coefficient pre-computation
 const SAMPLE_RATE: f32 = 1000.0;
 /// Notch filter center frequency (Hz) — set to dominant rocket body resonance
@@ -33,14 +56,43 @@ const GYRO_LPF_Q: f32 = 0.707;
 /// Accel LPF cutoff (Hz)
 const ACCEL_LPF_CUTOFF: f32 = 20.0;
 
-const ESC_OUTPUT_LOCKED: bool = true;
+/// Flight log rate (Hz) — one LogRecord every FAST_LOOP_HZ / LOG_RATE_HZ iterations
+const LOG_RATE_HZ: u64 = 100;
+
 const ROLL_MAX_DEG: f32 = 35.0;
+/// Roll stick expo (0 = linear, 1 = pure cubic)
+const ROLL_EXPO: f32 = 0.3;
+/// Roll D-term PT1 cutoff (Hz)
+const ROLL_D_LPF_CUTOFF: f32 = 40.0;
+/// |tanθ| cap in the Euler roll rate (tan 80°): the term is unbounded at
+/// ±90° pitch and would saturate the D-term
+const ROLL_RATE_TAN_PITCH_MAX: f32 = 5.67;
+
+/// Flight phase thresholds (earth-frame specific force, G)
+const BOOST_ACCEL_G: f32 = 2.5;
+const BURNOUT_ACCEL_G: f32 = 0.1;
+/// Apogee → Descent delay
+const APOGEE_HOLD_MS: u32 = 200;
+/// Descent → Landed: VerticalKalman::land_detect true for LANDED_HOLD_MS
+const LANDED_HOLD_MS: u32 = 5000;
+
+/// GPS vertical speed variance (m²/s²) — M10 velDown is ~0.3-0.5 m/s 1σ
+const R_GPS_VZ: f32 = 0.25;
+
+/// Expected earth-frame magnetic field (unit vector, z-up like the EKF accel model):
+/// north with ~61° inclination (France).
+const MAG_REF: [f32; 3] = [0.485, 0.0, -0.875];
+
+/// Mag samples averaged on the ground for the expected |m| of update_mag_gated
+const MAG_FIELD_CAL_SAMPLES: u32 = 100;
 
 // ── Calibration parameters (filled from main after static calib) ──────────────
 
 pub struct FastLoopConfig {
     pub gyro_bias: [f32; 3],
     pub accel_bias: [f32; 3],
+    /// Raw static accel average (LSB, gravity included) — EKF initial attitude
+    pub pad_accel: [f32; 3],
 }
 
 // ── Task ─────────────────────────────────────────────────────────────────────
@@ -48,11 +100,13 @@ pub struct FastLoopConfig {
 #[task]
 pub async fn fast_loop_task(
     mut imu: Icm42688<'static, SPI1>,
+    mut imu_drdy: ExtiInput<'static, PB13>,
     config: FastLoopConfig,
-    baro_rx: Receiver<'static, CriticalSectionRawMutex, BaroData, 1>,
-    gps_rx: Receiver<'static, CriticalSectionRawMutex, GpsData, 1>,
+    baro_rx: Receiver<'static, CriticalSectionRawMutex, BaroData, BARO_CHAN_DEPTH>,
+    mag_rx: Receiver<'static, CriticalSectionRawMutex, MagData, 1>,
+    gps_rx: Receiver<'static, CriticalSectionRawMutex, GpsNav, 1>,
     crsf_rx: Receiver<'static, CriticalSectionRawMutex, RcData, 1>,
-    attitude_tx: Sender<'static, CriticalSectionRawMutex, AttitudeState, 1>,
+    log_tx: Sender<'static, CriticalSectionRawMutex, LogRecord, LOG_CHAN_DEPTH>,
 ) {
     // ── Filter instances ──────────────────────────────────────────────────────
     // Notch filter per gyro axis
@@ -78,25 +132,48 @@ pub async fn fast_loop_task(
 
     // ── Estimators ────────────────────────────────────────────────────────────
     let mut ekf = AttitudeEkf::new();
+    ekf.set_noise(calibration::Q_QUAT, calibration::Q_GBIAS, calibration::R_ACCEL_NORMAL);
+    // Start from the rail tilt instead of level
+    ekf.initialize_from_accel(config.pad_accel[0], config.pad_accel[1], config.pad_accel[2]);
     let mut kalman = VerticalKalman::new();
 
     // ── Controllers ───────────────────────────────────────────────────────────
-    let mut roll_ctrl = RollController::new(4.0, 0.8, 0.08, 0.4, 1.0);
-    let mut tab_gear_ctrl = GearedTabController::new(0.015, 0.002, 20.0, 1.0, 360.0);
+    // kp, ki, kd, kb (= ki), i_limit, out_limit, kff, D cutoff, loop rate —
+    // gains as loaded from flash by main
+    let g = ROLL_GAINS.lock(|c| *c.borrow());
+    let mut roll_ctrl = RollController::new(
+        g.kp, g.ki, g.kd, g.ki, g.i_limit, g.out_limit, g.kff, ROLL_D_LPF_CUTOFF, SAMPLE_RATE,
+    );
+    let mut tab_gear_ctrl = GearedTabController::new(0.015, 0.002, 20.0, 25.0, 1.0, 360.0, 0.002);
 
     // ── Cached slow-loop data (updated from channels when available) ──────────
     let mut baro = BaroData::default();
-    let mut gps  = GpsData::default();
+    let mut gps  = GpsNav::default();
     let mut rc   = RcData::default();
     let mut ground_alt = 0.0f32;
     let mut ground_calibrated = false;
+    let mut apogee_detected = false;
+    let mut apogee_detector = ApogeeDetector::new(APOGEE_CONFIRM_SAMPLES);
+    let mut was_armed = false;
+    let mut arming = ArmingChecker::new();
+    let mut rc_rx_ms: u32 = 0;
+    let mut ekf_resets: u16 = 0;
+    let mut phase = FlightPhase::Idle;
+    let mut phase_since_ms: u32 = 0;
+    let mut phase_timer = PhaseTimer::new();
+    let mut low_alt_since_ms: Option<u32> = None;
+    // Pre-flight |m| average (µT); the gate is off until it is complete
+    let mut mag_field_sum = 0.0f32;
+    let mut mag_field_n: u32 = 0;
 
     // ── Timing ────────────────────────────────────────────────────────────────
-    let mut ticker = Ticker::every(Duration::from_hz(FAST_LOOP_HZ));
     let mut last = Instant::now();
+    let mut log_tick: u64 = 0;
 
     loop {
-        ticker.next().await;
+        // Paced by the ICM-42688 data-ready (INT1, 1 kHz ODR): no ticker phase jitter
+        select(imu_drdy.wait_for_rising_edge(), Timer::after(IMU_DRDY_TIMEOUT)).await;
+        TASK_ALIVE.fetch_or(ALIVE_FAST_LOOP, Ordering::Relaxed);
 
         // Precise dt measurement
         let now = Instant::now();
@@ -107,8 +184,12 @@ pub async fn fast_loop_task(
         // ── A. Read IMU (SPI @ 10 MHz, non-blocking) ─────────────────────────
         let (accel_raw, gyro_raw) = match imu.read_all().await {
             Ok(v) => v,
-            Err(_) => continue, // skip iteration on SPI error
+            Err(_) => {
+                set_health(&HEALTH_FLAGS, HEALTH_IMU, false);
+                continue; // skip iteration on SPI error
+            }
         };
+        set_health(&HEALTH_FLAGS, HEALTH_IMU, true);
 
         // ── B. Calibration correction ─────────────────────────────────────────
         let ax_c = accel_raw[0] as f32 - config.accel_bias[0];
@@ -149,7 +230,35 @@ pub async fn fast_loop_task(
 
         // ── E. EKF predict + update ───────────────────────────────────────────
         ekf.predict(dt, gx_rad, gy_rad, gz_rad);
+        // On the pad: zero rate is a gyro-bias measurement
+        if phase == FlightPhase::Idle {
+            ekf.zupt_update(gx_rad, gy_rad, gz_rad);
+        }
         ekf.update_accel(ax_g, ay_g, az_g);
+        if let Ok(mag) = mag_rx.try_receive() {
+            ekf.set_mag_interference(
+                HEALTH_FLAGS.load(Ordering::Relaxed) & FLAG_MAG_INTERFERENCE != 0,
+            );
+            let mx = mag.x as f32 * HMC5883_UT_PER_LSB;
+            let my = mag.y as f32 * HMC5883_UT_PER_LSB;
+            let mz = mag.z as f32 * HMC5883_UT_PER_LSB;
+            if mag_field_n < MAG_FIELD_CAL_SAMPLES {
+                if phase == FlightPhase::Idle {
+                    mag_field_sum += (mx * mx + my * my + mz * mz).sqrt();
+                    mag_field_n += 1;
+                }
+                ekf.update_mag(mx, my, mz, MAG_REF);
+            } else {
+                let expected_ut = mag_field_sum / MAG_FIELD_CAL_SAMPLES as f32;
+                ekf.update_mag_gated(mx, my, mz, expected_ut, MAG_FIELD_TOLERANCE_UT, MAG_REF);
+            }
+        }
+        // Diverged (P blown up / NaN quaternion) → restart from P0, telemetry reports it
+        let ekf_health = ekf.health_check();
+        if !ekf_health.is_healthy {
+            ekf.reset();
+            ekf_resets = ekf_resets.wrapping_add(1);
+        }
 
         let (roll_rad, pitch_rad, yaw_rad) = ekf.get_euler();
 
@@ -158,10 +267,11 @@ pub async fn fast_loop_task(
         let (_, _, az_earth) = ekf.rotate_to_earth(ax_g, ay_g, az_g);
         let az_lin_ms2 = (az_earth - 1.0) * 9.81; // remove 1G gravity, → m/s²
         let az_filt = az_lpf.filter(az_lin_ms2);
-        kalman.predict(dt, az_filt);
+        // Dual rate: propagate every tick (1 kHz), correct only on new baro samples (16 Hz)
+        kalman.predict_only(dt, az_filt);
 
-        // Check for new baro data
-        if let Ok(new_baro) = baro_rx.try_receive() {
+        // Every queued baro sample is a separate measurement
+        while let Ok(new_baro) = baro_rx.try_receive() {
             baro = new_baro;
             // Ground calibration on first valid sample
             if !ground_calibrated && baro.alt_m != 0.0 {
@@ -174,30 +284,147 @@ pub async fn fast_loop_task(
 
         let k_state = kalman.state();
 
+        // Flight phase transitions (Idle ↔ Armed is handled with the arm switch)
+        let now_ms = now.as_millis() as u32;
+        // Baro apogee with hysteresis: Kalman velocity sign alone chatters near the top
+        let apogee_confirmed = phase == FlightPhase::Coast && {
+            let baro_agl_m = BARO_ALT_CM.load(Ordering::Relaxed) as f32 * 0.01 - ground_alt;
+            apogee_detector.update(baro_agl_m)
+        };
+        let az_filt_g = az_filt / 9.81 + 1.0;
+        let next_phase = match phase {
+            FlightPhase::Armed if az_filt_g > BOOST_ACCEL_G => FlightPhase::Boost,
+            FlightPhase::Boost if az_filt_g < BURNOUT_ACCEL_G => FlightPhase::Coast,
+            FlightPhase::Coast if apogee_confirmed => FlightPhase::Apogee,
+            FlightPhase::Apogee if now_ms.wrapping_sub(phase_since_ms) >= APOGEE_HOLD_MS => {
+                FlightPhase::Descent
+            }
+            FlightPhase::Descent => {
+                if kalman.land_detect(k_state.position) {
+                    let since = *low_alt_since_ms.get_or_insert(now_ms);
+                    if now_ms.wrapping_sub(since) >= LANDED_HOLD_MS {
+                        FlightPhase::Landed
+                    } else {
+                        phase
+                    }
+                } else {
+                    low_alt_since_ms = None;
+                    phase
+                }
+            }
+            _ => phase,
+        };
+        if next_phase != phase {
+            phase = next_phase;
+            phase_since_ms = now_ms;
+            FLIGHT_EVENTS.lock(|l| l.borrow_mut().push(phase, now_ms));
+            // The one apogee of the flight: freezes the GPS trajectory ring and
+            // sets LOG_FLAG_APOGEE — hook for recovery / payload deployment
+            if phase == FlightPhase::Apogee {
+                apogee_detected = true;
+                GPS_RING_FROZEN.store(true, Ordering::Relaxed);
+            }
+        }
+
         // ── G. Slow data refresh (non-blocking) ───────────────────────────────
         if let Ok(new_gps) = gps_rx.try_receive() {
             gps = new_gps;
+            if gps.vz_valid && gps.pdop_ok {
+                kalman.update_gps_vz(gps.vz_ms, R_GPS_VZ);
+            }
         }
         if let Ok(new_rc) = crsf_rx.try_receive() {
             rc = new_rc;
+            rc_rx_ms = now.as_millis() as u32;
         }
 
         // ── H. Flight control ─────────────────────────────────────────────────
         let roll_stick   = crsf_to_unit(rc.channels[0]);
         let throttle_unit = ((rc.channels[2] as f32 - 172.0) / (1811.0 - 172.0)).clamp(0.0, 1.0);
-        let armed        = rc.channels[4] > 1200;
-        let gear_ratio   = GearRatio::from_aux_channel(rc.channels[5]);
-        let roll_setpoint = max_roll_setpoint_from_stick(roll_stick, ROLL_MAX_DEG);
+        // Arming interlock: link, attitude, throttle low, GPS. FailSafe → zero output
+        let link_age_ms = if rc.failsafe {
+            u32::MAX
+        } else {
+            rc.frame_age_ms.saturating_add((now.as_millis() as u32).wrapping_sub(rc_rx_ms))
+        };
+        arming.set_battery_low(BATTERY_LOW.load(Ordering::Relaxed));
+        let arming_state = arming.update(
+            rc.channels[4] > 1200
+                && !USB_ARM_INHIBIT.load(Ordering::Relaxed)
+                && !IMU_FAULT.load(Ordering::Relaxed),
+            link_age_ms,
+            ekf_health.is_healthy,
+            rc.channels[2],
+            gps.sats,
+        );
+        let armed        = arming_state == ArmingState::Armed;
+        ARMED.store(armed, Ordering::Relaxed);
+
+        // USB commands: live roll gains, ground re-calibration (disarmed only)
+        if ROLL_GAINS_UPDATED.swap(false, Ordering::Relaxed) {
+            roll_ctrl.set_gains(&ROLL_GAINS.lock(|c| *c.borrow()));
+        }
+        if !armed && RECALIB_REQUEST.swap(false, Ordering::Relaxed) {
+            ground_alt = baro.alt_m;
+            kalman.reset();
+            ekf.reset();
+        }
+        set_health(&HEALTH_FLAGS, HEALTH_CRSF, link_age_ms < crate::state::ARM_LINK_TIMEOUT_MS);
+        // Idle → Armed: re-zero the vertical filter on the pad (AGL = 0, at rest)
+        if armed && !was_armed {
+            if ground_calibrated {
+                ground_alt = baro.alt_m;
+            }
+            kalman.reset_to(0.0, 0.0);
+            if matches!(phase, FlightPhase::Idle | FlightPhase::Landed) {
+                phase = FlightPhase::Armed;
+                phase_since_ms = now.as_millis() as u32;
+                low_alt_since_ms = None;
+                FLIGHT_EVENTS.lock(|l| l.borrow_mut().push(phase, phase_since_ms));
+                // New flight: apogee still ahead, record a fresh GPS trajectory
+                apogee_detected = false;
+                GPS_RING.lock(|r| r.borrow_mut().clear());
+                GPS_RING_FROZEN.store(false, Ordering::Relaxed);
+            }
+        }
+        if !armed && was_armed {
+            apogee_detector.reset();
+        }
+        // Disarmed on the pad → Idle; once in flight the phase runs on
+        if !armed && phase == FlightPhase::Armed {
+            phase = FlightPhase::Idle;
+            phase_since_ms = now.as_millis() as u32;
+            FLIGHT_EVENTS.lock(|l| l.borrow_mut().push(phase, phase_since_ms));
+        }
+        was_armed = armed;
+
+        // Phase durations for the post-flight summary (boost = burn time)
+        if let Some((done, duration_ms)) = phase_timer.update(phase, now.as_millis() as u32) {
+            PHASE_DURATIONS.lock(|d| {
+                let mut durations = d.get();
+                durations.record(done, duration_ms);
+                d.set(durations);
+            });
+        }
+
+        let gear_ratio   = GearRatio::from_aux_channel_3pos(rc.channels[5]);
+        let roll_setpoint = max_roll_setpoint_from_stick(roll_stick, ROLL_MAX_DEG, ROLL_EXPO);
+
+        // D-term on the Euler roll rate, the derivative of roll_rad itself:
+        // φ̇ = p + (q·sinφ + r·cosφ)·tanθ (heading-independent)
+        let tan_pitch = pitch_rad.tan().clamp(-ROLL_RATE_TAN_PITCH_MAX, ROLL_RATE_TAN_PITCH_MAX);
+        let roll_rate = gx_rad + (gy_rad * roll_rad.sin() + gz_rad * roll_rad.cos()) * tan_pitch;
 
         let tab_cmd_roll = if armed {
-            roll_ctrl.update(dt, roll_setpoint, roll_rad, gx_rad)
+            roll_ctrl.update(dt, roll_setpoint, roll_rad, roll_rate)
         } else {
             roll_ctrl.reset();
             0.0
         };
 
         let motor_throttle = if armed { throttle_unit } else { 0.0 };
-        let _esc_cmd = unit_to_dshot(motor_throttle, armed);
+        let esc_cmd = if ESC_OUTPUT_LOCKED { 0 } else { unit_to_dshot(motor_throttle, armed) };
+        MOTOR_DSHOT_CMD[MOTOR_MAIN].store(esc_cmd, Ordering::Relaxed);
 
         let tab_target_deg = roll_output_to_tab_target_deg(tab_cmd_roll, 20.0);
         let (_, tab_motor_cmd_signed) = if armed {
@@ -212,7 +439,7 @@ pub async fn fast_loop_task(
         } else {
             signed_unit_to_dshot_3d(tab_motor_cmd_signed, armed)
         };
-        TAB_MOTOR_DSHOT_CMD.store(tab_motor_dshot, Ordering::Relaxed);
+        MOTOR_DSHOT_CMD[MOTOR_TAB].store(tab_motor_dshot, Ordering::Relaxed);
 
         // ── I. Publish attitude state for telemetry task ──────────────────────
         let state = AttitudeState {
@@ -222,8 +449,45 @@ pub async fn fast_loop_task(
             alt_m:   k_state.position,
             vel_ms:  k_state.velocity,
             is_high_g: ekf.debug.is_high_g,
+            ekf_debug: ekf.debug,
+            ekf_p_trace: ekf_health.p_trace_per_state(),
+            ekf_covar: ekf.covar_diag(),
+            ekf_resets,
+            baro_fault_count: kalman.diagnostics().baro_fault_count,
+            baro_stuck: kalman.diagnostics().baro_stuck,
+            phase,
         };
-        // Non-blocking send; telemetry task may miss a frame if it's busy
-        let _ = attitude_tx.try_send(state);
+        ATTITUDE.store(state);
+
+        // ── J. Flight log @ 100 Hz ────────────────────────────────────────────
+        log_tick = log_tick.wrapping_add(1);
+        if log_tick % (FAST_LOOP_HZ / LOG_RATE_HZ) == 0 {
+            let mut flags = 0u8;
+            if armed { flags |= LOG_FLAG_ARMED; }
+            if ekf.debug.is_high_g { flags |= LOG_FLAG_HIGH_G; }
+            if apogee_detected { flags |= LOG_FLAG_APOGEE; }
+            if phase == FlightPhase::Landed { flags |= LOG_FLAG_LANDED; }
+            if matches!(
+                phase,
+                FlightPhase::Boost | FlightPhase::Coast | FlightPhase::Apogee | FlightPhase::Descent
+            ) {
+                flags |= LOG_FLAG_LAUNCHED;
+            }
+            let baro_agl_cm = (baro.alt_m - ground_alt) * 100.0;
+            let record = LogRecord {
+                ts_ms: now.as_millis() as u32,
+                accel: accel_raw,
+                gyro: gyro_raw,
+                baro_alt_cm: baro_agl_cm.clamp(i16::MIN as f32, i16::MAX as f32) as i16,
+                vbat_mv: VBAT_MV.load(Ordering::Relaxed),
+                flags,
+                unix_s: match RTC_UNIX_OFFSET.load(Ordering::Relaxed) {
+                    0 => 0,
+                    offset => offset + now.as_secs() as u32,
+                },
+            };
+            // Drop the record if the logger is stalled on an erase
+            let _ = log_tx.try_send(record);
+        }
     }
 }
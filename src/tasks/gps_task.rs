@@ -1,45 +1,147 @@
 use embassy_executor::task;
 use embassy_stm32::peripherals::{DMA1_CH1, DMA1_CH3, USART3};
-use embassy_stm32::usart::Uart;
+use embassy_stm32::usart::{Config as UsartConfig, Uart};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::Sender;
-use embassy_time::{Duration, Timer};
+use embassy_time::{Duration, Instant, Timer};
 use embassy_futures::select::{select, Either};
 
-use crate::drivers::gps::NmeaParser;
-use crate::state::GpsData;
+use crate::drivers::gps::{
+    ubx_cfg_enable_navpvt, ubx_cfg_gnss_all_115200, GpsDeadReckoning, GpsPoint, GpsState, NmeaParser, BAUD_CANDIDATES,
+};
+use crate::state::{set_health, GpsNav, HEALTH_GPS};
+use crate::tasks::watchdog_task::ALIVE_GPS;
+use crate::{GPS_RING, GPS_RING_FROZEN, HEALTH_FLAGS, TASK_ALIVE};
+use core::sync::atomic::Ordering;
 
-/// GPS task — reads NMEA from USART3 and sends GpsData when a new fix is parsed.
+/// u-blox factory default UART rate
+const GPS_FACTORY_BAUD: u32 = 9600;
+
+/// Trajectory ring decimation: one point per second
+const RING_PERIOD_MS: u32 = 1000;
+
+/// GPS task — reads NMEA + UBX NAV-PVT from USART3 and sends a GpsNav to
+/// fast_loop and telemetry when a new fix is parsed.
+/// Falls back to baud-rate probing (BAUD_CANDIDATES) when the module stays silent.
 #[task]
 pub async fn gps_task(
-    mut gps_uart: Uart<'static, USART3, DMA1_CH3, DMA1_CH1>,
-    gps_tx: Sender<'static, CriticalSectionRawMutex, GpsData, 1>,
+    gps_uart: Uart<'static, USART3, DMA1_CH3, DMA1_CH1>,
+    gps_tx: Sender<'static, CriticalSectionRawMutex, GpsNav, 1>,
+    gps_tel_tx: Sender<'static, CriticalSectionRawMutex, GpsNav, 1>,
 ) {
+    let (mut uart_tx, mut uart_rx) = gps_uart.split();
     let mut parser = NmeaParser::new();
     let mut buf = [0u8; 512];
+    let mut last_ring_ms: u32 = 0;
+    let mut uart_baud = BAUD_CANDIDATES[0];
+    let mut normalized = false;
+    let mut dead_reckoning = GpsDeadReckoning::new();
+    // Start the silence timeout now, not at t = 0 (boot takes seconds)
+    parser.reset_baud(Instant::now().as_millis() as u32);
+
+    // Binary NAV-PVT: once it flows the parser prefers it over GGA/RMC
+    let (cfg, len) = ubx_cfg_enable_navpvt();
+    let _ = uart_tx.write(&cfg[..len]).await;
 
     loop {
         // Wait for a burst of NMEA data (GPS sends at 10 Hz → 100ms window)
-        match select(
-            gps_uart.read_until_idle(&mut buf),
+        let rx = select(
+            uart_rx.read_until_idle(&mut buf),
             Timer::after(Duration::from_millis(110)),
         )
-        .await
-        {
+        .await;
+
+        TASK_ALIVE.fetch_or(ALIVE_GPS, Ordering::Relaxed);
+        let now_ms = Instant::now().as_millis() as u32;
+        let n = match rx {
+            Either::First(Ok(n)) => n,
+            _ => 0,
+        };
+        let was_probing = matches!(parser.data.state, GpsState::BaudProbing { .. });
+        parser.update_timing(now_ms, n);
+        set_health(&HEALTH_FLAGS, HEALTH_GPS, parser.data.state == GpsState::ReceivingData);
+
+        if was_probing && parser.data.state == GpsState::Initialised {
+            if parser.baudrate() != BAUD_CANDIDATES[0] && !normalized {
+                // Locked on a foreign rate: ask the module to come back to the default
+                normalized = true;
+                // VALSET CFG-UART1-BAUDRATE; at 9600 the module was factory reset and
+                // also needs its constellations back, sent at the new rate
+                let factory = parser.baudrate() == GPS_FACTORY_BAUD;
+                let ((cfg, len), (gnss, gnss_len)) = ubx_cfg_gnss_all_115200();
+                let _ = uart_tx.write(&cfg[..len]).await;
+                Timer::after(Duration::from_millis(20)).await; // let the last byte shift out
+                // Follow the module before sending anything else
+                parser.reset_baud(now_ms);
+                uart_baud = parser.baudrate();
+                let mut config = UsartConfig::default();
+                config.baudrate = uart_baud;
+                let _ = uart_tx.set_config(&config);
+                let _ = uart_rx.set_config(&config);
+                if factory {
+                    let _ = uart_tx.write(&gnss[..gnss_len]).await;
+                }
+            }
+            let (cfg, len) = ubx_cfg_enable_navpvt();
+            let _ = uart_tx.write(&cfg[..len]).await;
+        }
+
+        if parser.baudrate() != uart_baud {
+            uart_baud = parser.baudrate();
+            let mut config = UsartConfig::default();
+            config.baudrate = uart_baud;
+            let _ = uart_tx.set_config(&config);
+            let _ = uart_rx.set_config(&config);
+            continue;
+        }
+
+        match rx {
             Either::First(Ok(n)) => {
                 parser.push_data(&buf[..n]);
 
                 let d = &parser.data;
-                let data = GpsData {
-                    lat: d.lat,
-                    lon: d.lon,
+                // Fix lost: propagate the last fix for up to GPS_DR_MAX_MS
+                // instead of repeating stale coordinates
+                let (lat, lon) = if d.fix {
+                    dead_reckoning.set_fix(d.lat, d.lon, d.speed_cms, d.course, now_ms);
+                    (d.lat, d.lon)
+                } else if dead_reckoning.is_active(now_ms) {
+                    dead_reckoning.update(now_ms)
+                } else {
+                    (d.lat, d.lon)
+                };
+                let data = GpsNav {
+                    lat,
+                    lon,
                     alt: d.alt,
+                    alt_msl: d.alt_msl,
                     sats: d.sats,
+                    sats_gps: d.sats_gps,
+                    sats_glo: d.sats_glo,
+                    sats_gal: d.sats_gal,
+                    sats_bds: d.sats_bds,
                     fix: d.fix,
                     speed_kts: d.speed,
                     course_deg: d.course,
+                    vz_ms: -(d.nav_pvt.vel_ned_mm_s[2] as f32) * 1e-3,
+                    vz_valid: d.pvt_active() && d.nav_pvt.fix_ok && d.nav_pvt.fix_type >= 3,
+                    pdop_ok: d.pdop_ok(),
+                    quality: d.gps_quality(),
+                    hdop: d.hdop,
+                    timestamp_ms: now_ms,
                 };
                 let _ = gps_tx.try_send(data);
+                let _ = gps_tel_tx.try_send(data);
+
+                // Trajectory history (stops once fast_loop froze it at apogee)
+                if d.fix
+                    && !GPS_RING_FROZEN.load(Ordering::Relaxed)
+                    && now_ms.wrapping_sub(last_ring_ms) >= RING_PERIOD_MS
+                {
+                    last_ring_ms = now_ms;
+                    let point = GpsPoint { lat: d.lat, lon: d.lon, alt: d.alt_msl, ts_ms: now_ms };
+                    GPS_RING.lock(|r| r.borrow_mut().push(point));
+                }
             }
             Either::First(Err(_)) | Either::Second(_) => {
                 // UART error or timeout — keep looping
@@ -0,0 +1,80 @@
+use core::sync::atomic::Ordering;
+
+use embassy_executor::task;
+use embassy_stm32::dma::NoDma;
+use embassy_stm32::peripherals::SPI3;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Receiver;
+use heapless::Deque;
+
+use crate::drivers::flash::{
+    FlightLogger, LogRecord, LOG_FLAG_ARMED, LOG_FLAG_LANDED, LOG_FLAG_LAUNCHED,
+};
+use crate::state::{set_health, HEALTH_FLASH};
+use crate::{HEALTH_FLAGS, LOG_ERASE_REQUEST, ROLL_GAINS, ROLL_GAINS_SAVE};
+
+/// Depth of the fast_loop → logger channel. Larger than 1 so that records
+/// survive the ~50 ms stall of a sector erase.
+pub const LOG_CHAN_DEPTH: usize = 8;
+
+/// Armed records kept in RAM before launch and written at the head of the
+/// flight file (1 s at 100 Hz)
+const LOG_PRELAUNCH_LEN: usize = 100;
+
+/// Logger task — writes the 100 Hz LogRecord stream from fast_loop to flash
+/// (every page write is read back and verified). One file per flight: opened
+/// at launch with the last second of armed records, closed on landing or
+/// disarm; an arm without launch writes nothing. Roll gains changed and log
+/// erases requested over USB are carried out between flights.
+#[task]
+pub async fn logger_task(
+    mut logger: FlightLogger<'static, SPI3, NoDma, NoDma>,
+    log_rx: Receiver<'static, CriticalSectionRawMutex, LogRecord, LOG_CHAN_DEPTH>,
+) {
+    let mut prelaunch: Deque<LogRecord, LOG_PRELAUNCH_LEN> = Deque::new();
+    loop {
+        let record = log_rx.receive().await;
+        let armed = record.flags & LOG_FLAG_ARMED != 0;
+        let in_flight = armed
+            && record.flags & LOG_FLAG_LAUNCHED != 0
+            && record.flags & LOG_FLAG_LANDED == 0;
+
+        if in_flight != logger.is_recording() {
+            let res = if in_flight {
+                match logger.start_flight().await {
+                    Ok(()) => logger.record_all(prelaunch.iter().copied()).await,
+                    Err(e) => Err(e),
+                }
+            } else {
+                logger.end_flight().await
+            };
+            prelaunch.clear();
+            set_health(&HEALTH_FLAGS, HEALTH_FLASH, res.is_ok());
+        }
+        if !in_flight {
+            if armed && record.flags & LOG_FLAG_LAUNCHED == 0 {
+                if prelaunch.is_full() {
+                    prelaunch.pop_front();
+                }
+                let _ = prelaunch.push_back(record);
+            } else {
+                prelaunch.clear();
+            }
+            // The sector erase stalls logging: config writes wait for the ground
+            if ROLL_GAINS_SAVE.swap(false, Ordering::Relaxed) {
+                let gains = ROLL_GAINS.lock(|c| *c.borrow());
+                let ok = logger.write_pid_gains(&gains).await.is_ok();
+                set_health(&HEALTH_FLAGS, HEALTH_FLASH, ok);
+            }
+            if !armed && LOG_ERASE_REQUEST.swap(false, Ordering::Relaxed) {
+                let ok = logger.erase_log().await.is_ok();
+                set_health(&HEALTH_FLAGS, HEALTH_FLASH, ok);
+            }
+            continue;
+        }
+
+        // Log full, SPI error or read-back mismatch: the record is dropped, keep draining
+        let ok = logger.record(record).await.is_ok();
+        set_health(&HEALTH_FLAGS, HEALTH_FLASH, ok);
+    }
+}
@@ -1,5 +1,8 @@
+pub mod adc_task;
 pub mod baro_task;
 pub mod crsf_task;
 pub mod fast_loop;
 pub mod gps_task;
+pub mod logger_task;
 pub mod telemetry_task;
+pub mod watchdog_task;
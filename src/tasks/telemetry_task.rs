@@ -5,30 +5,60 @@ use embassy_stm32::peripherals::{DMA1_CH4, UART4};
 use embassy_stm32::usart::UartTx;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::Receiver;
-use embassy_time::{Duration, Ticker};
+use embassy_time::{Duration, Instant, Ticker};
+use micromath::F32Ext;
 
-use crate::state::{AttitudeState, BaroData, GpsData};
-use crate::usb::UsbSerial;
+use crate::drivers::gps::constellation_summary;
+use crate::drivers::kalman::R_BARO_NOMINAL;
+use crate::drivers::spl06::AltitudeStats;
+use crate::state::{
+    BaroData, BatteryState, FlightEvent, FlightPhase, GpsNav, LinkData, SystemHealth,
+};
+use crate::usb::{
+    UsbBinaryFrame, UsbBinaryWrite, UsbSerialTx, USB_FRAME_ATTITUDE, USB_FRAME_BARO,
+    USB_FRAME_BATTERY, USB_FRAME_GPS, USB_LINE_TOO_LONG,
+};
+use crate::{
+    ATTITUDE, FLIGHT_EVENTS, GPS_RING, GPS_RING_FROZEN, HEALTH_FLAGS, MOTOR_RPM, PHASE_DURATIONS,
+    PRESSURE_TREND, USB_BINARY_MODE, WEATHER_WARNING,
+};
+use core::sync::atomic::Ordering;
 
 const USB_DEBUG_ENABLED: bool = true;
 
+/// Landing: |v| and AGL below these for LANDED_TICKS (20 Hz) → landed
+const LANDED_VEL_MS: f32 = 0.5;
+const LANDED_ALT_M: f32 = 5.0;
+const LANDED_TICKS: u32 = 40;
+
+/// Baro noise statistics printed once this many samples are in (~6 s at 16 Hz)
+const BARO_STATS_MIN_N: u32 = 100;
+
 /// Telemetry task — 20 Hz.
-/// Receives attitude from fast_loop and slow sensor data via channels.
+/// Loads attitude from fast_loop (ATTITUDE) and receives slow sensor data via channels.
 /// Sends CRSF telemetry frames and USB debug lines.
 #[task]
 pub async fn telemetry_task(
     mut crsf_tx: UartTx<'static, UART4, DMA1_CH4>,
-    mut usb_serial: UsbSerial<'static>,
-    attitude_rx: Receiver<'static, CriticalSectionRawMutex, AttitudeState, 1>,
-    gps_rx: Receiver<'static, CriticalSectionRawMutex, GpsData, 1>,
+    mut usb_serial: UsbSerialTx<'static>,
+    gps_rx: Receiver<'static, CriticalSectionRawMutex, GpsNav, 1>,
     baro_rx: Receiver<'static, CriticalSectionRawMutex, BaroData, 1>,
+    link_rx: Receiver<'static, CriticalSectionRawMutex, LinkData, 1>,
+    batt_rx: Receiver<'static, CriticalSectionRawMutex, BatteryState, 1>,
+    stats_rx: Receiver<'static, CriticalSectionRawMutex, AltitudeStats, 1>,
 ) {
     let mut tick: u32 = 0;
 
     // Local cached data
-    let mut attitude = AttitudeState::default();
-    let mut gps = GpsData::default();
+    let mut gps = GpsNav::default();
     let mut baro = BaroData::default();
+    let mut link = LinkData::default();
+    let mut batt = BatteryState::default();
+
+    let mut ekf_resets_seen: u16 = 0;
+    let mut landed_ticks: u32 = 0;
+    let mut track_dumped = false;
+    let mut events_dumped = false;
 
     let mut ticker = Ticker::every(Duration::from_hz(20));
 
@@ -36,13 +66,50 @@ pub async fn telemetry_task(
         ticker.next().await;
         tick = tick.wrapping_add(1);
 
+        let attitude = ATTITUDE.load();
+
         // Refresh from channels (non-blocking)
-        if let Ok(a) = attitude_rx.try_receive() { attitude = a; }
         if let Ok(g) = gps_rx.try_receive()      { gps = g; }
         if let Ok(b) = baro_rx.try_receive()      { baro = b; }
+        if let Ok(l) = link_rx.try_receive()      { link = l; }
+        if let Ok(b) = batt_rx.try_receive()      { batt = b; }
+
+        // ── EKF divergence resets (reported as soon as seen) ─────────────────
+        if attitude.ekf_resets != ekf_resets_seen && usb_serial.dtr() {
+            ekf_resets_seen = attitude.ekf_resets;
+            let mut m = heapless::String::<64>::new();
+            let _ = write!(m, "[EKF] diverged -> reset (#{})\r\n", attitude.ekf_resets);
+            let _ = usb_serial.write_packet(m.as_bytes()).await;
+        }
+
+        let usb_binary = USB_BINARY_MODE.load(Ordering::Relaxed);
+
+        // ── USB binary frames (every 2 ticks = 10 Hz) ────────────────────────
+        if USB_DEBUG_ENABLED && usb_binary && usb_serial.dtr() && tick % 2 == 0 {
+            let mut f = UsbBinaryFrame::new(USB_FRAME_ATTITUDE);
+            f.push_f32(attitude.roll_rad).push_f32(attitude.pitch_rad).push_f32(attitude.yaw_rad)
+                .push_f32(attitude.alt_m).push_f32(attitude.vel_ms)
+                .push_u8(attitude.phase as u8);
+            usb_serial.write_binary_frame(&mut f).await;
+
+            let mut f = UsbBinaryFrame::new(USB_FRAME_BARO);
+            f.push_f32(baro.pressure_hpa).push_f32(baro.alt_m).push_f32(baro.temp_c)
+                .push_f32(baro.kf_vel_ms);
+            usb_serial.write_binary_frame(&mut f).await;
+
+            let mut f = UsbBinaryFrame::new(USB_FRAME_GPS);
+            f.push_f32(gps.lat).push_f32(gps.lon).push_f32(gps.alt_msl)
+                .push_u8(gps.sats).push_u8(gps.fix as u8);
+            usb_serial.write_binary_frame(&mut f).await;
+
+            let mut f = UsbBinaryFrame::new(USB_FRAME_BATTERY);
+            f.push_f32(batt.voltage_v).push_f32(batt.current_a)
+                .push_u16(batt.consumed_mah).push_u8(batt.percent);
+            usb_serial.write_binary_frame(&mut f).await;
+        }
 
         // ── USB Debug (every 10 ticks = 0.5s) ────────────────────────────────
-        if USB_DEBUG_ENABLED && usb_serial.dtr() && tick % 10 == 0 {
+        if USB_DEBUG_ENABLED && !usb_binary && usb_serial.dtr() && tick % 10 == 0 {
             let roll_deg  = attitude.roll_rad.to_degrees();
             let pitch_deg = attitude.pitch_rad.to_degrees();
             let yaw_deg   = attitude.yaw_rad.to_degrees();
@@ -56,42 +123,179 @@ pub async fn telemetry_task(
             );
             let _ = usb_serial.write_packet(m.as_bytes()).await;
 
+            let mut m = heapless::String::<64>::new();
+            let _ = write!(m, "[EKF] {} p_tr={:.3}\r\n", attitude.ekf_debug, attitude.ekf_p_trace);
+            let _ = usb_serial.write_packet(m.as_bytes()).await;
+
+            // Block covariance traces every 5 s: all should fall from P0 as the EKF converges
+            if tick % 100 == 0 {
+                let c = attitude.ekf_covar;
+                let mut m = heapless::String::<64>::new();
+                let _ = write!(m, "[EKF] P q={:.2e} gb={:.2e} ab={:.2e}\r\n", c.q, c.gb, c.ab);
+                let _ = usb_serial.write_packet(m.as_bytes()).await;
+            }
+
+            let mut m = heapless::String::<128>::new();
+            let _ = write!(m,
+                "[GPS] fix={} s={} q={} lat={:.6} lon={:.6} alt={:.0}m\r\n",
+                gps.fix as u8, gps.sats, gps.quality as u8, gps.lat, gps.lon, gps.alt
+            );
+            let _ = usb_serial.write_packet(m.as_bytes()).await;
+
+            // 128: worst case is ~104 chars (u8 counts at 255, hdop at f32::MAX, u32 age)
             let mut m = heapless::String::<128>::new();
+            let _ = write!(m, "[GNSS] {} hdop={:.1} age=",
+                constellation_summary(gps.sats_gps, gps.sats_gal, gps.sats_glo, gps.sats_bds),
+                gps.hdop);
+            if gps.fix {
+                let gps_age_ms = (Instant::now().as_millis() as u32).wrapping_sub(gps.timestamp_ms);
+                let _ = write!(m, "{}ms\r\n", gps_age_ms);
+            } else {
+                let _ = write!(m, "-\r\n");
+            }
+            let _ = usb_serial.write_packet(m.as_bytes()).await;
+
+            let mut m = heapless::String::<96>::new();
             let _ = write!(m,
-                "[GPS] fix={} s={} lat={:.6} lon={:.6} alt={:.0}m\r\n",
-                gps.fix as u8, gps.sats, gps.lat, gps.lon, gps.alt
+                "[BARO] {:.1}hPa {:.1}m {:.1}C vz={:.2} az={:.2} rej={} stuck={}\r\n",
+                baro.pressure_hpa, baro.alt_m, baro.temp_c, baro.kf_vel_ms, baro.kf_acc_ms2,
+                attitude.baro_fault_count, attitude.baro_stuck as u8
             );
             let _ = usb_serial.write_packet(m.as_bytes()).await;
 
             let mut m = heapless::String::<64>::new();
             let _ = write!(m,
-                "[BARO] {:.1}hPa {:.1}m {:.1}C\r\n",
-                baro.pressure_hpa, baro.alt_m, baro.temp_c
+                "[LINK] rssi={}dBm lq={} snr={} rf={} CRSF loss={:.1}%\r\n",
+                link.rssi_dbm, link.lq, link.snr, link.rf_mode, link.diag.loss_pct
             );
             let _ = usb_serial.write_packet(m.as_bytes()).await;
         }
 
+        // ── Health summary (every 20 ticks = 1 s) ────────────────────────────
+        if USB_DEBUG_ENABLED && !usb_binary && usb_serial.dtr() && tick % 20 == 10 {
+            let health = SystemHealth::from_flags(HEALTH_FLAGS.load(Ordering::Relaxed));
+            let mut m = heapless::String::<32>::new();
+            let digits = health.digits();
+            let _ = write!(m, "HLT:{}{}\r\n",
+                core::str::from_utf8(&digits).unwrap_or("?"),
+                if health.mag_interference { " MAGI" } else { "" });
+            let _ = usb_serial.write_packet(m.as_bytes()).await;
+        }
+
+        // ── Dropped USB command line (usb_rx_task) ───────────────────────────
+        if !usb_binary && usb_serial.dtr() && USB_LINE_TOO_LONG.swap(false, Ordering::Relaxed) {
+            let _ = usb_serial
+                .write_packet(b"# USB: command line too long, dropped\r\n")
+                .await;
+        }
+
+        // ── Baro noise floor (~1 Hz from baro_task) ──────────────────────────
+        if let Ok(st) = stats_rx.try_receive() {
+            if USB_DEBUG_ENABLED && !usb_binary && usb_serial.dtr() && st.n >= BARO_STATS_MIN_N {
+                // std above √R → the Kalman baro noise R is underestimated
+                let std_m = st.variance.sqrt();
+                let r_std = R_BARO_NOMINAL.sqrt();
+                let mut m = heapless::String::<80>::new();
+                let _ = write!(m,
+                    "[BSTAT] n={} mean={:.2}m std={:.3}m sqrtR={:.2}m{}\r\n",
+                    st.n, st.mean, std_m, r_std, if std_m > r_std { " R LOW" } else { "" }
+                );
+                let _ = usb_serial.write_packet(m.as_bytes()).await;
+            }
+        }
+
+        // ── Weather warning (once a minute while the pressure trend is steep) ─
+        if USB_DEBUG_ENABLED && !usb_binary && usb_serial.dtr() && tick % 1200 == 15
+            && WEATHER_WARNING.load(Ordering::Relaxed)
+        {
+            let trend = f32::from_bits(PRESSURE_TREND.load(Ordering::Relaxed));
+            let mut m = heapless::String::<64>::new();
+            let _ = write!(m, "[BARO] Weather warning: trend={:.1} hPa/hr\r\n", trend);
+            let _ = usb_serial.write_packet(m.as_bytes()).await;
+        }
+
+        // ── GPS trajectory dump after landing ─────────────────────────────────
+        // fast_loop unfreezes the ring when the next flight is armed
+        if !GPS_RING_FROZEN.load(Ordering::Relaxed) {
+            track_dumped = false;
+            landed_ticks = 0;
+        } else if !track_dumped {
+            if attitude.vel_ms.abs() < LANDED_VEL_MS && attitude.alt_m < LANDED_ALT_M {
+                landed_ticks = landed_ticks.saturating_add(1);
+            } else {
+                landed_ticks = 0;
+            }
+            if landed_ticks >= LANDED_TICKS && usb_serial.dtr() {
+                let (n, dist) = GPS_RING.lock(|r| {
+                    let r = r.borrow();
+                    (r.len(), r.distance_traveled_m())
+                });
+                let mut m = heapless::String::<64>::new();
+                let _ = write!(m, "# GPS TRACK n={} dist={:.0}m\r\n", n, dist);
+                let _ = usb_serial.write_packet(m.as_bytes()).await;
+                for i in 0..n {
+                    let Some(p) = GPS_RING.lock(|r| r.borrow().get(i)) else { break };
+                    let mut m = heapless::String::<64>::new();
+                    let _ = write!(m, "{},{:.6},{:.6},{:.1}\r\n", p.ts_ms, p.lat, p.lon, p.alt);
+                    let _ = usb_serial.write_packet(m.as_bytes()).await;
+                }
+                track_dumped = true;
+            }
+        }
+
+        // ── Flight event summary once landed ────────────────────────────────
+        if attitude.phase != FlightPhase::Landed {
+            events_dumped = false;
+        } else if !events_dumped && usb_serial.dtr() {
+            let log = FLIGHT_EVENTS.lock(|l| *l.borrow());
+            let mut events = heapless::Vec::<FlightEvent, { crate::state::FLIGHT_EVENT_LOG_LEN }>::new();
+            log.dump(|e| { let _ = events.push(*e); });
+            let _ = usb_serial.write_packet(b"# FLIGHT EVENTS\r\n").await;
+            let t0 = events.first().map(|e| e.timestamp_ms).unwrap_or(0);
+            for e in events.iter() {
+                let mut m = heapless::String::<48>::new();
+                let _ = write!(m, "{:>8} ms  +{} ms  {}\r\n",
+                    e.timestamp_ms, e.timestamp_ms.wrapping_sub(t0), e.phase.as_str());
+                let _ = usb_serial.write_packet(m.as_bytes()).await;
+            }
+            let d = PHASE_DURATIONS.lock(|d| d.get());
+            let mut m = heapless::String::<64>::new();
+            let _ = write!(m, "BURN={}ms COAST={}ms DESCENT={}ms\r\n",
+                d.boost_ms, d.coast_ms, d.descent_ms);
+            let _ = usb_serial.write_packet(m.as_bytes()).await;
+            events_dumped = true;
+        }
+
         // ── CRSF Telemetry ─────────────────────────────────────────────────
         let mut pkt_buf = [0u8; 64];
         let pkt_len = if tick % 20 == 2 {
-            // Battery placeholder — no ADC here; extend later
+            // Battery ~1 Hz (0.1 V, 0.1 A, mAh, %)
             crate::drivers::crsf::build_telemetry_packet(
                 &mut pkt_buf,
                 crate::drivers::crsf::CRSF_FRAMETYPE_BATTERY_SENSOR,
-                &crate::drivers::crsf::payload_battery(0, 0, 0, 0),
+                &crate::drivers::crsf::payload_battery(
+                    (batt.voltage_v * 10.0) as u16,
+                    (batt.current_a * 10.0) as u16,
+                    batt.consumed_mah as u32,
+                    batt.percent,
+                ),
             )
         } else if tick % 4 == 0 {
-            // GPS ~5 Hz
-            let lat_i = (gps.lat * 10_000_000.0) as i32;
-            let lon_i = (gps.lon * 10_000_000.0) as i32;
-            let spd_u = (gps.speed_kts * 1.852 * 10.0) as u16;
-            let hdg_u = (gps.course_deg * 100.0) as u16;
-            let alt_u = (gps.alt + 1000.0).max(0.0) as u16;
-            crate::drivers::crsf::build_telemetry_packet(
-                &mut pkt_buf,
-                crate::drivers::crsf::CRSF_FRAMETYPE_GPS,
-                &crate::drivers::crsf::payload_gps(lat_i, lon_i, spd_u, hdg_u, alt_u, gps.sats),
-            )
+            // GPS ~5 Hz — only good-geometry fixes, a bad one corrupts the ground-station map
+            if gps.pdop_ok {
+                let lat_i = (gps.lat * 10_000_000.0) as i32;
+                let lon_i = (gps.lon * 10_000_000.0) as i32;
+                let spd_u = (gps.speed_kts * 1.852 * 10.0) as u16;
+                let hdg_u = (gps.course_deg * 100.0) as u16;
+                let alt_u = (gps.alt_msl + 1000.0).max(0.0) as u16;
+                crate::drivers::crsf::build_telemetry_packet(
+                    &mut pkt_buf,
+                    crate::drivers::crsf::CRSF_FRAMETYPE_GPS,
+                    &crate::drivers::crsf::payload_gps(lat_i, lon_i, spd_u, hdg_u, alt_u, gps.sats),
+                )
+            } else {
+                0
+            }
         } else if tick % 4 == 1 {
             // Attitude ~5 Hz
             crate::drivers::crsf::build_telemetry_packet(
@@ -124,12 +328,30 @@ pub async fn telemetry_task(
             )
         } else if tick % 20 == 6 {
             // Flight mode ~1 Hz
-            let mode_str = if attitude.is_high_g { "BOOST" } else { "COAST" };
+            let mode_str = attitude.phase.as_str();
             crate::drivers::crsf::build_telemetry_packet(
                 &mut pkt_buf,
                 crate::drivers::crsf::CRSF_FRAMETYPE_FLIGHT_MODE,
                 &crate::drivers::crsf::payload_flight_mode(mode_str),
             )
+        } else if cfg!(feature = "rpm_telemetry") && (tick % 20 == 3 || tick % 20 == 11) {
+            // Motor RPM 2 Hz, only while something spins
+            let mut rpm = [0u16; 4];
+            for (r, shared) in rpm.iter_mut().zip(MOTOR_RPM.iter()) {
+                *r = shared.load(Ordering::Relaxed);
+            }
+            if rpm.iter().any(|&r| r != 0) {
+                crate::drivers::crsf::build_telemetry_packet(
+                    &mut pkt_buf,
+                    crate::drivers::crsf::CRSF_FRAMETYPE_RPM,
+                    &crate::drivers::crsf::payload_rpm(rpm),
+                )
+            } else {
+                0
+            }
+        } else if tick % 20 == 19 {
+            // Heartbeat 1 Hz — some ELRS RX stop forwarding telemetry without it
+            crate::drivers::crsf::build_heartbeat_packet(&mut pkt_buf)
         } else {
             0
         };
@@ -0,0 +1,37 @@
+use core::sync::atomic::Ordering;
+
+use embassy_executor::task;
+use embassy_stm32::peripherals::IWDG;
+use embassy_stm32::wdg::IndependentWatchdog;
+use embassy_time::{Duration, Ticker};
+
+use crate::TASK_ALIVE;
+
+/// Alive bits in `crate::TASK_ALIVE`, set by each critical task every iteration
+pub const ALIVE_FAST_LOOP: u8 = 1 << 0;
+pub const ALIVE_GPS: u8 = 1 << 1;
+pub const ALIVE_BARO: u8 = 1 << 2;
+const ALIVE_ALL: u8 = ALIVE_FAST_LOOP | ALIVE_GPS | ALIVE_BARO;
+
+/// IWDG timeout (µs)
+const WDG_TIMEOUT_US: u32 = 2_000_000;
+/// Pet period — 4 chances per timeout window
+const WDG_PET_MS: u64 = 500;
+
+/// Watchdog task — pets the IWDG every 500 ms, but only when every critical
+/// task has reported an iteration since the last pet. A stalled task lets the
+/// 2 s timeout expire and resets the MCU.
+#[task]
+pub async fn watchdog_task(iwdg: IWDG) {
+    let mut wdg = IndependentWatchdog::new(iwdg, WDG_TIMEOUT_US);
+    wdg.unleash();
+
+    let mut ticker = Ticker::every(Duration::from_millis(WDG_PET_MS));
+    loop {
+        ticker.next().await;
+        if TASK_ALIVE.load(Ordering::Relaxed) & ALIVE_ALL == ALIVE_ALL {
+            TASK_ALIVE.store(0, Ordering::Relaxed);
+            wdg.pet();
+        }
+    }
+}
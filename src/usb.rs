@@ -1,9 +1,13 @@
 use embassy_stm32::usb_otg::{Driver, self};
 use embassy_usb::UsbDevice;
 use embassy_stm32::{bind_interrupts, peripherals};
-use embassy_usb::class::cdc_acm::{CdcAcmClass, State};
+use embassy_usb::class::cdc_acm::{self, CdcAcmClass, State};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Sender;
 use embassy_usb::{Builder, Config};
 use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::drivers::roll::PidGainsUpdate;
 
 bind_interrupts!(pub struct Irqs {
     OTG_FS => usb_otg::InterruptHandler<peripherals::USB_OTG_FS>;
@@ -12,6 +16,9 @@ bind_interrupts!(pub struct Irqs {
 // Type definitions for easier usage
 pub type UsbDriver = Driver<'static, peripherals::USB_OTG_FS>;
 pub type UsbSerial<'a> = CdcAcmClass<'a, UsbDriver>;
+/// Halves of `UsbSerial` after `split()`: telemetry writes, usb_rx_task reads
+pub type UsbSerialTx<'a> = cdc_acm::Sender<'a, UsbDriver>;
+pub type UsbSerialRx<'a> = cdc_acm::Receiver<'a, UsbDriver>;
 
 // Static buffers to keep then alive during the program execution
 // We use StaticCell to avoid `static mut` and unsafe where possible for the structure,
@@ -91,3 +98,280 @@ pub fn init(
 
     (usb, class)
 }
+
+// ── Binary telemetry framing ──────────────────────────────────────────────────
+//  Wire format (little-endian): A5 5A | type | len | payload[len] | crc8
+//  crc8 = CRSF polynomial 0xD5 over type, len and payload.
+
+pub const USB_FRAME_MAGIC: [u8; 2] = [0xA5, 0x5A];
+pub const USB_FRAME_MAX_PAYLOAD: usize = 60;
+
+pub const USB_FRAME_ATTITUDE: u8 = 0x01;
+pub const USB_FRAME_BARO: u8 = 0x02;
+pub const USB_FRAME_GPS: u8 = 0x03;
+pub const USB_FRAME_BATTERY: u8 = 0x04;
+
+pub struct UsbBinaryFrame {
+    pub magic: [u8; 2],
+    pub type_id: u8,
+    pub len: u8,
+    pub payload: [u8; USB_FRAME_MAX_PAYLOAD],
+    pub crc: u8,
+}
+
+impl UsbBinaryFrame {
+    pub fn new(type_id: u8) -> Self {
+        Self {
+            magic: USB_FRAME_MAGIC,
+            type_id,
+            len: 0,
+            payload: [0; USB_FRAME_MAX_PAYLOAD],
+            crc: 0,
+        }
+    }
+
+    /// Append raw bytes; silently truncated at USB_FRAME_MAX_PAYLOAD.
+    pub fn push(&mut self, bytes: &[u8]) -> &mut Self {
+        let start = self.len as usize;
+        let n = bytes.len().min(USB_FRAME_MAX_PAYLOAD - start);
+        self.payload[start..start + n].copy_from_slice(&bytes[..n]);
+        self.len += n as u8;
+        self
+    }
+
+    pub fn push_f32(&mut self, v: f32) -> &mut Self {
+        self.push(&v.to_le_bytes())
+    }
+
+    pub fn push_u16(&mut self, v: u16) -> &mut Self {
+        self.push(&v.to_le_bytes())
+    }
+
+    pub fn push_u8(&mut self, v: u8) -> &mut Self {
+        self.push(&[v])
+    }
+
+    /// Serialize into `buf`, computing the CRC. Returns the number of bytes.
+    pub fn serialize(&mut self, buf: &mut [u8; USB_FRAME_MAX_PAYLOAD + 5]) -> usize {
+        let n = self.len as usize;
+        buf[0..2].copy_from_slice(&self.magic);
+        buf[2] = self.type_id;
+        buf[3] = self.len;
+        buf[4..4 + n].copy_from_slice(&self.payload[..n]);
+        self.crc = crate::drivers::crsf::calc_crc8(&buf[2..4 + n]);
+        buf[4 + n] = self.crc;
+        n + 5
+    }
+}
+
+/// Binary frame output on the CDC-ACM class.
+#[allow(async_fn_in_trait)]
+pub trait UsbBinaryWrite {
+    async fn write_binary_frame(&mut self, frame: &mut UsbBinaryFrame);
+}
+
+impl UsbBinaryWrite for UsbSerialTx<'static> {
+    /// Serialize and send in ≤64-byte USB packets.
+    async fn write_binary_frame(&mut self, frame: &mut UsbBinaryFrame) {
+        let mut buf = [0u8; USB_FRAME_MAX_PAYLOAD + 5];
+        let n = frame.serialize(&mut buf);
+        for chunk in buf[..n].chunks(64) {
+            let _ = self.write_packet(chunk).await;
+        }
+    }
+}
+
+// ── USB commands ──────────────────────────────────────────────────────────────
+//  ASCII lines terminated by CR and/or LF, e.g. "KP=4.2\r\n".
+
+/// Depth of the usb_rx_task → main command channel
+pub const USB_CMD_CHAN_DEPTH: usize = 4;
+
+#[derive(Clone, Copy)]
+pub enum Command {
+    Qnh(f32),    // QNH=<Pa>
+    Gains(PidGainsUpdate), // KP= KI= KD= KFF= ILIM= OLIM=, any subset on one line
+    Armed(bool), // ARMED=0/1 — 0 inhibits arming, 1 releases it
+    DumpLog,     // DUMP_LOG
+    EraseLog,    // ERASE_LOG — erase every flight file
+    ResetCalib,  // RESET_CALIB
+    Dfu,         // DFU — reboot into the ROM bootloader
+    SetTime(u32), // SETTIME=<unix seconds, UTC>
+    BinaryMode(bool), // BINARY=0/1 — ASCII debug lines / binary frames
+}
+
+/// Parse one command line (surrounding whitespace ignored).
+pub fn parse_command(line: &str) -> Option<Command> {
+    let line = line.trim();
+    match line {
+        "DUMP_LOG" => return Some(Command::DumpLog),
+        "ERASE_LOG" => return Some(Command::EraseLog),
+        "RESET_CALIB" => return Some(Command::ResetCalib),
+        "DFU" => return Some(Command::Dfu),
+        _ => {}
+    }
+    if let Some(update) = parse_gains(line) {
+        return Some(Command::Gains(update));
+    }
+    let (key, value) = line.split_once('=')?;
+    let value = value.trim();
+    match key.trim() {
+        "QNH" => value.parse().ok().map(Command::Qnh),
+        "SETTIME" => value.parse().ok().map(Command::SetTime),
+        "ARMED" => match value {
+            "0" => Some(Command::Armed(false)),
+            "1" => Some(Command::Armed(true)),
+            _ => None,
+        },
+        "BINARY" => match value {
+            "0" => Some(Command::BinaryMode(false)),
+            "1" => Some(Command::BinaryMode(true)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Accepted ranges: gains (KP KI KD KFF) 0…GAIN_MAX, ILIM (rad·s) 0…I_LIMIT_MAX,
+/// OLIM above 0 up to the unit controller output
+const GAIN_MAX: f32 = 100.0;
+const I_LIMIT_MAX: f32 = 10.0;
+const OUT_LIMIT_MAX: f32 = 1.0;
+
+/// "KP=4.2 KI=0.9 ..." — every whitespace-separated token must be a gain
+/// assignment in range, otherwise the line is not a gain command. A blank
+/// line is not one either (it would trigger a flash save).
+fn parse_gains(line: &str) -> Option<PidGainsUpdate> {
+    let mut update = PidGainsUpdate::default();
+    let mut any = false;
+    for token in line.split_whitespace() {
+        let (key, value) = token.split_once('=')?;
+        let v: f32 = value.parse().ok()?;
+        let max = match key {
+            "ILIM" => I_LIMIT_MAX,
+            "OLIM" => OUT_LIMIT_MAX,
+            _ => GAIN_MAX,
+        };
+        // `contains` is false for NaN; ±inf falls outside
+        if !(0.0..=max).contains(&v) || (key == "OLIM" && v == 0.0) {
+            return None;
+        }
+        let v = Some(v);
+        match key {
+            "KP" => update.kp = v,
+            "KI" => update.ki = v,
+            "KD" => update.kd = v,
+            "KFF" => update.kff = v,
+            "ILIM" => update.i_limit = v,
+            "OLIM" => update.out_limit = v,
+            _ => return None,
+        }
+        any = true;
+    }
+    any.then_some(update)
+}
+
+/// Longest accepted command line, line ending excluded
+pub const USB_LINE_MAX: usize = 64;
+/// A command line overflowed LineBuffer and was dropped (telemetry_task reports it).
+/// Kept here rather than in main.rs: bin/calibrate includes this module too.
+pub static USB_LINE_TOO_LONG: AtomicBool = AtomicBool::new(false);
+
+/// Line assembly state kept across `read_command` calls: the bytes that
+/// follow a line ending in the same packet belong to the next command.
+pub struct LineBuffer {
+    line: [u8; USB_LINE_MAX],
+    len: usize,
+    /// The current line outgrew `line`: discarded up to its line ending
+    overflow: bool,
+    pkt: [u8; 64],
+    pkt_pos: usize,
+    pkt_len: usize,
+}
+
+impl LineBuffer {
+    pub const fn new() -> Self {
+        Self { line: [0; USB_LINE_MAX], len: 0, overflow: false, pkt: [0; 64], pkt_pos: 0, pkt_len: 0 }
+    }
+
+    /// Drop the partial line and any unread packet bytes (USB disconnect)
+    fn clear(&mut self) {
+        self.len = 0;
+        self.overflow = false;
+        self.pkt_pos = 0;
+        self.pkt_len = 0;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommandError {
+    /// USB cable or host port closed
+    Disconnected,
+    /// Line longer than USB_LINE_MAX: dropped whole, never parsed truncated
+    TooLong,
+    /// Unknown command, bad value or invalid UTF-8
+    Invalid,
+}
+
+/// Command input on the CDC-ACM RX endpoint.
+#[allow(async_fn_in_trait)]
+pub trait UsbCommandRead {
+    async fn read_command(&mut self, buf: &mut LineBuffer) -> Result<Command, CommandError>;
+}
+
+impl UsbCommandRead for UsbSerialRx<'static> {
+    /// Consume buffered bytes, then packets, until a line ends and parse it.
+    async fn read_command(&mut self, buf: &mut LineBuffer) -> Result<Command, CommandError> {
+        loop {
+            while buf.pkt_pos < buf.pkt_len {
+                let b = buf.pkt[buf.pkt_pos];
+                buf.pkt_pos += 1;
+                if b == b'\r' || b == b'\n' {
+                    let len = core::mem::take(&mut buf.len);
+                    if core::mem::take(&mut buf.overflow) {
+                        return Err(CommandError::TooLong);
+                    }
+                    if len > 0 {
+                        let line = core::str::from_utf8(&buf.line[..len])
+                            .map_err(|_| CommandError::Invalid)?;
+                        return parse_command(line).ok_or(CommandError::Invalid);
+                    }
+                } else if buf.len < buf.line.len() {
+                    buf.line[buf.len] = b;
+                    buf.len += 1;
+                } else {
+                    buf.overflow = true;
+                }
+            }
+            match self.read_packet(&mut buf.pkt).await {
+                Ok(n) => {
+                    buf.pkt_pos = 0;
+                    buf.pkt_len = n;
+                }
+                Err(_) => {
+                    buf.clear();
+                    return Err(CommandError::Disconnected);
+                }
+            }
+        }
+    }
+}
+
+/// USB RX task — parses command lines and forwards them to main.
+#[embassy_executor::task]
+pub async fn usb_rx_task(
+    mut rx: UsbSerialRx<'static>,
+    cmd_tx: Sender<'static, CriticalSectionRawMutex, Command, USB_CMD_CHAN_DEPTH>,
+) {
+    let mut buf = LineBuffer::new();
+    loop {
+        rx.wait_connection().await;
+        match rx.read_command(&mut buf).await {
+            Ok(cmd) => {
+                let _ = cmd_tx.try_send(cmd);
+            }
+            Err(CommandError::TooLong) => USB_LINE_TOO_LONG.store(true, Ordering::Relaxed),
+            Err(_) => {}
+        }
+    }
+}
@@ -0,0 +1,82 @@
+#!/usr/bin/env python3
+"""Example consumer for the Goldhorn_Air binary USB telemetry.
+
+Frame (little-endian): A5 5A | type | len | payload[len] | crc8
+crc8 = polynomial 0xD5 (same as CRSF) over type, len and payload.
+
+Switches the firmware to binary mode (BINARY=1) on start and back to ASCII
+(BINARY=0) on exit:
+    pip install pyserial
+    python3 tools/usb_binary_reader.py /dev/ttyACM0
+"""
+import struct
+import sys
+
+import serial
+
+MAGIC = b"\xA5\x5A"
+
+DECODERS = {
+    0x01: ("ATT", "<fffffB", ("roll_rad", "pitch_rad", "yaw_rad", "alt_m", "vel_ms", "phase")),
+    0x02: ("BARO", "<ffff", ("pressure_hpa", "alt_m", "temp_c", "kf_vel_ms")),
+    0x03: ("GPS", "<fffBB", ("lat", "lon", "alt_msl", "sats", "fix")),
+    0x04: ("BATT", "<ffHB", ("voltage_v", "current_a", "consumed_mah", "percent")),
+}
+
+PHASES = ["IDLE", "ARMED", "BOOST", "COAST", "APOGEE", "DESCENT", "LANDED"]
+
+
+def crc8(data: bytes) -> int:
+    crc = 0
+    for b in data:
+        crc ^= b
+        for _ in range(8):
+            crc = ((crc << 1) ^ 0xD5) & 0xFF if crc & 0x80 else (crc << 1) & 0xFF
+    return crc
+
+
+def frames(port):
+    buf = bytearray()
+    while True:
+        buf += port.read(port.in_waiting or 1)
+        while True:
+            start = buf.find(MAGIC)
+            if start < 0:
+                del buf[:-1]
+                break
+            del buf[:start]
+            if len(buf) < 4:
+                break
+            n = buf[3]
+            if len(buf) < 5 + n:
+                break
+            body, crc = bytes(buf[2:4 + n]), buf[4 + n]
+            del buf[:5 + n]
+            if crc8(body) == crc:
+                yield body[0], body[2:]
+
+
+def main():
+    dev = sys.argv[1] if len(sys.argv) > 1 else "/dev/ttyACM0"
+    with serial.Serial(dev, 115200, timeout=0.1) as port:
+        port.write(b"BINARY=1\r\n")
+        try:
+            for type_id, payload in frames(port):
+                if type_id not in DECODERS:
+                    continue
+                name, fmt, fields = DECODERS[type_id]
+                if len(payload) != struct.calcsize(fmt):
+                    continue
+                values = dict(zip(fields, struct.unpack(fmt, payload)))
+                if "phase" in values and values["phase"] < len(PHASES):
+                    values["phase"] = PHASES[values["phase"]]
+                print(name, " ".join(f"{k}={v:.4g}" if isinstance(v, float) else f"{k}={v}"
+                                     for k, v in values.items()))
+        except KeyboardInterrupt:
+            pass
+        finally:
+            port.write(b"BINARY=0\r\n")
+
+
+if __name__ == "__main__":
+    main()